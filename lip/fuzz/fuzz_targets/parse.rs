@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lip::{parser, tokenizer};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(tokens) = tokenizer::tokenize(source) {
+        let _ = parser::parse(&tokens);
+        let _ = parser::parse_program(&tokens);
+        let _ = parser::parse_program_recovering(&tokens);
+    }
+});
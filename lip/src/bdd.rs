@@ -0,0 +1,260 @@
+//! Reduced ordered binary decision diagrams, a canonical form for boolean functions: two
+//! functions over the same variables are equal exactly when their diagrams are the same node.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::symbolic::Logic;
+
+/// A node of a [`Bdd`], standing for the boolean function it is the root of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Node(u32);
+
+impl Node {
+    pub const FALSE: Node = Node(0);
+    pub const TRUE: Node = Node(1);
+}
+
+/// A decision on variable `var`: `low` is the function when it is false, `high` when it is
+/// true. Variables nearer the root have lower numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Decision {
+    var: usize,
+    low: Node,
+    high: Node,
+}
+
+/// The nodes of any number of diagrams over variables numbered from 0, which share their
+/// common parts.
+#[derive(Debug)]
+pub struct Bdd {
+    /// The decision of every node, indexed by node. The two terminals have none.
+    decisions: Vec<Decision>,
+    unique: HashMap<Decision, Node>,
+    ite_cache: HashMap<(Node, Node, Node), Node>,
+}
+
+impl Default for Bdd {
+    fn default() -> Self {
+        let terminal = Decision {
+            var: usize::MAX,
+            low: Node::FALSE,
+            high: Node::FALSE,
+        };
+        Self {
+            decisions: vec![terminal, terminal],
+            unique: HashMap::new(),
+            ite_cache: HashMap::new(),
+        }
+    }
+}
+
+impl Bdd {
+    /// The function that is variable `var`.
+    pub fn var(&mut self, var: usize) -> Node {
+        self.decision(var, Node::FALSE, Node::TRUE)
+    }
+
+    /// The number of nodes in the diagram of `node`, including the terminals it reaches.
+    pub fn count(&self, node: Node) -> usize {
+        let mut seen = HashSet::from([node]);
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if node == Node::TRUE || node == Node::FALSE {
+                continue;
+            }
+            let Decision { low, high, .. } = self.decisions[node.0 as usize];
+            for child in [low, high] {
+                if seen.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+        seen.len()
+    }
+
+    /// "If `cond` then `then` else `other`", which every other operation is made of.
+    pub fn ite(&mut self, cond: Node, then: Node, other: Node) -> Node {
+        match (cond, then, other) {
+            (Node::TRUE, _, _) => return then,
+            (Node::FALSE, _, _) => return other,
+            _ if then == other => return then,
+            (_, Node::TRUE, Node::FALSE) => return cond,
+            _ => (),
+        }
+        if let Some(&node) = self.ite_cache.get(&(cond, then, other)) {
+            return node;
+        }
+        let var = [cond, then, other]
+            .iter()
+            .map(|&node| self.decisions[node.0 as usize].var)
+            .min()
+            .expect("three nodes");
+        let (c0, c1) = self.cofactors(cond, var);
+        let (t0, t1) = self.cofactors(then, var);
+        let (o0, o1) = self.cofactors(other, var);
+        let low = self.ite(c0, t0, o0);
+        let high = self.ite(c1, t1, o1);
+        let node = self.decision(var, low, high);
+        self.ite_cache.insert((cond, then, other), node);
+        node
+    }
+
+    /// An assignment of variables `0..vars` that makes `node` true, preferring false for each
+    /// variable, or `None` if there is none.
+    pub fn satisfy(&self, node: Node, vars: usize) -> Option<Vec<bool>> {
+        if node == Node::FALSE {
+            return None;
+        }
+        let mut assignment = vec![false; vars];
+        let mut node = node;
+        while node != Node::TRUE {
+            let Decision { var, low, high } = self.decisions[node.0 as usize];
+            // Every node but FALSE has a path to TRUE.
+            if low == Node::FALSE {
+                assignment[var] = true;
+                node = high;
+            } else {
+                node = low;
+            }
+        }
+        Some(assignment)
+    }
+
+    /// The value of `node` under `assignment`, which gives every variable it depends on.
+    pub fn evaluate(&self, node: Node, assignment: &[bool]) -> bool {
+        let mut node = node;
+        while node != Node::TRUE && node != Node::FALSE {
+            let Decision { var, low, high } = self.decisions[node.0 as usize];
+            node = if assignment[var] { high } else { low };
+        }
+        node == Node::TRUE
+    }
+
+    /// The functions `node` is when `var` is false and when it is true. `var` must not come
+    /// after the variable of `node`.
+    fn cofactors(&self, node: Node, var: usize) -> (Node, Node) {
+        let decision = self.decisions[node.0 as usize];
+        if decision.var == var {
+            (decision.low, decision.high)
+        } else {
+            (node, node)
+        }
+    }
+
+    /// The node deciding on `var`, made only if there is no equal one.
+    fn decision(&mut self, var: usize, low: Node, high: Node) -> Node {
+        if low == high {
+            return low;
+        }
+        let decision = Decision { var, low, high };
+        if let Some(&node) = self.unique.get(&decision) {
+            return node;
+        }
+        let node = Node(self.decisions.len() as u32);
+        self.decisions.push(decision);
+        self.unique.insert(decision, node);
+        node
+    }
+}
+
+impl Logic for Bdd {
+    type Bit = Node;
+
+    fn constant(&mut self, b: bool) -> Node {
+        if b {
+            Node::TRUE
+        } else {
+            Node::FALSE
+        }
+    }
+
+    fn as_constant(&self, bit: Node) -> Option<bool> {
+        match bit {
+            Node::TRUE => Some(true),
+            Node::FALSE => Some(false),
+            _ => None,
+        }
+    }
+
+    fn sample(&self, bit: Node) -> bool {
+        let mut node = bit;
+        while node != Node::TRUE && node != Node::FALSE {
+            node = self.decisions[node.0 as usize].low;
+        }
+        node == Node::TRUE
+    }
+
+    fn not(&mut self, a: Node) -> Node {
+        self.ite(a, Node::FALSE, Node::TRUE)
+    }
+
+    fn and(&mut self, a: Node, b: Node) -> Node {
+        self.ite(a, b, Node::FALSE)
+    }
+
+    fn or(&mut self, a: Node, b: Node) -> Node {
+        self.ite(a, Node::TRUE, b)
+    }
+
+    fn xor(&mut self, a: Node, b: Node) -> Node {
+        let not_b = self.not(b);
+        self.ite(a, not_b, b)
+    }
+
+    fn ite(&mut self, cond: Node, then: Node, other: Node) -> Node {
+        Bdd::ite(self, cond, then, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bdd_is_canonical() {
+        let mut bdd = Bdd::default();
+        let (a, b) = (bdd.var(0), bdd.var(1));
+        // De Morgan: (nand a b) is (| (^ a) (^ b)).
+        let and = bdd.and(a, b);
+        let nand = bdd.not(and);
+        let (not_a, not_b) = (bdd.not(a), bdd.not(b));
+        assert_eq!(nand, bdd.or(not_a, not_b));
+        // a xor a is false, a or not a is true.
+        assert_eq!(Node::FALSE, bdd.xor(a, a));
+        assert_eq!(Node::TRUE, bdd.or(a, not_a));
+        assert_ne!(bdd.and(a, not_b), bdd.and(not_a, b));
+    }
+
+    #[test]
+    fn bdd_satisfy_finds_assignments() {
+        let mut bdd = Bdd::default();
+        let (a, b, c) = (bdd.var(0), bdd.var(1), bdd.var(2));
+        let not_a = bdd.not(a);
+        let f = bdd.and(not_a, b);
+        let f = bdd.and(f, c);
+        assert_eq!(Some(vec![false, true, true]), bdd.satisfy(f, 3));
+        assert!(bdd.evaluate(f, &[false, true, true]));
+        assert!(!bdd.evaluate(f, &[true, true, true]));
+        assert_eq!(Some(vec![false; 3]), bdd.satisfy(Node::TRUE, 3));
+        assert_eq!(None, bdd.satisfy(Node::FALSE, 3));
+        assert!(!bdd.sample(f));
+        assert!(bdd.sample(not_a));
+    }
+
+    #[test]
+    fn bdd_stays_small_for_parity() {
+        let mut bdd = Bdd::default();
+        let mut parity = Node::FALSE;
+        for var in 0..64 {
+            let x = bdd.var(var);
+            parity = bdd.xor(parity, x);
+        }
+        // Two nodes per variable after the first, and the terminals, where a truth table would
+        // have 2^64 rows.
+        assert_eq!(2 * 64 - 1 + 2, bdd.count(parity));
+        assert_eq!(1, bdd.count(Node::TRUE));
+        let mut assignment = vec![false; 64];
+        assignment[10] = true;
+        assert!(bdd.evaluate(parity, &assignment));
+    }
+}
@@ -0,0 +1,137 @@
+/// A fixed-width bit vector, written `#b1011` with the most significant bit first.
+///
+/// Bits are indexed from the least significant one, so bit 0 of `#b1011` is `1`. A bit vector
+/// is never empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bits {
+    /// The bits, least significant first.
+    bits: Vec<bool>,
+}
+
+impl Bits {
+    /// Makes a bit vector from bits listed least significant first, or `None` if there are none.
+    pub fn new(bits: Vec<bool>) -> Option<Self> {
+        (!bits.is_empty()).then_some(Self { bits })
+    }
+
+    /// Parses the digits of a literal, such as the `1011` of `#b1011`.
+    pub fn from_digits(digits: &str) -> Option<Self> {
+        let bits = digits
+            .chars()
+            .rev()
+            .map(|c| match c {
+                '0' => Some(false),
+                '1' => Some(true),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        Self::new(bits)
+    }
+
+    pub fn width(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Returns bit `index`, counting from the least significant bit.
+    pub fn bit(&self, index: usize) -> Option<bool> {
+        self.bits.get(index).copied()
+    }
+
+    /// Iterates over the bits, least significant first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = bool> + '_ {
+        self.bits.iter().copied()
+    }
+
+    /// Makes a bit vector of the same width whose bit `i` is `f(i)`.
+    pub fn try_map<E>(&self, mut f: impl FnMut(usize) -> Result<bool, E>) -> Result<Self, E> {
+        let mut bits = Vec::with_capacity(self.width());
+        for i in 0..self.width() {
+            bits.push(f(i)?);
+        }
+        Ok(Self { bits })
+    }
+
+    /// Concatenates `parts` with the first one in the most significant position.
+    pub fn concat<'a>(parts: impl DoubleEndedIterator<Item = &'a Bits>) -> Option<Self> {
+        Self::new(parts.rev().flat_map(Bits::iter).collect())
+    }
+
+    /// Returns bits `lo` to `hi` inclusive, or `None` if the range is empty or out of bounds.
+    pub fn slice(&self, hi: usize, lo: usize) -> Option<Self> {
+        Self::new(self.bits.get(lo..=hi)?.to_vec())
+    }
+
+    /// Shifts towards the most significant bit by `amount`, filling with zeros.
+    pub fn shl(&self, amount: usize) -> Self {
+        let amount = amount.min(self.width());
+        let mut bits = vec![false; amount];
+        bits.extend_from_slice(&self.bits[..self.width() - amount]);
+        Self { bits }
+    }
+
+    /// Shifts towards the least significant bit by `amount`, filling with zeros.
+    pub fn shr(&self, amount: usize) -> Self {
+        let amount = amount.min(self.width());
+        let mut bits = self.bits[amount..].to_vec();
+        bits.resize(self.width(), false);
+        Self { bits }
+    }
+}
+
+/// A bool is a bit vector of width 1.
+impl From<bool> for Bits {
+    fn from(bit: bool) -> Self {
+        Self { bits: vec![bit] }
+    }
+}
+
+impl std::fmt::Display for Bits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#b")?;
+        for bit in self.iter().rev() {
+            write!(f, "{}", u8::from(bit))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(digits: &str) -> Bits {
+        Bits::from_digits(digits).unwrap()
+    }
+
+    #[test]
+    fn bits_from_digits_round_trip() {
+        let value = bits("1011");
+        assert_eq!(4, value.width());
+        assert_eq!(Some(true), value.bit(0));
+        assert_eq!(Some(false), value.bit(2));
+        assert_eq!(None, value.bit(4));
+        assert_eq!("#b1011", value.to_string());
+        assert_eq!(None, Bits::from_digits(""));
+        assert_eq!(None, Bits::from_digits("102"));
+    }
+
+    #[test]
+    fn bits_concat_and_slice() {
+        let parts = [bits("10"), bits("011")];
+        assert_eq!(Some(bits("10011")), Bits::concat(parts.iter()));
+        let value = bits("110100");
+        assert_eq!(Some(bits("101")), value.slice(4, 2));
+        assert_eq!(Some(bits("0")), value.slice(0, 0));
+        assert_eq!(None, value.slice(6, 2));
+        assert_eq!(None, value.slice(1, 2));
+    }
+
+    #[test]
+    fn bits_shift_fills_with_zeros() {
+        let value = bits("1011");
+        assert_eq!(bits("0110"), value.shl(1));
+        assert_eq!(bits("0010"), value.shr(2));
+        assert_eq!(bits("0000"), value.shl(9));
+        assert_eq!(value, value.shr(0));
+    }
+}
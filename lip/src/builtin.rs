@@ -0,0 +1,402 @@
+use crate::{
+    bits::Bits,
+    cnf::Formula,
+    equiv::{self, Equivalence},
+    evaluator::{self, EvalErr, Value},
+    parser::Operator,
+    sat,
+    span::Span,
+    table::TruthTable,
+};
+
+/// The value of an identifier that is not defined: the builtin or named operator of that name.
+/// Definitions can shadow both.
+pub(crate) fn primitive(name: &str) -> Option<Value> {
+    Builtin::from_name(name)
+        .map(Value::Builtin)
+        .or_else(|| Operator::from_name(name).map(Value::Operator))
+}
+
+/// A function provided by the interpreter.
+///
+/// Builtins are not stored in the [`crate::environment::Environment`]. An identifier that is
+/// not defined evaluates to the builtin of that name, so definitions can shadow builtins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `(list values...)` makes a list of its arguments.
+    List,
+    /// `(apply f args... list)` calls `f` with `args` followed by the elements of `list`.
+    Apply,
+    /// `(partial f args...)` is the lambda `f` with its first parameters bound to `args`, taking
+    /// the remaining ones. It binds required parameters only, not the rest parameter.
+    Partial,
+    /// `(first list)` is the first element of `list`.
+    First,
+    /// `(nth list i)` is element `i` of `list`, counting from 0.
+    Nth,
+    /// `(length list)` is the number of elements in `list`.
+    Length,
+    /// `(concat bits...)` joins bit vectors, the first one ending up most significant. A bool
+    /// counts as a single bit.
+    Concat,
+    /// `(slice bits hi lo)` takes bits `hi` down to `lo` inclusive.
+    Slice,
+    /// `(bit bits i)` is bit `i` as a bool, counting from the least significant bit.
+    Bit,
+    /// `(shl bits n)` shifts towards the most significant bit, filling with zeros.
+    Shl,
+    /// `(shr bits n)` shifts towards the least significant bit, filling with zeros.
+    Shr,
+    /// `(and-reduce bits)` is true when every bit is set.
+    AndReduce,
+    /// `(or-reduce bits)` is true when any bit is set.
+    OrReduce,
+    /// `(xor-reduce bits)` is true when an odd number of bits are set.
+    XorReduce,
+    /// `(width bits)` is the number of bits.
+    Width,
+    /// `(truth-table f)` lists the rows of the truth table of the lambda `f`, each as
+    /// `(list inputs output)` where `inputs` lists the arguments.
+    TruthTable,
+    /// `(equiv? f g)` is true when the lambdas `f` and `g` return the same value for every
+    /// input.
+    Equiv,
+    /// `(counterexample f g)` lists the arguments of an input the lambdas `f` and `g` differ
+    /// on, or is false if there is none, like [`Builtin::Solve`]. [`Builtin::Equiv`] is the bool
+    /// of whether there is none.
+    Counterexample,
+    /// `(sat? f)` is true when some input makes the lambda `f` true. Called directly, `f` may
+    /// instead be an expression whose free variables are the inputs.
+    Sat,
+    /// `(solve f)` lists the arguments of an input that makes `f` true, or is false if there is
+    /// none. [`Builtin::Sat`] is the bool of whether there is one. An expression's free
+    /// variables are listed in the order they are evaluated.
+    Solve,
+    /// `(all-solutions f)` lists the arguments of every input that makes `f` true, in truth
+    /// table order.
+    AllSolutions,
+}
+
+impl Builtin {
+    pub const ALL: &'static [Builtin] = &[
+        Builtin::List,
+        Builtin::Apply,
+        Builtin::Partial,
+        Builtin::First,
+        Builtin::Nth,
+        Builtin::Length,
+        Builtin::Concat,
+        Builtin::Slice,
+        Builtin::Bit,
+        Builtin::Shl,
+        Builtin::Shr,
+        Builtin::AndReduce,
+        Builtin::OrReduce,
+        Builtin::XorReduce,
+        Builtin::Width,
+        Builtin::TruthTable,
+        Builtin::Equiv,
+        Builtin::Counterexample,
+        Builtin::Sat,
+        Builtin::Solve,
+        Builtin::AllSolutions,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::List => "list",
+            Builtin::Apply => "apply",
+            Builtin::Partial => "partial",
+            Builtin::First => "first",
+            Builtin::Nth => "nth",
+            Builtin::Length => "length",
+            Builtin::Concat => "concat",
+            Builtin::Slice => "slice",
+            Builtin::Bit => "bit",
+            Builtin::Shl => "shl",
+            Builtin::Shr => "shr",
+            Builtin::AndReduce => "and-reduce",
+            Builtin::OrReduce => "or-reduce",
+            Builtin::XorReduce => "xor-reduce",
+            Builtin::Width => "width",
+            Builtin::TruthTable => "truth-table",
+            Builtin::Equiv => "equiv?",
+            Builtin::Counterexample => "counterexample",
+            Builtin::Sat => "sat?",
+            Builtin::Solve => "solve",
+            Builtin::AllSolutions => "all-solutions",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    /// Calls a builtin other than [`Builtin::Apply`], whose call the evaluator makes itself so
+    /// that it runs in tail position. Errors point at the whole call at `span`.
+    pub(crate) fn call(self, args: Vec<Value>, span: Span) -> Result<Value, EvalErr> {
+        match self {
+            Builtin::List => Ok(Value::List(args)),
+            Builtin::Apply => unreachable!("`apply` is called by the evaluator"),
+            Builtin::Partial => {
+                let mut args = args.into_iter();
+                match args.next() {
+                    Some(Value::Lambda(lambda)) => {
+                        evaluator::partial(&lambda, args.collect(), span)
+                    }
+                    Some(value) => Err(EvalErr::TypeMismatch("lambda", Box::new(value), span)),
+                    None => Err(EvalErr::ArityMismatch(1, 0, span)),
+                }
+            }
+            Builtin::First | Builtin::Nth => {
+                let (list, index) = if self == Builtin::First {
+                    let [list] = arity(args, span)?;
+                    (list, 0)
+                } else {
+                    let [list, index] = arity(args, span)?;
+                    (list, int(index, span)?)
+                };
+                let list = list_of(list, span)?;
+                let len = list.len();
+                list.into_iter()
+                    .nth(index)
+                    .ok_or(EvalErr::IndexOutOfRange(index, len, span))
+            }
+            Builtin::Length => {
+                let [list] = arity(args, span)?;
+                Ok(Value::Int(list_of(list, span)?.len()))
+            }
+            Builtin::Concat => {
+                let mut parts = Vec::with_capacity(args.len());
+                for arg in args {
+                    parts.push(match arg {
+                        Value::Bool(b) => Bits::from(b),
+                        value => bits(value, span)?,
+                    });
+                }
+                Bits::concat(parts.iter())
+                    .map(Value::Bits)
+                    .ok_or(EvalErr::ArityMismatch(1, 0, span))
+            }
+            Builtin::Slice => {
+                let [value, hi, lo] = arity(args, span)?;
+                let (value, hi, lo) = (bits(value, span)?, int(hi, span)?, int(lo, span)?);
+                if hi >= value.width() {
+                    return Err(EvalErr::IndexOutOfRange(hi, value.width(), span));
+                }
+                value
+                    .slice(hi, lo)
+                    .map(Value::Bits)
+                    .ok_or(EvalErr::EmptySlice(hi, lo, span))
+            }
+            Builtin::Bit => {
+                let [value, index] = arity(args, span)?;
+                let (value, index) = (bits(value, span)?, int(index, span)?);
+                value
+                    .bit(index)
+                    .map(Value::Bool)
+                    .ok_or(EvalErr::IndexOutOfRange(index, value.width(), span))
+            }
+            Builtin::Shl | Builtin::Shr => {
+                let [value, amount] = arity(args, span)?;
+                let (value, amount) = (bits(value, span)?, int(amount, span)?);
+                Ok(Value::Bits(if self == Builtin::Shl {
+                    value.shl(amount)
+                } else {
+                    value.shr(amount)
+                }))
+            }
+            Builtin::AndReduce | Builtin::OrReduce | Builtin::XorReduce | Builtin::Width => {
+                let [value] = arity(args, span)?;
+                let value = bits(value, span)?;
+                let mut bits = value.iter();
+                Ok(match self {
+                    Builtin::AndReduce => Value::Bool(bits.all(|bit| bit)),
+                    Builtin::OrReduce => Value::Bool(bits.any(|bit| bit)),
+                    Builtin::XorReduce => Value::Bool(bits.fold(false, |parity, bit| parity ^ bit)),
+                    _ => Value::Int(bits.count()),
+                })
+            }
+            Builtin::TruthTable => {
+                let [function] = arity(args, span)?;
+                let table = TruthTable::of_value(function, span)?;
+                let rows = table.rows.into_iter().map(|row| {
+                    let inputs = row.inputs.into_iter().map(Value::Bool).collect();
+                    Value::List(vec![Value::List(inputs), row.output])
+                });
+                Ok(Value::List(rows.collect()))
+            }
+            Builtin::Equiv => {
+                let [left, right] = arity(args, span)?;
+                let equivalence = equiv::check(&left, &right, span)?;
+                Ok(Value::Bool(equivalence == Equivalence::Equivalent))
+            }
+            Builtin::Counterexample => {
+                let [left, right] = arity(args, span)?;
+                let assignment = equiv::check(&left, &right, span)?.assignment();
+                Ok(assignment.map_or(Value::Bool(false), |assignment| {
+                    Value::List(assignment.into_iter().map(Value::Bool).collect())
+                }))
+            }
+            Builtin::Sat | Builtin::Solve | Builtin::AllSolutions => {
+                let [function] = arity(args, span)?;
+                self.call_formula(&Formula::of_value(&function, span)?, span)
+            }
+        }
+    }
+
+    /// Whether this is a builtin solving a [`Formula`], whose operand the evaluator turns into
+    /// one itself so that it may have free variables.
+    pub(crate) fn takes_formula(self) -> bool {
+        matches!(self, Builtin::Sat | Builtin::Solve | Builtin::AllSolutions)
+    }
+
+    /// Calls a builtin that [`takes_formula`](Builtin::takes_formula) on `formula`. Errors point
+    /// at `span`.
+    pub(crate) fn call_formula(self, formula: &Formula, span: Span) -> Result<Value, EvalErr> {
+        let tuple =
+            |assignment: Vec<bool>| Value::List(assignment.into_iter().map(Value::Bool).collect());
+        match self {
+            Builtin::Sat => Ok(Value::Bool(sat::solve(formula).is_some())),
+            Builtin::Solve => Ok(sat::solve(formula).map_or(Value::Bool(false), tuple)),
+            Builtin::AllSolutions => {
+                let solutions = sat::all_solutions(formula, span)?;
+                Ok(Value::List(solutions.into_iter().map(tuple).collect()))
+            }
+            _ => unreachable!("`{self}` does not take a formula"),
+        }
+    }
+}
+
+/// Checks that exactly `N` arguments are given.
+pub(crate) fn arity<T, const N: usize>(args: Vec<T>, span: Span) -> Result<[T; N], EvalErr> {
+    let found = args.len();
+    args.try_into()
+        .map_err(|_| EvalErr::ArityMismatch(N, found, span))
+}
+
+fn list_of(value: Value, span: Span) -> Result<Vec<Value>, EvalErr> {
+    match value {
+        Value::List(values) => Ok(values),
+        value => Err(EvalErr::TypeMismatch("list", Box::new(value), span)),
+    }
+}
+
+fn bits(value: Value, span: Span) -> Result<Bits, EvalErr> {
+    match value {
+        Value::Bits(bits) => Ok(bits),
+        value => Err(EvalErr::TypeMismatch("bits", Box::new(value), span)),
+    }
+}
+
+fn int(value: Value, span: Span) -> Result<usize, EvalErr> {
+    match value {
+        Value::Int(int) => Ok(int),
+        value => Err(EvalErr::TypeMismatch("int", Box::new(value), span)),
+    }
+}
+
+impl std::fmt::Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_names_round_trip() {
+        for builtin in Builtin::ALL {
+            assert_eq!(Some(*builtin), Builtin::from_name(builtin.name()));
+        }
+        assert_eq!(None, Builtin::from_name("undefined-thing"));
+    }
+
+    fn call(builtin: Builtin, args: &[&str]) -> Result<Value, EvalErr> {
+        let args = args
+            .iter()
+            .map(|arg| match arg.strip_prefix("#b") {
+                Some(digits) => Value::Bits(Bits::from_digits(digits).unwrap()),
+                None => Value::Int(arg.parse().unwrap()),
+            })
+            .collect();
+        builtin.call(args, Span::default())
+    }
+
+    #[test]
+    fn builtin_list_accessors() {
+        let span = Span::default();
+        let list = || Value::List(vec![Value::Bool(true), Value::Bool(false)]);
+        let call = |builtin: Builtin, args: Vec<Value>| builtin.call(args, span);
+        assert_eq!(Ok(Value::Bool(true)), call(Builtin::First, vec![list()]));
+        assert_eq!(
+            Ok(Value::Bool(false)),
+            call(Builtin::Nth, vec![list(), Value::Int(1)])
+        );
+        assert_eq!(Ok(Value::Int(2)), call(Builtin::Length, vec![list()]));
+        assert_eq!(
+            Err(EvalErr::IndexOutOfRange(2, 2, span)),
+            call(Builtin::Nth, vec![list(), Value::Int(2)])
+        );
+        assert_eq!(
+            Err(EvalErr::IndexOutOfRange(0, 0, span)),
+            call(Builtin::First, vec![Value::List(Vec::new())])
+        );
+        assert_eq!(
+            Err(EvalErr::TypeMismatch("list", Box::new(Value::Int(1)), span)),
+            call(Builtin::Length, vec![Value::Int(1)])
+        );
+    }
+
+    fn bits_value(digits: &str) -> Value {
+        Value::Bits(Bits::from_digits(digits).unwrap())
+    }
+
+    #[test]
+    fn builtin_bit_vector_functions() {
+        let span = Span::default();
+        assert_eq!(
+            Ok(bits_value("10011")),
+            call(Builtin::Concat, &["#b10", "#b011"])
+        );
+        assert_eq!(
+            Ok(bits_value("101")),
+            call(Builtin::Slice, &["#b110100", "4", "2"])
+        );
+        assert_eq!(Ok(Value::Bool(true)), call(Builtin::Bit, &["#b0010", "1"]));
+        assert_eq!(Ok(bits_value("0110")), call(Builtin::Shl, &["#b1011", "1"]));
+        assert_eq!(Ok(bits_value("0101")), call(Builtin::Shr, &["#b1011", "1"]));
+        assert_eq!(
+            Ok(Value::Bool(false)),
+            call(Builtin::AndReduce, &["#b1011"])
+        );
+        assert_eq!(Ok(Value::Bool(true)), call(Builtin::OrReduce, &["#b0010"]));
+        assert_eq!(Ok(Value::Bool(true)), call(Builtin::XorReduce, &["#b1011"]));
+        assert_eq!(Ok(Value::Int(4)), call(Builtin::Width, &["#b1011"]));
+        assert_eq!(
+            Err(EvalErr::IndexOutOfRange(4, 4, span)),
+            call(Builtin::Bit, &["#b1011", "4"])
+        );
+        assert_eq!(
+            Err(EvalErr::IndexOutOfRange(4, 4, span)),
+            call(Builtin::Slice, &["#b1011", "4", "0"])
+        );
+        assert_eq!(
+            Err(EvalErr::EmptySlice(1, 2, span)),
+            call(Builtin::Slice, &["#b1011", "1", "2"])
+        );
+        assert_eq!(
+            Err(EvalErr::ArityMismatch(1, 0, span)),
+            call(Builtin::Concat, &[])
+        );
+        assert_eq!(
+            Err(EvalErr::TypeMismatch("bits", Box::new(Value::Int(1)), span)),
+            call(Builtin::Width, &["1"])
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! Conjunctive normal form, and the Tseitin transform from lip functions to it.
+//!
+//! [`Tseitin`] is a [`Logic`] whose bools are the outputs of gates. Each gate gets a fresh
+//! variable, and clauses stating that the variable equals the gate of its operands, so a
+//! function becomes a [`Cnf`] that grows linearly with the gates evaluated rather than with the
+//! size of its truth table. The result is equisatisfiable with the function, not equivalent:
+//! every assignment of the inputs fixes the gate variables.
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    environment::Environment,
+    evaluator::{self, EvalErr, Lambda, Value},
+    parser::Expr,
+    span::Span,
+    symbolic::{self, Logic, Sym},
+    table,
+};
+
+/// A variable of a [`Cnf`], or its negation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lit(u32);
+
+impl Lit {
+    pub fn new(var: usize, positive: bool) -> Self {
+        Lit((var as u32) << 1 | !positive as u32)
+    }
+
+    /// The variable, counting from 0.
+    pub fn var(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 & 1 == 0
+    }
+
+    /// The literal as the DIMACS format writes it: the variable counting from 1, negated if the
+    /// literal is.
+    pub fn dimacs(self) -> i64 {
+        let var = self.var() as i64 + 1;
+        if self.is_positive() {
+            var
+        } else {
+            -var
+        }
+    }
+
+    /// A dense number for the literal, below twice the number of variables.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl std::ops::Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+/// A formula in conjunctive normal form over variables `0..vars`: every clause must have a true
+/// literal. An empty clause can never be satisfied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cnf {
+    pub vars: usize,
+    pub clauses: Vec<Vec<Lit>>,
+}
+
+/// Writes the problem line and the clauses of the DIMACS format.
+impl std::fmt::Display for Cnf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "p cnf {} {}", self.vars, self.clauses.len())?;
+        for clause in &self.clauses {
+            for lit in clause {
+                write!(f, "{} ", lit.dimacs())?;
+            }
+            writeln!(f, "0")?;
+        }
+        Ok(())
+    }
+}
+
+/// A bool of a [`Tseitin`] circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Wire {
+    Const(bool),
+    Lit(Lit),
+}
+
+/// What drives the variable of a [`Tseitin`] circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Gate {
+    Input,
+    And(Lit, Lit),
+    Xor(Lit, Lit),
+    Ite(Lit, Lit, Lit),
+}
+
+/// A circuit built by the Tseitin transform. Gates are folded when an operand is constant or
+/// the operands are the same or opposite, and equal gates are shared, so that evaluating the
+/// same function twice gives the same wire.
+#[derive(Debug, Default)]
+pub struct Tseitin {
+    cnf: Cnf,
+    /// The gate of every variable, indexed by variable.
+    gates: Vec<Gate>,
+    shared: HashMap<Gate, Lit>,
+}
+
+impl Tseitin {
+    /// A new input, which is a variable constrained by no clause.
+    pub fn input(&mut self) -> Wire {
+        Wire::Lit(self.var(Gate::Input))
+    }
+
+    /// Requires `wire` to be true.
+    pub fn assert(&mut self, wire: Wire) {
+        match wire {
+            Wire::Const(true) => (),
+            Wire::Const(false) => self.cnf.clauses.push(Vec::new()),
+            Wire::Lit(lit) => self.cnf.clauses.push(vec![lit]),
+        }
+    }
+
+    pub fn into_cnf(self) -> Cnf {
+        self.cnf
+    }
+
+    /// The value of `wire` when the inputs, in the order they were made, are `inputs`.
+    pub fn evaluate(&self, wire: Wire, inputs: &[bool]) -> bool {
+        let lit = match wire {
+            Wire::Const(b) => return b,
+            Wire::Lit(lit) => lit,
+        };
+        let mut values = Vec::with_capacity(lit.var() + 1);
+        let mut inputs = inputs.iter();
+        let value = |values: &[bool], lit: Lit| values[lit.var()] == lit.is_positive();
+        for gate in &self.gates[..=lit.var()] {
+            let var = match *gate {
+                Gate::Input => *inputs.next().expect("a value for every input"),
+                Gate::And(a, b) => value(&values, a) && value(&values, b),
+                Gate::Xor(a, b) => value(&values, a) != value(&values, b),
+                Gate::Ite(c, t, e) => value(&values, if value(&values, c) { t } else { e }),
+            };
+            values.push(var);
+        }
+        value(&values, lit)
+    }
+
+    /// The variable of `gate`, made with its clauses unless an equal gate exists.
+    fn gate(&mut self, gate: Gate) -> Lit {
+        if let Some(&lit) = self.shared.get(&gate) {
+            return lit;
+        }
+        let out = self.var(gate);
+        let clauses = match gate {
+            Gate::Input => Vec::new(),
+            Gate::And(a, b) => vec![vec![!out, a], vec![!out, b], vec![out, !a, !b]],
+            Gate::Xor(a, b) => vec![
+                vec![!out, a, b],
+                vec![!out, !a, !b],
+                vec![out, !a, b],
+                vec![out, a, !b],
+            ],
+            Gate::Ite(c, t, e) => vec![
+                vec![!c, !t, out],
+                vec![!c, t, !out],
+                vec![c, !e, out],
+                vec![c, e, !out],
+            ],
+        };
+        self.cnf.clauses.extend(clauses);
+        self.shared.insert(gate, out);
+        out
+    }
+
+    fn var(&mut self, gate: Gate) -> Lit {
+        let lit = Lit::new(self.cnf.vars, true);
+        self.cnf.vars += 1;
+        self.gates.push(gate);
+        lit
+    }
+}
+
+impl Logic for Tseitin {
+    type Bit = Wire;
+
+    fn constant(&mut self, b: bool) -> Wire {
+        Wire::Const(b)
+    }
+
+    fn as_constant(&self, bit: Wire) -> Option<bool> {
+        match bit {
+            Wire::Const(b) => Some(b),
+            Wire::Lit(_) => None,
+        }
+    }
+
+    fn sample(&self, bit: Wire) -> bool {
+        let inputs = self.gates.iter().filter(|gate| **gate == Gate::Input);
+        self.evaluate(bit, &vec![false; inputs.count()])
+    }
+
+    fn not(&mut self, a: Wire) -> Wire {
+        match a {
+            Wire::Const(b) => Wire::Const(!b),
+            Wire::Lit(lit) => Wire::Lit(!lit),
+        }
+    }
+
+    fn and(&mut self, a: Wire, b: Wire) -> Wire {
+        match (a, b) {
+            (Wire::Const(false), _) | (_, Wire::Const(false)) => Wire::Const(false),
+            (Wire::Const(true), other) | (other, Wire::Const(true)) => other,
+            (Wire::Lit(a), Wire::Lit(b)) if a == b => Wire::Lit(a),
+            (Wire::Lit(a), Wire::Lit(b)) if a == !b => Wire::Const(false),
+            (Wire::Lit(a), Wire::Lit(b)) => Wire::Lit(self.gate(Gate::And(a.min(b), a.max(b)))),
+        }
+    }
+
+    fn xor(&mut self, a: Wire, b: Wire) -> Wire {
+        match (a, b) {
+            (Wire::Const(false), other) | (other, Wire::Const(false)) => other,
+            (Wire::Const(true), other) | (other, Wire::Const(true)) => self.not(other),
+            (Wire::Lit(a), Wire::Lit(b)) if a == b => Wire::Const(false),
+            (Wire::Lit(a), Wire::Lit(b)) if a == !b => Wire::Const(true),
+            (Wire::Lit(a), Wire::Lit(b)) => {
+                // Negations move to the output, so that equal functions share a gate.
+                let negated = a.is_positive() != b.is_positive();
+                let (a, b) = (Lit::new(a.var(), true), Lit::new(b.var(), true));
+                let out = self.gate(Gate::Xor(a.min(b), a.max(b)));
+                Wire::Lit(if negated { !out } else { out })
+            }
+        }
+    }
+
+    fn ite(&mut self, cond: Wire, then: Wire, other: Wire) -> Wire {
+        match (cond, then, other) {
+            (Wire::Const(c), _, _) => return if c { then } else { other },
+            _ if then == other => return then,
+            (_, Wire::Const(true), Wire::Const(false)) => return cond,
+            (_, Wire::Const(false), Wire::Const(true)) => return self.not(cond),
+            _ => (),
+        }
+        match (cond, then, other) {
+            (Wire::Lit(c), Wire::Lit(t), Wire::Lit(e)) => Wire::Lit(self.gate(Gate::Ite(c, t, e))),
+            _ => {
+                let not_cond = self.not(cond);
+                let (then, other) = (self.and(cond, then), self.and(not_cond, other));
+                self.or(then, other)
+            }
+        }
+    }
+}
+
+/// A [`Cnf`] that is satisfiable exactly when a predicate is true for some input. Variable `i`
+/// is input `i` for each of the `inputs`, and the other variables belong to gates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formula {
+    pub inputs: Vec<String>,
+    pub cnf: Cnf,
+}
+
+impl Formula {
+    /// The formula of `value`, which must be a lambda returning a bool, or a bool taken as a
+    /// predicate of no inputs. Errors point at `span`.
+    pub fn of_value(value: &Value, span: Span) -> Result<Self, EvalErr> {
+        match value {
+            Value::Lambda(lambda) => Self::of_lambda(lambda, span),
+            Value::Bool(b) => {
+                let mut circuit = Tseitin::default();
+                circuit.assert(Wire::Const(*b));
+                Ok(Self {
+                    inputs: Vec::new(),
+                    cnf: circuit.into_cnf(),
+                })
+            }
+            value => Err(EvalErr::TypeMismatch(
+                "lambda",
+                Box::new(value.clone()),
+                span,
+            )),
+        }
+    }
+
+    /// Evaluates `lambda`, whose parameters must be plain names, on symbolic inputs.
+    pub fn of_lambda(lambda: &Rc<Lambda>, span: Span) -> Result<Self, EvalErr> {
+        let inputs = table::input_names(lambda, span)?;
+        let mut circuit = Tseitin::default();
+        let wires = inputs.iter().map(|_| circuit.input()).collect();
+        let callee = Value::Lambda(lambda.clone());
+        let out = symbolic::apply(&mut circuit, &callee, wires, span)?;
+        Self::new(circuit, inputs, out, span)
+    }
+
+    /// Evaluates `expr` in `env` with its free variables as the inputs. Without free variables,
+    /// an expression evaluating to a lambda is taken as that lambda.
+    pub fn of_expr(expr: &Expr, env: &Environment) -> Result<Self, EvalErr> {
+        let inputs = table::free_variables(expr, env)?;
+        if inputs.is_empty() {
+            return Self::of_value(&evaluator::eval(expr, env)?, expr.span);
+        }
+        let mut circuit = Tseitin::default();
+        let wires = inputs
+            .iter()
+            .map(|name| (name.clone(), circuit.input()))
+            .collect();
+        let out = symbolic::eval(&mut circuit, expr, env, wires)?;
+        Self::new(circuit, inputs, out, expr.span)
+    }
+
+    /// The clauses in the DIMACS format, after a comment line `c <variable> <name>` for each
+    /// input. The other variables belong to gates.
+    pub fn dimacs(&self) -> String {
+        let mut out = String::new();
+        for (var, name) in self.inputs.iter().enumerate() {
+            out.push_str(&format!("c {} {name}\n", var + 1));
+        }
+        out + &self.cnf.to_string()
+    }
+
+    fn new(
+        mut circuit: Tseitin,
+        inputs: Vec<String>,
+        out: Sym<Wire>,
+        span: Span,
+    ) -> Result<Self, EvalErr> {
+        let Sym::Bool(out) = out else {
+            let value = out.sample(&circuit);
+            return Err(EvalErr::TypeMismatch("bool", Box::new(value), span));
+        };
+        circuit.assert(out);
+        Ok(Self {
+            inputs,
+            cnf: circuit.into_cnf(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, prelude, repl::eval_str, test_util::TestResult, tokenizer};
+
+    #[test]
+    fn tseitin_folds_and_shares_gates() {
+        let mut circuit = Tseitin::default();
+        let (a, b) = (circuit.input(), circuit.input());
+        let and = circuit.and(a, b);
+        assert_eq!(and, circuit.and(b, a));
+        let not_a = circuit.not(a);
+        assert_eq!(Wire::Const(false), circuit.and(a, not_a));
+        assert_eq!(Wire::Const(true), circuit.xor(a, not_a));
+        let (xor, not_b) = (circuit.xor(a, b), circuit.not(b));
+        assert_eq!(circuit.not(xor), circuit.xor(a, not_b));
+        assert_eq!(a, circuit.ite(Wire::Const(true), a, b));
+        // Two inputs, an and gate and a xor gate.
+        let cnf = circuit.into_cnf();
+        assert_eq!(4, cnf.vars);
+        assert_eq!(3 + 4, cnf.clauses.len());
+    }
+
+    #[test]
+    fn tseitin_evaluates_gates() {
+        let mut circuit = Tseitin::default();
+        let (a, b, c) = (circuit.input(), circuit.input(), circuit.input());
+        let xor = circuit.xor(a, b);
+        let mux = circuit.ite(c, xor, a);
+        for i in 0..8 {
+            let inputs = [i & 4 != 0, i & 2 != 0, i & 1 != 0];
+            let expected = if inputs[2] {
+                inputs[0] != inputs[1]
+            } else {
+                inputs[0]
+            };
+            assert_eq!(expected, circuit.evaluate(mux, &inputs));
+        }
+        assert!(!circuit.sample(mux));
+    }
+
+    #[test]
+    fn formula_of_functions_and_expressions() -> TestResult {
+        let env = prelude::environment(true);
+        let value = eval_str("(lambda (a b) (xor a b))", &env)?.unwrap();
+        let formula = Formula::of_value(&value, Span::default())?;
+        assert_eq!(vec!["a", "b"], formula.inputs);
+        assert_eq!(3, formula.cnf.vars);
+        assert_eq!(4 + 1, formula.cnf.clauses.len());
+        let expr = parser::parse(&tokenizer::tokenize("(mux2 s x y)")?)?;
+        let formula = Formula::of_expr(&expr, &env)?;
+        assert_eq!(vec!["s", "x", "y"], formula.inputs);
+        let value = eval_str("(lambda (a) (list a))", &env)?.unwrap();
+        let e = Formula::of_value(&value, Span::default()).unwrap_err();
+        assert_eq!("expected bool, found `(list false)`", e.to_string());
+        let formula =
+            Formula::of_expr(&parser::parse(&tokenizer::tokenize("(& a (^ b))")?)?, &env)?;
+        assert_eq!(
+            "c 1 a\nc 2 b\np cnf 3 4\n-3 1 0\n-3 -2 0\n3 -1 2 0\n3 0\n",
+            formula.dimacs()
+        );
+        let e = Formula::of_value(&Value::Int(1), Span::default()).unwrap_err();
+        assert_eq!("expected lambda, found `1`", e.to_string());
+        Ok(())
+    }
+}
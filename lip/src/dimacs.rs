@@ -0,0 +1,462 @@
+//! Reading CNF in the DIMACS format, as written by [`Formula::dimacs`] and used by SAT
+//! benchmarks, into lip expressions.
+//!
+//! A file starts with comment lines beginning with `c` and the problem line
+//! `p cnf <variables> <clauses>`, followed by the clauses: integers for the literals, negative
+//! for negated variables, each clause ended by `0`. A line `%` ends the clauses early, as in
+//! some benchmark collections. Variables are named `x1`, `x2` and so on, unless a comment
+//! `c <variable> <name>`, as [`Formula::dimacs`] writes for inputs, names them.
+
+use std::rc::Rc;
+
+use crate::{
+    cnf::{Cnf, Formula, Lit},
+    environment::Environment,
+    evaluator::{Lambda, Value},
+    parser::{Expr, ExprKind, Operator, Params, Pattern},
+    span::{SourceId, Span},
+    tokenizer::{self, TokenKind},
+};
+
+/// The most variables a problem line may declare.
+pub const MAX_VARIABLES: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DimacsErr {
+    /// No problem line comes before the clauses.
+    MissingHeader(Span),
+    /// A problem line that is not `p cnf <variables> <clauses>`, pointing at the first field
+    /// that is wrong or missing.
+    MalformedHeader(Span),
+    /// A second problem line.
+    DuplicateHeader(Span),
+    /// A problem line declaring more than [`MAX_VARIABLES`] variables, pointing at the number.
+    TooManyVariables(usize, Span),
+    /// A word in a clause that is not an integer.
+    InvalidLiteral(String, Span),
+    /// A literal of a variable above the number declared by the problem line.
+    VariableOutOfRange(usize, usize, Span),
+    /// A different number of clauses than the problem line declares, pointing at its count.
+    ClauseCount(usize, usize, Span),
+    /// Literals after the last `0`.
+    UnterminatedClause(Span),
+}
+
+impl DimacsErr {
+    pub fn span(&self) -> Span {
+        match self {
+            DimacsErr::MissingHeader(span)
+            | DimacsErr::MalformedHeader(span)
+            | DimacsErr::DuplicateHeader(span)
+            | DimacsErr::TooManyVariables(_, span)
+            | DimacsErr::InvalidLiteral(_, span)
+            | DimacsErr::VariableOutOfRange(_, _, span)
+            | DimacsErr::ClauseCount(_, _, span)
+            | DimacsErr::UnterminatedClause(span) => *span,
+        }
+    }
+}
+
+impl std::error::Error for DimacsErr {}
+
+impl std::fmt::Display for DimacsErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DimacsErr::MissingHeader(_) => {
+                write!(f, "expected the problem line `p cnf <variables> <clauses>`")
+            }
+            DimacsErr::MalformedHeader(_) => write!(
+                f,
+                "malformed problem line, expected `p cnf <variables> <clauses>`"
+            ),
+            DimacsErr::DuplicateHeader(_) => write!(f, "there is already a problem line"),
+            DimacsErr::TooManyVariables(vars, _) => write!(
+                f,
+                "the problem line declares {vars} variables, the limit is {MAX_VARIABLES}"
+            ),
+            DimacsErr::InvalidLiteral(word, _) => write!(f, "invalid literal `{word}`"),
+            DimacsErr::VariableOutOfRange(var, vars, _) => {
+                write!(f, "variable {var} is out of range for {vars} variables")
+            }
+            DimacsErr::ClauseCount(declared, found, _) => write!(
+                f,
+                "the problem line declares {declared} clause(s), found {found}"
+            ),
+            DimacsErr::UnterminatedClause(_) => write!(f, "clause is not terminated with `0`"),
+        }
+    }
+}
+
+/// A CNF read from the DIMACS format, remembering where each literal was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dimacs {
+    /// The name of every variable, in order.
+    pub inputs: Vec<String>,
+    clauses: Vec<Clause>,
+    /// The problem line.
+    header: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Clause {
+    lits: Vec<(Lit, Span)>,
+    span: Span,
+}
+
+struct Header {
+    vars: usize,
+    clauses: usize,
+    /// Where the number of clauses is.
+    count: Span,
+    span: Span,
+}
+
+/// Reads the DIMACS `source`.
+pub fn parse(source: &str) -> Result<Dimacs, DimacsErr> {
+    let mut header: Option<Header> = None;
+    let mut names = Vec::new();
+    let mut clauses = Vec::new();
+    let mut lits = Vec::new();
+    let id = SourceId::of(source);
+    let mut offset = 0;
+    for (number, line) in source.split('\n').enumerate() {
+        let words = words(line, offset, number + 1, id);
+        offset += line.len() + 1;
+        let (first, line_span) = match (words.first(), words.last()) {
+            (Some(&(first, start)), Some(&(_, end))) => (first, start.to(end)),
+            _ => continue,
+        };
+        if first.starts_with('c') {
+            if let [_, (var, _), (name, _)] = &words[..] {
+                if let (Ok(var), true) = (var.parse::<usize>(), is_name(name)) {
+                    names.push((var, name.to_string()));
+                }
+            }
+            continue;
+        }
+        if first == "%" {
+            break;
+        }
+        if first == "p" {
+            if header.is_some() {
+                return Err(DimacsErr::DuplicateHeader(line_span));
+            }
+            header = Some(parse_header(&words, line_span)?);
+            continue;
+        }
+        let Some(Header { vars, .. }) = header else {
+            return Err(DimacsErr::MissingHeader(words[0].1));
+        };
+        for &(word, span) in &words {
+            let int: i64 = word
+                .parse()
+                .map_err(|_| DimacsErr::InvalidLiteral(word.to_string(), span))?;
+            let var = int.unsigned_abs() as usize;
+            if int == 0 {
+                let start = lits.first().map_or(span, |&(_, start): &(Lit, Span)| start);
+                clauses.push(Clause {
+                    lits: std::mem::take(&mut lits),
+                    span: start.to(span),
+                });
+            } else if var > vars {
+                return Err(DimacsErr::VariableOutOfRange(var, vars, span));
+            } else {
+                lits.push((Lit::new(var - 1, int > 0), span));
+            }
+        }
+    }
+    if let (Some(&(_, start)), Some(&(_, end))) = (lits.first(), lits.last()) {
+        return Err(DimacsErr::UnterminatedClause(start.to(end)));
+    }
+    let Some(header) = header else {
+        return Err(DimacsErr::MissingHeader(Span::default().in_source(id)));
+    };
+    if clauses.len() != header.clauses {
+        return Err(DimacsErr::ClauseCount(
+            header.clauses,
+            clauses.len(),
+            header.count,
+        ));
+    }
+    Ok(Dimacs {
+        inputs: input_names(header.vars, names),
+        clauses,
+        header: header.span,
+    })
+}
+
+/// The words of `line`, which starts at byte `offset` of line `number` of the source `id`, with
+/// their spans.
+fn words(line: &str, offset: usize, number: usize, id: SourceId) -> Vec<(&str, Span)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(begin), true) => {
+                let col = line[..begin].chars().count() + 1;
+                let span = Span::new(offset + begin, offset + i, number, col).in_source(id);
+                words.push((&line[begin..i], span));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    words
+}
+
+fn parse_header(words: &[(&str, Span)], line: Span) -> Result<Header, DimacsErr> {
+    // A missing field is reported at the end of the line.
+    let end = Span {
+        start: line.end,
+        col: line.col + (line.end - line.start),
+        ..line
+    };
+    let field = |i: usize| words.get(i).copied().unwrap_or(("", end));
+    let number = |i: usize| {
+        let (word, span) = field(i);
+        word.parse::<usize>()
+            .map(|n| (n, span))
+            .map_err(|_| DimacsErr::MalformedHeader(span))
+    };
+    let (format, span) = field(1);
+    if format != "cnf" {
+        return Err(DimacsErr::MalformedHeader(span));
+    }
+    let (vars, span) = number(2)?;
+    if vars > MAX_VARIABLES {
+        return Err(DimacsErr::TooManyVariables(vars, span));
+    }
+    let (clauses, count) = number(3)?;
+    if let Some(&(_, span)) = words.get(4) {
+        return Err(DimacsErr::MalformedHeader(span));
+    }
+    Ok(Header {
+        vars,
+        clauses,
+        count,
+        span: line,
+    })
+}
+
+/// Whether `name` can be the name of a parameter.
+fn is_name(name: &str) -> bool {
+    matches!(
+        tokenizer::tokenize(name).as_deref(),
+        Ok([token]) if matches!(token.kind, TokenKind::Ident(_))
+    )
+}
+
+/// Names variables `1..=vars` after the comments in `names` that name them first and
+/// differently from the others, and the rest `x1`, `x2` and so on, made unique with `_`.
+fn input_names(vars: usize, names: Vec<(usize, String)>) -> Vec<String> {
+    let mut inputs = vec![None; vars];
+    for (var, name) in names {
+        let taken = inputs.iter().flatten().any(|input| *input == name);
+        if let Some(input @ None) = inputs.get_mut(var.wrapping_sub(1)) {
+            if !taken {
+                *input = Some(name);
+            }
+        }
+    }
+    let named: Vec<String> = inputs.iter().flatten().cloned().collect();
+    inputs
+        .into_iter()
+        .enumerate()
+        .map(|(var, name)| {
+            name.unwrap_or_else(|| {
+                let mut name = format!("x{}", var + 1);
+                while named.contains(&name) {
+                    name.push('_');
+                }
+                name
+            })
+        })
+        .collect()
+}
+
+impl Dimacs {
+    /// The conjunction of the clauses, with the variables free. Each literal points at where
+    /// it is in the DIMACS source, and each clause at the whole clause.
+    pub fn expr(&self) -> Expr {
+        let call = |operator, operands: Vec<Expr>, span| {
+            let operator = Expr::new(ExprKind::Operator(operator), span);
+            Expr::new(ExprKind::Call(Box::new(operator), operands), span)
+        };
+        let mut clauses = self.clauses.iter().map(|clause| {
+            let mut lits = clause.lits.iter().map(|&(lit, span)| {
+                let var = Expr::new(ExprKind::Ident(self.inputs[lit.var()].clone()), span);
+                if lit.is_positive() {
+                    var
+                } else {
+                    call(Operator::Not, vec![var], span)
+                }
+            });
+            match &clause.lits[..] {
+                [] => Expr::new(ExprKind::Bool(false), clause.span),
+                [_] => lits.next_back().expect("one literal"),
+                _ => call(Operator::Or, lits.collect(), clause.span),
+            }
+        });
+        match &self.clauses[..] {
+            [] => Expr::new(ExprKind::Bool(true), self.header),
+            [_] => clauses.next_back().expect("one clause"),
+            _ => call(Operator::And, clauses.collect(), self.header),
+        }
+    }
+
+    /// The variables as the parameters of a lambda.
+    pub fn params(&self) -> Params {
+        let required = self.inputs.iter().cloned().map(Pattern::Ident).collect();
+        Params::new(required, None)
+    }
+
+    /// A lambda in `env` taking the variables in order and returning the conjunction of the
+    /// clauses.
+    pub fn lambda(&self, env: &Environment) -> Value {
+        Value::Lambda(Rc::new(Lambda::new(
+            self.params(),
+            self.expr(),
+            env.clone(),
+        )))
+    }
+
+    /// The clauses as a [`Formula`] of the variables, for the SAT solver.
+    pub fn formula(&self) -> Formula {
+        let clauses = self
+            .clauses
+            .iter()
+            .map(|clause| clause.lits.iter().map(|&(lit, _)| lit).collect());
+        Formula {
+            inputs: self.inputs.clone(),
+            cnf: Cnf {
+                vars: self.inputs.len(),
+                clauses: clauses.collect(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{printer, repl::eval_str, sat, test_util::TestResult};
+
+    const EXAMPLE: &str = "c an example\np cnf 3 2\n1 -3 0\n2 3 -1 0\n";
+
+    #[test]
+    fn dimacs_reads_clauses() -> TestResult {
+        let dimacs = parse(EXAMPLE)?;
+        assert_eq!(vec!["x1", "x2", "x3"], dimacs.inputs);
+        assert_eq!(
+            "(& (| x1 (^ x3)) (| x2 x3 (^ x1)))",
+            printer::pretty(&dimacs.expr(), printer::WIDTH)
+        );
+        let formula = dimacs.formula();
+        assert_eq!(2, formula.cnf.clauses.len());
+        assert!(sat::solve(&formula).is_some());
+        // Clauses may span lines, share them, and stop at `%`.
+        let dimacs = parse("p cnf 2 3\n1\n-2 0 2 0\n0\n%\n0\n")?;
+        assert_eq!(
+            "(& (| x1 (^ x2)) x2 F)",
+            printer::pretty(&dimacs.expr(), printer::WIDTH)
+        );
+        assert_eq!("T", printer::pretty(&parse("p cnf 0 0")?.expr(), 80));
+        Ok(())
+    }
+
+    #[test]
+    fn dimacs_round_trips_formulas() -> TestResult {
+        let env = crate::prelude::environment(true);
+        let value = eval_str("(lambda (a b cin) (first (full-adder a b cin)))", &env)?.unwrap();
+        let formula = Formula::of_value(&value, Span::default())?;
+        let dimacs = parse(&formula.dimacs())?;
+        assert_eq!(&formula.inputs[..], &dimacs.inputs[..3]);
+        assert_eq!(formula.cnf, dimacs.formula().cnf);
+        // The gates named after the inputs keep names of their own.
+        let dimacs = parse("c 1 x2\nc 1 a\nc 3 bad name\nc 2 if\np cnf 3 0\n")?;
+        assert_eq!(vec!["x2", "x2_", "x3"], dimacs.inputs);
+        Ok(())
+    }
+
+    #[test]
+    fn dimacs_lambda_is_evaluated() -> TestResult {
+        let env = Environment::default();
+        env.add("f".to_string(), parse(EXAMPLE)?.lambda(&env));
+        assert_eq!(Some(Value::Bool(false)), eval_str("(f F F T)", &env)?);
+        assert_eq!(Some(Value::Bool(true)), eval_str("(f T T F)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn dimacs_reports_errors() {
+        let cases = [
+            (
+                "1 2 0\n",
+                "expected the problem line `p cnf <variables> <clauses>`",
+                (1, 1),
+            ),
+            (
+                "c only comments\n",
+                "expected the problem line `p cnf <variables> <clauses>`",
+                (1, 1),
+            ),
+            (
+                "p dnf 2 1\n",
+                "malformed problem line, expected `p cnf <variables> <clauses>`",
+                (1, 3),
+            ),
+            (
+                "p cnf two 1\n",
+                "malformed problem line, expected `p cnf <variables> <clauses>`",
+                (1, 7),
+            ),
+            (
+                "p cnf 2\n",
+                "malformed problem line, expected `p cnf <variables> <clauses>`",
+                (1, 8),
+            ),
+            (
+                "p cnf 2 1 1\n",
+                "malformed problem line, expected `p cnf <variables> <clauses>`",
+                (1, 11),
+            ),
+            (
+                "p cnf 40000000000 1\n1 0\n",
+                "the problem line declares 40000000000 variables, the limit is 1048576",
+                (1, 7),
+            ),
+            (
+                "p cnf 2 1\np cnf 2 1\n",
+                "there is already a problem line",
+                (2, 1),
+            ),
+            ("p cnf 2 1\n1 a 0\n", "invalid literal `a`", (2, 3)),
+            (
+                "p cnf 2 1\n1 -3 0\n",
+                "variable 3 is out of range for 2 variables",
+                (2, 3),
+            ),
+            (
+                "p cnf 2 3\n1 0\n-2 0\n",
+                "the problem line declares 3 clause(s), found 2",
+                (1, 9),
+            ),
+            (
+                "p cnf 2 1\n1 0\n2 0\n",
+                "the problem line declares 1 clause(s), found 2",
+                (1, 9),
+            ),
+            (
+                "p cnf 2 1\n1 0\n2 -1\n",
+                "clause is not terminated with `0`",
+                (3, 1),
+            ),
+        ];
+        for (source, message, (line, col)) in cases {
+            let e = parse(source).unwrap_err();
+            assert_eq!(message, e.to_string(), "{source}");
+            assert_eq!((line, col), (e.span().line, e.span().col), "{source}");
+        }
+    }
+}
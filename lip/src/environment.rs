@@ -1,26 +1,167 @@
 use crate::evaluator::Value;
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::PathBuf,
+    rc::Rc,
+};
 
-#[derive(Debug, Default, Clone)]
+/// A scope of variables, optionally nested in a parent scope.
+///
+/// `Environment` is a handle: clones share the same scope, so a lambda that captures its
+/// defining environment sees definitions added to it later.
+#[derive(Default, Clone)]
 pub struct Environment {
+    scope: Rc<RefCell<Scope>>,
+}
+
+#[derive(Default)]
+struct Scope {
     data: HashMap<String, Value>,
+    parent: Option<Environment>,
+    /// How many names [`Environment::fresh_name`] has made, counted in the outermost scope.
+    fresh_names: Cell<usize>,
+    /// In the outermost scope of a module, the files being imported, outermost first, ending
+    /// with the file the module was loaded from. Empty in other scopes.
+    imports: Vec<PathBuf>,
+    /// Whether variables added to this scope are bound in the parent instead, as in
+    /// [`Environment::overlay`].
+    overlay: bool,
 }
 
 impl Environment {
     pub fn new(data: HashMap<String, Value>) -> Self {
-        Self { data }
+        Self {
+            scope: Rc::new(RefCell::new(Scope {
+                data,
+                ..Scope::default()
+            })),
+        }
+    }
+
+    /// Creates an empty scope for the module loaded from the last file in `imports`, which the
+    /// files before it are importing. The paths should be canonical.
+    ///
+    /// The module is nested in the outermost scope rather than in this one, so it sees the
+    /// prelude but not the definitions of the program importing it.
+    pub fn module(&self, imports: Vec<PathBuf>) -> Self {
+        Self {
+            scope: Rc::new(RefCell::new(Scope {
+                parent: Some(self.root()),
+                imports,
+                ..Scope::default()
+            })),
+        }
+    }
+
+    /// Returns the outermost scope this one is nested in, or this one if it has no parent.
+    fn root(&self) -> Self {
+        match &self.scope.borrow().parent {
+            Some(parent) => parent.root(),
+            None => self.clone(),
+        }
+    }
+
+    /// Creates an empty scope nested in this one.
+    pub fn child(&self) -> Self {
+        Self {
+            scope: Rc::new(RefCell::new(Scope {
+                parent: Some(self.clone()),
+                ..Scope::default()
+            })),
+        }
+    }
+
+    /// Creates a scope nested in this one that binds `data` but adds any other variable to this
+    /// one. A macro expansion is evaluated in such a scope, so that it sees the values it took
+    /// from the definition of the macro while what it defines is still defined at the call.
+    pub fn overlay(&self, data: HashMap<String, Value>) -> Self {
+        Self {
+            scope: Rc::new(RefCell::new(Scope {
+                data,
+                parent: Some(self.clone()),
+                overlay: true,
+                ..Scope::default()
+            })),
+        }
+    }
+
+    /// The scope that variables added to this one are bound in.
+    fn defining(&self) -> Self {
+        let scope = self.scope.borrow();
+        match &scope.parent {
+            Some(parent) if scope.overlay => parent.defining(),
+            _ => self.clone(),
+        }
+    }
+
+    /// Binds `var` in this scope, shadowing any binding in the parent scopes. An overlay binds it
+    /// in its parent instead.
+    pub fn add(&self, var: String, value: Value) {
+        let scope = self.defining().scope;
+        scope.borrow_mut().data.insert(var, value);
+    }
+
+    /// Looks up `var` in this scope and then in the parent scopes.
+    pub fn get(&self, var: &str) -> Option<Value> {
+        let scope = self.scope.borrow();
+        match scope.data.get(var) {
+            Some(value) => Some(value.clone()),
+            None => scope.parent.as_ref()?.get(var),
+        }
+    }
+
+    /// Returns the variables bound in this scope, leaving out the parent scopes.
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let scope = self.scope.borrow();
+        let data = scope.data.iter();
+        data.map(|(var, value)| (var.clone(), value.clone()))
+            .collect()
+    }
+
+    pub fn extend(&self, other: HashMap<String, Value>) {
+        let scope = self.defining().scope;
+        scope.borrow_mut().data.extend(other);
     }
 
-    pub fn add(&mut self, var: String, value: Value) {
-        self.data.insert(var, value);
+    /// Makes a new name based on `ident` that cannot be written in source code, such as `t%1`.
+    /// Every name made in the same outermost scope is different.
+    pub fn fresh_name(&self, ident: &str) -> String {
+        let scope = self.scope.borrow();
+        match &scope.parent {
+            Some(parent) => parent.fresh_name(ident),
+            None => {
+                let count = scope.fresh_names.get() + 1;
+                scope.fresh_names.set(count);
+                format!("{ident}%{count}")
+            }
+        }
     }
 
-    pub fn get(&self, var: &str) -> Option<&Value> {
-        self.data.get(var)
+    /// The files being imported when the module this scope is in was made, as passed to
+    /// [`Environment::module`]. Empty outside of modules, such as in the REPL.
+    pub fn imports(&self) -> Vec<PathBuf> {
+        let scope = self.scope.borrow();
+        match &scope.parent {
+            _ if !scope.imports.is_empty() => scope.imports.clone(),
+            Some(parent) => parent.imports(),
+            None => Vec::new(),
+        }
     }
 
-    pub fn extend(&mut self, other: HashMap<String, Value>) {
-        self.data.extend(other)
+    /// Whether both handles refer to the same scope.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.scope, &other.scope)
+    }
+}
+
+impl std::fmt::Debug for Environment {
+    /// Prints the variables of this scope only. Parent scopes are omitted, as are the
+    /// environments captured by lambdas, which would otherwise be printed recursively.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Environment")
+            .field("data", &self.scope.borrow().data)
+            .finish()
     }
 }
 
@@ -30,9 +171,67 @@ mod tests {
 
     #[test]
     fn environment_add_and_get_succeed() {
-        let mut env = Environment::default();
+        let env = Environment::default();
         assert!(env.get("myvar").is_none());
         env.add("myvar".to_string(), Value::Bool(false));
-        assert_eq!(Value::Bool(false), *env.get("myvar").unwrap());
+        assert_eq!(Value::Bool(false), env.get("myvar").unwrap());
+    }
+
+    #[test]
+    fn environment_child_shadows_parent() {
+        let env = Environment::default();
+        env.add("a".to_string(), Value::Bool(false));
+        env.add("b".to_string(), Value::Bool(false));
+        let child = env.child();
+        child.add("a".to_string(), Value::Bool(true));
+        assert_eq!(Value::Bool(true), child.get("a").unwrap());
+        assert_eq!(Value::Bool(false), child.get("b").unwrap());
+        assert_eq!(Value::Bool(false), env.get("a").unwrap());
+    }
+
+    #[test]
+    fn environment_overlay_adds_to_parent() {
+        let env = Environment::default();
+        let data = HashMap::from([("a".to_string(), Value::Bool(true))]);
+        let overlay = env.overlay(data).overlay(HashMap::new());
+        overlay.add("b".to_string(), Value::Bool(false));
+        assert_eq!(Value::Bool(true), overlay.get("a").unwrap());
+        assert_eq!(vec![("b".to_string(), Value::Bool(false))], env.bindings());
+        let child = overlay.child();
+        child.add("c".to_string(), Value::Bool(false));
+        assert!(env.get("c").is_none());
+    }
+
+    #[test]
+    fn environment_fresh_names_differ() {
+        let env = Environment::default();
+        let child = env.child();
+        assert_eq!("t%1", env.fresh_name("t"));
+        assert_eq!("t%2", child.fresh_name("t"));
+        assert_eq!("a%3", child.child().fresh_name("a"));
+        assert_eq!("t%1", Environment::default().fresh_name("t"));
+    }
+
+    #[test]
+    fn environment_module_records_imports() {
+        let root = Environment::default();
+        root.add("prelude".to_string(), Value::Bool(true));
+        let program = root.child();
+        program.add("program".to_string(), Value::Bool(true));
+        let imports = vec![PathBuf::from("/a.lip"), PathBuf::from("/b.lip")];
+        let module = program.child().module(imports.clone());
+        assert_eq!(imports, module.child().child().imports());
+        assert!(program.imports().is_empty());
+        assert!(module.get("prelude").is_some());
+        assert!(module.get("program").is_none());
+    }
+
+    #[test]
+    fn environment_bindings_leave_out_parents() {
+        let env = Environment::default();
+        env.add("a".to_string(), Value::Bool(false));
+        let child = env.child();
+        child.add("b".to_string(), Value::Bool(true));
+        assert_eq!(vec![("b".to_string(), Value::Bool(true))], child.bindings());
     }
 }
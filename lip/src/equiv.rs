@@ -0,0 +1,264 @@
+//! Checking whether two lambdas are the same boolean function.
+//!
+//! Lambdas with few inputs are compared on every assignment. Above [`EXHAUSTIVE_INPUTS`]
+//! inputs, both are evaluated [`symbolic`]ally into [`Bdd`]s, which are equal exactly when the
+//! functions are, and a differing assignment is read off the BDD of where they differ.
+
+use std::rc::Rc;
+
+use crate::{
+    bdd::{Bdd, Node},
+    evaluator::{self, EvalErr, Lambda, Value},
+    span::Span,
+    symbolic::{self, Logic, Sym},
+    table,
+};
+
+/// The most inputs for which lambdas are compared by trying every assignment.
+pub const EXHAUSTIVE_INPUTS: usize = 10;
+
+/// The outcome of [`check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Equivalence {
+    Equivalent,
+    /// An assignment the lambdas differ on, as the names of the inputs of the first lambda with
+    /// their values, and what each lambda returns for it.
+    Counterexample {
+        inputs: Vec<(String, bool)>,
+        left: Value,
+        right: Value,
+    },
+}
+
+impl Equivalence {
+    /// The values of the inputs of the counterexample, if any.
+    pub fn assignment(&self) -> Option<Vec<bool>> {
+        match self {
+            Equivalence::Equivalent => None,
+            Equivalence::Counterexample { inputs, .. } => {
+                Some(inputs.iter().map(|(_, value)| *value).collect())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Equivalence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Equivalence::Equivalent => write!(f, "equivalent"),
+            Equivalence::Counterexample {
+                inputs,
+                left,
+                right,
+            } => {
+                write!(f, "not equivalent: with")?;
+                if inputs.is_empty() {
+                    write!(f, " no inputs")?;
+                }
+                for (i, (name, value)) in inputs.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    let value = if *value { "T" } else { "F" };
+                    write!(f, "{separator} {name} = {value}")?;
+                }
+                write!(f, " the first gives `{left}` and the second `{right}`")
+            }
+        }
+    }
+}
+
+/// Checks whether the lambdas `left` and `right`, whose parameters must be plain names, return
+/// the same value for every assignment of bools to their parameters. Errors point at `span`.
+pub fn check(left: &Value, right: &Value, span: Span) -> Result<Equivalence, EvalErr> {
+    let (left_lambda, right_lambda) = (lambda(left, span)?, lambda(right, span)?);
+    let names = table::input_names(left_lambda, span)?;
+    let arity = table::input_names(right_lambda, span)?.len();
+    if names.len() != arity {
+        return Err(EvalErr::DifferentArity(names.len(), arity, span));
+    }
+    let assignment = if names.len() <= EXHAUSTIVE_INPUTS {
+        exhaustive(left, right, names.len(), span)?
+    } else {
+        symbolic(left, right, names.len(), span)?
+    };
+    let Some(assignment) = assignment else {
+        return Ok(Equivalence::Equivalent);
+    };
+    let args = || assignment.iter().copied().map(Value::Bool).collect();
+    Ok(Equivalence::Counterexample {
+        left: evaluator::apply(left.clone(), args(), span)?,
+        right: evaluator::apply(right.clone(), args(), span)?,
+        inputs: names.into_iter().zip(assignment).collect(),
+    })
+}
+
+fn lambda(value: &Value, span: Span) -> Result<&Rc<Lambda>, EvalErr> {
+    match value {
+        Value::Lambda(lambda) => Ok(lambda),
+        value => Err(EvalErr::TypeMismatch(
+            "lambda",
+            Box::new(value.clone()),
+            span,
+        )),
+    }
+}
+
+/// Calls both lambdas with every assignment of `n` inputs in truth table order, and returns the
+/// first one they differ on.
+fn exhaustive(
+    left: &Value,
+    right: &Value,
+    n: usize,
+    span: Span,
+) -> Result<Option<Vec<bool>>, EvalErr> {
+    for i in 0..1usize << n {
+        let assignment: Vec<bool> = (0..n).rev().map(|bit| i >> bit & 1 == 1).collect();
+        let args = || assignment.iter().copied().map(Value::Bool).collect();
+        if evaluator::apply(left.clone(), args(), span)?
+            != evaluator::apply(right.clone(), args(), span)?
+        {
+            return Ok(Some(assignment));
+        }
+    }
+    Ok(None)
+}
+
+/// Evaluates both lambdas symbolically over `n` inputs, and returns an assignment they differ
+/// on.
+fn symbolic(
+    left: &Value,
+    right: &Value,
+    n: usize,
+    span: Span,
+) -> Result<Option<Vec<bool>>, EvalErr> {
+    let mut bdd = Bdd::default();
+    let inputs: Vec<Node> = (0..n).map(|var| bdd.var(var)).collect();
+    let left = symbolic::apply(&mut bdd, left, inputs.clone(), span)?;
+    let right = symbolic::apply(&mut bdd, right, inputs, span)?;
+    let differ = differ(&mut bdd, &left, &right);
+    Ok(bdd.satisfy(differ, n))
+}
+
+/// The function that is true for the inputs where `left` and `right` are different values.
+fn differ(bdd: &mut Bdd, left: &Sym<Node>, right: &Sym<Node>) -> Node {
+    let any = |bdd: &mut Bdd, pairs: Vec<Node>| {
+        pairs
+            .into_iter()
+            .fold(Node::FALSE, |any, differ| bdd.or(any, differ))
+    };
+    match (left, right) {
+        (Sym::Bool(a), Sym::Bool(b)) => bdd.xor(*a, *b),
+        (Sym::Bits(a), Sym::Bits(b)) if a.len() == b.len() => {
+            let pairs = a.iter().zip(b).map(|(a, b)| bdd.xor(*a, *b)).collect();
+            any(bdd, pairs)
+        }
+        (Sym::Int(a), Sym::Int(b)) if a == b => Node::FALSE,
+        (Sym::List(a), Sym::List(b)) if a.len() == b.len() => {
+            let pairs = a.iter().zip(b).map(|(a, b)| differ(bdd, a, b)).collect();
+            any(bdd, pairs)
+        }
+        (Sym::Value(a), Sym::Value(b)) if a == b => Node::FALSE,
+        // Lambdas are compared as in the evaluator, by their code and environment, which
+        // symbolic lambdas made in different calls never share.
+        (Sym::Lambda(a, _), Sym::Lambda(b, _)) if a == b => Node::FALSE,
+        _ => Node::TRUE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{environment::Environment, prelude, repl::eval_str, test_util::TestResult};
+
+    fn value(source: &str, env: &Environment) -> Result<Value, crate::Error> {
+        Ok(eval_str(source, env)?.unwrap())
+    }
+
+    /// Checks both ways, exhaustively and symbolically, that they agree.
+    fn both(left: &str, right: &str, env: &Environment) -> Result<Option<Vec<bool>>, crate::Error> {
+        let (left, right) = (value(left, env)?, value(right, env)?);
+        let Value::Lambda(lambda) = &left else {
+            panic!("not a lambda");
+        };
+        let n = lambda.params.required.len();
+        let span = Span::default();
+        let found = exhaustive(&left, &right, n, span)?;
+        assert_eq!(found.is_some(), symbolic(&left, &right, n, span)?.is_some());
+        Ok(found)
+    }
+
+    #[test]
+    fn equiv_proves_de_morgan() -> TestResult {
+        let env = Environment::default();
+        eval_str("(def nand (lambda (a b) (^ (& a b))))", &env)?;
+        assert_eq!(None, both("nand", "(lambda (a b) (| (^ a) (^ b)))", &env)?);
+        assert_eq!(
+            Some(vec![false, true]),
+            both("nand", "(lambda (a b) (& (^ a) (^ b)))", &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn equiv_compares_prelude_circuits() -> TestResult {
+        let env = prelude::environment(true);
+        let ripple = "(lambda (a b c) (full-adder a b c))";
+        let spec = "(lambda (a b c) (list (xor a b c) (| (& a b) (& c (xor a b)))))";
+        assert_eq!(None, both(ripple, spec, &env)?);
+        let wrong = "(lambda (a b c) (list (xor a b c) (& a b)))";
+        assert_eq!(Some(vec![false, true, true]), both(ripple, wrong, &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn equiv_reports_counterexample() -> TestResult {
+        let env = Environment::default();
+        let (left, right) = (value("(lambda (a b) (| a b))", &env)?, value("xor", &env)?);
+        let e = check(&left, &right, Span::default()).unwrap_err();
+        assert_eq!(
+            "expected lambda, found `primitive operator: xor`",
+            e.to_string()
+        );
+        let right = value("(lambda (x y) (xor x y))", &env)?;
+        let result = check(&left, &right, Span::default())?;
+        assert_eq!(Some(vec![true, true]), result.assignment());
+        assert_eq!(
+            "not equivalent: with a = T, b = T the first gives `true` and the second `false`",
+            result.to_string()
+        );
+        let right = value("(lambda (a) a)", &env)?;
+        let e = check(&left, &right, Span::default()).unwrap_err();
+        assert_eq!("cannot compare functions of 2 and 1 inputs", e.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn equiv_is_symbolic_for_many_inputs() -> TestResult {
+        let env = prelude::environment(true);
+        // Two 16-bit adders, with far too many inputs to try every assignment. The bits of the
+        // operands alternate in the parameters, which keeps the BDDs of sums small.
+        let bits = |prefix: &'static str| (0..16).map(move |i| format!("{prefix}{i}"));
+        let params: Vec<String> = bits("x").zip(bits("y")).flat_map(|(x, y)| [x, y]).collect();
+        let params = params.join(" ");
+        let vector = |prefix| format!("(concat {})", bits(prefix).collect::<Vec<_>>().join(" "));
+        let (x, y) = (vector("x"), vector("y"));
+        let left = value(&format!("(lambda ({params}) (first (add {x} {y})))"), &env)?;
+        let right = value(&format!("(lambda ({params}) (first (add {y} {x})))"), &env)?;
+        assert_eq!(
+            Equivalence::Equivalent,
+            check(&left, &right, Span::default())?
+        );
+        let right = value(&format!("(lambda ({params}) (xor {x} {y}))"), &env)?;
+        let result = check(&left, &right, Span::default())?;
+        let Equivalence::Counterexample {
+            inputs,
+            left,
+            right,
+        } = result
+        else {
+            panic!("expected a counterexample");
+        };
+        assert_eq!(32, inputs.len());
+        assert_ne!(left, right);
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{
+    dimacs::DimacsErr, evaluator::EvalErr, parser::ParserErr, repl::CommandErr, span::Span,
+    tokenizer::TokenizeErr,
+};
+
+/// Any error raised while running lip code, tagged with the stage that failed.
+///
+/// The underlying error is available through [`std::error::Error::source`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Tokenize(TokenizeErr),
+    Parse(ParserErr),
+    Eval(EvalErr),
+    Dimacs(DimacsErr),
+    Command(CommandErr),
+}
+
+impl Error {
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Tokenize(e) => e.span(),
+            Error::Parse(e) => e.span(),
+            Error::Eval(e) => e.span(),
+            Error::Dimacs(e) => e.span(),
+            Error::Command(e) => e.span(),
+        }
+    }
+
+    /// Formats the error and its cause, followed by the offending line of `source` with a caret.
+    /// The line is left out when the error is not in `source`, as when it is raised in a lambda
+    /// defined elsewhere.
+    pub fn report(&self, source: &str) -> String {
+        use std::error::Error as _;
+        let cause = self.source().map(|e| format!(": {e}")).unwrap_or_default();
+        let span = self.span();
+        if !span.is_in(source) {
+            return format!("{self}{cause}");
+        }
+        format!("{self}{cause}\n{}", span.highlight(source))
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Tokenize(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            Error::Eval(e) => Some(e),
+            Error::Dimacs(e) => Some(e),
+            Error::Command(e) => Some(e),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Tokenize(_) => write!(f, "failed to tokenize"),
+            Error::Parse(_) => write!(f, "failed to parse"),
+            Error::Eval(_) => write!(f, "failed to evaluate"),
+            Error::Dimacs(_) => write!(f, "failed to read DIMACS"),
+            Error::Command(_) => write!(f, "failed to run command"),
+        }
+    }
+}
+
+impl From<TokenizeErr> for Error {
+    fn from(e: TokenizeErr) -> Self {
+        Error::Tokenize(e)
+    }
+}
+
+impl From<ParserErr> for Error {
+    fn from(e: ParserErr) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<EvalErr> for Error {
+    fn from(e: EvalErr) -> Self {
+        Error::Eval(e)
+    }
+}
+
+impl From<DimacsErr> for Error {
+    fn from(e: DimacsErr) -> Self {
+        Error::Dimacs(e)
+    }
+}
+
+impl From<CommandErr> for Error {
+    fn from(e: CommandErr) -> Self {
+        Error::Command(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn error_chains_to_stage_error() {
+        let e = Error::from(ParserErr::UnexpectedEnd(Span::default()));
+        assert_eq!("failed to parse", e.to_string());
+        assert_eq!("unexpected end of input", e.source().unwrap().to_string());
+    }
+}
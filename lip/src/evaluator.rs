@@ -1,124 +1,720 @@
-use std::collections::HashMap;
+use std::{cell::Cell, ops::Range, rc::Rc};
 
+use crate::bits::Bits;
+use crate::builtin::{self, Builtin};
+use crate::cnf::Formula;
 use crate::environment::Environment;
-use crate::parser::{self, Expr};
+use crate::macros::Macro;
+use crate::module;
+use crate::parser::{self, Expr, ExprKind, LetKind, Params, Pattern};
+use crate::sat;
+use crate::span::Span;
+use crate::table;
+use crate::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EvalErr {
-    Eval(String),
+    /// A value of the wrong type. The first field names the expected type.
+    TypeMismatch(&'static str, Box<Value>, Span),
+    /// A call with the wrong number of arguments: expected, then found.
+    ArityMismatch(usize, usize, Span),
+    /// A call whose operator is not an operator or a lambda.
+    NotCallable(Box<Value>, Span),
+    /// An identifier that is not defined in the environment.
+    UnboundIdentifier(String, Span),
+    /// An [`ExprKind::Error`] left by the error-recovering parser.
+    MalformedExpression(Span),
+    /// More than [`RECURSION_LIMIT`] nested evaluations, or more than [`STACK_LIMIT`] of stack
+    /// used by them, usually from a recursive call that is not in tail position.
+    RecursionLimit(Span),
+    /// A `cond` without `else` none of whose tests is true.
+    NoMatchingClause(Span),
+    /// Bit vectors of different widths: expected, then found.
+    WidthMismatch(usize, usize, Span),
+    /// An index past the end of a bit vector or a list: the index, then the length.
+    IndexOutOfRange(usize, usize, Span),
+    /// A slice whose high index is below its low one: high, then low.
+    EmptySlice(usize, usize, Span),
+    /// A value that a pattern cannot take apart, such as a list of the wrong length.
+    PatternMismatch(Pattern, Box<Value>, Span),
+    /// A macro operand that a macro parameter pattern cannot take apart.
+    SyntaxMismatch(Pattern, Box<Expr>, Span),
+    /// A macro operand used where the template expects a name.
+    NotAName(Box<Expr>, Span),
+    /// A macro rest parameter used in the template outside of a call or a parameter list.
+    MisplacedSplice(String, Span),
+    /// An imported file that cannot be read: the path as written, then the reason.
+    ImportFailed(String, String, Span),
+    /// Files that import each other, listed from the first one back to itself.
+    ImportCycle(Vec<String>, Span),
+    /// An error in an imported file: the path as written, then the error, whose span points
+    /// into that file.
+    InModule(String, Box<Error>, Span),
+    /// A name in an import list that the module does not define: the path, then the name.
+    NotExported(String, String, Span),
+    /// A truth table over more than [`table::MAX_INPUTS`] inputs: the number of inputs.
+    TooManyInputs(usize, Span),
+    /// A formula with more than [`sat::MAX_SOLUTIONS`] solutions to list.
+    TooManySolutions(Span),
+    /// Functions compared by [`crate::equiv`] whose numbers of inputs differ.
+    DifferentArity(usize, usize, Span),
+    /// A construct that [`crate::symbolic`] evaluation cannot handle, described by the first
+    /// field.
+    NotSymbolic(String, Span),
+}
+
+impl EvalErr {
+    pub fn span(&self) -> Span {
+        match self {
+            EvalErr::TypeMismatch(_, _, span)
+            | EvalErr::ArityMismatch(_, _, span)
+            | EvalErr::NotCallable(_, span)
+            | EvalErr::UnboundIdentifier(_, span)
+            | EvalErr::MalformedExpression(span)
+            | EvalErr::RecursionLimit(span)
+            | EvalErr::NoMatchingClause(span)
+            | EvalErr::WidthMismatch(_, _, span)
+            | EvalErr::IndexOutOfRange(_, _, span)
+            | EvalErr::EmptySlice(_, _, span)
+            | EvalErr::PatternMismatch(_, _, span)
+            | EvalErr::SyntaxMismatch(_, _, span)
+            | EvalErr::NotAName(_, span)
+            | EvalErr::MisplacedSplice(_, span)
+            | EvalErr::ImportFailed(_, _, span)
+            | EvalErr::ImportCycle(_, span)
+            | EvalErr::InModule(_, _, span)
+            | EvalErr::NotExported(_, _, span)
+            | EvalErr::TooManyInputs(_, span)
+            | EvalErr::TooManySolutions(span)
+            | EvalErr::DifferentArity(_, _, span)
+            | EvalErr::NotSymbolic(_, span) => *span,
+        }
+    }
 }
 
 impl std::error::Error for EvalErr {}
 
 impl std::fmt::Display for EvalErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "failed to evaluate expression")
+        match self {
+            EvalErr::TypeMismatch(expected, value, _) => {
+                write!(f, "expected {expected}, found `{value}`")
+            }
+            EvalErr::ArityMismatch(expected, found, _) => {
+                write!(f, "expected {expected} argument(s), found {found}")
+            }
+            EvalErr::NotCallable(value, _) => write!(f, "`{value}` is not callable"),
+            EvalErr::UnboundIdentifier(ident, _) => write!(f, "`{ident}` is not defined"),
+            EvalErr::MalformedExpression(_) => write!(f, "cannot evaluate a malformed expression"),
+            EvalErr::RecursionLimit(_) => {
+                write!(
+                    f,
+                    "recursion limit of {RECURSION_LIMIT} nested calls or {} KiB of stack exceeded",
+                    STACK_LIMIT >> 10
+                )
+            }
+            EvalErr::NoMatchingClause(_) => {
+                write!(f, "no `cond` clause matched and there is no `else`")
+            }
+            EvalErr::WidthMismatch(expected, found, _) => {
+                write!(f, "expected {expected} bit(s), found {found}")
+            }
+            EvalErr::IndexOutOfRange(index, width, _) => {
+                write!(f, "index {index} is out of range for length {width}")
+            }
+            EvalErr::EmptySlice(hi, lo, _) => {
+                write!(f, "cannot slice from bit {hi} down to bit {lo}")
+            }
+            EvalErr::PatternMismatch(pattern, value, _) => {
+                write!(f, "`{value}` does not match the pattern `{pattern}`")
+            }
+            EvalErr::SyntaxMismatch(pattern, expr, _) => {
+                write!(f, "`{expr}` does not match the pattern `{pattern}`")
+            }
+            EvalErr::NotAName(expr, _) => write!(f, "expected a name, found `{expr}`"),
+            EvalErr::MisplacedSplice(ident, _) => write!(
+                f,
+                "`{ident}` stands for any number of expressions and can only be spliced into a \
+                 call or a parameter list"
+            ),
+            EvalErr::ImportFailed(path, reason, _) => write!(f, "cannot read `{path}`: {reason}"),
+            EvalErr::ImportCycle(files, _) => write!(f, "import cycle: {}", files.join(" -> ")),
+            EvalErr::InModule(path, e, _) => {
+                use std::error::Error as _;
+                let cause = e.source().map(|e| format!(": {e}")).unwrap_or_default();
+                write!(f, "in `{path}` at {}: {e}{cause}", e.span())
+            }
+            EvalErr::NotExported(path, name, _) => write!(f, "`{path}` does not define `{name}`"),
+            EvalErr::TooManyInputs(inputs, _) => write!(
+                f,
+                "a truth table over {inputs} inputs is too large, the limit is {}",
+                table::MAX_INPUTS
+            ),
+            EvalErr::TooManySolutions(_) => write!(
+                f,
+                "the formula has too many solutions to list, the limit is {}",
+                sat::MAX_SOLUTIONS
+            ),
+            EvalErr::DifferentArity(left, right, _) => {
+                write!(f, "cannot compare functions of {left} and {right} inputs")
+            }
+            EvalErr::NotSymbolic(what, _) => {
+                write!(f, "symbolic evaluation does not support {what}")
+            }
+        }
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Value {
     Bool(bool),
+    Bits(Bits),
+    Int(usize),
     Operator(parser::Operator),
-    Lambda(Vec<String>, Expr),
+    Lambda(Rc<Lambda>),
+    Builtin(Builtin),
+    List(Vec<Value>),
+    Macro(Rc<Macro>),
 }
 
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Bool(b) => write!(f, "{b}"),
+            Value::Bits(bits) => write!(f, "{bits}"),
+            Value::Int(int) => write!(f, "{int}"),
             Value::Operator(o) => write!(f, "primitive operator: {o}"),
-            Value::Lambda(args, expr) => write!(f, "lambda: ({}) -> {expr}", args.join(" ")),
+            Value::Lambda(lambda) => write!(f, "(lambda {} {})", lambda.params, lambda.body),
+            Value::Builtin(builtin) => write!(f, "builtin function: {builtin}"),
+            Value::List(values) => {
+                write!(f, "(list")?;
+                for value in values {
+                    write!(f, " {value}")?;
+                }
+                write!(f, ")")
+            }
+            Value::Macro(definition) => {
+                write!(f, "(macro {} {})", definition.params, definition.template)
+            }
         }
     }
 }
 
-fn eval_bool_operands(
-    operands: &[Expr],
-    env: &mut Environment,
-) -> Result<impl Iterator<Item = bool>, EvalErr> {
-    let operands: Vec<Value> = operands
-        .iter()
-        .map(|operand| eval(operand, env))
-        .collect::<Result<Vec<Value>, EvalErr>>()?;
-    if operands.iter().any(|arg| !matches!(arg, Value::Bool(_))) {
-        return Err(EvalErr::Eval("`operand must be bool".to_string()));
-    }
-    let operands = operands.into_iter().map(|operand| match operand {
-        Value::Bool(b) => b,
-        _ => unreachable!(),
-    });
-    Ok(operands)
-}
-
-pub fn eval(expr: &Expr, env: &mut Environment) -> Result<Value, EvalErr> {
-    match expr {
-        Expr::Bool(b) => Ok(Value::Bool(*b)),
-        Expr::Operator(o) => Ok(Value::Operator(*o)),
-        Expr::Call(operator, operands) => match eval(operator, env)? {
-            Value::Operator(operator) => match operator {
-                parser::Operator::And => {
-                    let result = eval_bool_operands(operands, env)?.fold(true, |acc, b| acc & b);
-                    Ok(Value::Bool(result))
-                }
-                parser::Operator::Or => {
-                    let result = eval_bool_operands(operands, env)?.fold(false, |acc, b| acc | b);
-                    Ok(Value::Bool(result))
-                }
-                parser::Operator::Not => {
-                    let operands: Vec<bool> = eval_bool_operands(operands, env)?.collect();
-                    if operands.len() != 1 {
-                        return Err(EvalErr::Eval(format!(
-                            "the number of arguments of {operator} must be 1"
-                        )));
-                    }
-                    Ok(Value::Bool(!operands[0]))
-                }
-            },
-            Value::Lambda(args, expr) => {
-                if args.len() != operands.len() {
-                    return Err(EvalErr::Eval(format!(
-                        "the number of arguments ({}) is not the same as that of parameters ({})",
-                        args.len(),
-                        operands.len()
-                    )));
-                }
-                let mut env = env.clone();
-                let operands: Vec<Value> = operands
-                    .iter()
-                    .map(|operand| eval(operand, &mut env))
-                    .collect::<Result<_, EvalErr>>()?;
-                let data: HashMap<String, Value> = args.into_iter().zip(operands).collect();
-                env.extend(data);
-                eval(&expr, &mut env)
+/// A lambda together with the environment it was defined in, which its body is evaluated in.
+#[derive(Clone)]
+pub struct Lambda {
+    pub params: Params,
+    pub body: Expr,
+    pub env: Environment,
+}
+
+impl Lambda {
+    pub fn new(params: Params, body: Expr, env: Environment) -> Self {
+        Self { params, body, env }
+    }
+}
+
+/// Lambdas are equal when they have the same code and capture the same environment.
+impl PartialEq for Lambda {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params && self.body == other.body && self.env.ptr_eq(&other.env)
+    }
+}
+
+impl Eq for Lambda {}
+
+/// The captured environment is left out: it usually contains the lambda itself.
+impl std::fmt::Debug for Lambda {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lambda")
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The maximum nesting of evaluations that are not in tail position.
+///
+/// Tail calls, i.e. calls in the branches of an `if` or in the body of a lambda, run in
+/// constant stack and do not count towards the limit.
+pub const RECURSION_LIMIT: usize = 1000;
+
+/// The most stack, in bytes, that nested evaluations may use before failing with
+/// [`EvalErr::RecursionLimit`] short of [`RECURSION_LIMIT`]. Frames are far larger in
+/// unoptimized builds, and this leaves room to spare on a 1 MiB stack, the default for
+/// WebAssembly.
+pub const STACK_LIMIT: usize = 512 << 10;
+
+thread_local! {
+    /// Where the stack was when the outermost evaluation on this thread started.
+    static STACK_BASE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// The address of a local, which moves by the size of the frames pushed since another call.
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Marks the start of an evaluation, from which [`stack_exhausted`] measures the stack used
+/// unless an outer evaluation on the same thread is already running.
+pub(crate) struct StackBase {
+    outermost: bool,
+}
+
+impl StackBase {
+    pub(crate) fn enter() -> Self {
+        let outermost = STACK_BASE.with(|base| {
+            let outermost = base.get().is_none();
+            if outermost {
+                base.set(Some(stack_position()));
             }
-            operator => Err(EvalErr::Eval(format!("`{operator} is not an operator`"))),
+            outermost
+        });
+        Self { outermost }
+    }
+}
+
+impl Drop for StackBase {
+    fn drop(&mut self) {
+        if self.outermost {
+            STACK_BASE.with(|base| base.set(None));
+        }
+    }
+}
+
+/// Whether the running evaluations have used more than [`STACK_LIMIT`] of the stack.
+pub(crate) fn stack_exhausted() -> bool {
+    STACK_BASE.with(|base| {
+        base.get()
+            .is_some_and(|base| base.abs_diff(stack_position()) > STACK_LIMIT)
+    })
+}
+
+fn eval_bool(expr: &Expr, env: &Environment, depth: usize) -> Result<bool, EvalErr> {
+    match eval_at(expr, env, depth)? {
+        Value::Bool(b) => Ok(b),
+        value => Err(EvalErr::TypeMismatch("bool", Box::new(value), expr.span)),
+    }
+}
+
+fn as_bool(value: &Value, span: Span) -> Result<bool, EvalErr> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        value => Err(EvalErr::TypeMismatch("bool", Box::new(value.clone()), span)),
+    }
+}
+
+/// Checks that `value` is a bit vector of `width` bits.
+fn as_bits(value: Value, width: usize, span: Span) -> Result<Bits, EvalErr> {
+    match value {
+        Value::Bits(bits) if bits.width() == width => Ok(bits),
+        Value::Bits(bits) => Err(EvalErr::WidthMismatch(width, bits.width(), span)),
+        value => Err(EvalErr::TypeMismatch("bits", Box::new(value), span)),
+    }
+}
+
+/// Gets the operands at `indices` from left to right, stopping at the first one that is
+/// `decisive` and returning whether there was one. The remaining operands are not evaluated.
+fn short_circuit(
+    indices: Range<usize>,
+    decisive: bool,
+    operand: &mut impl FnMut(usize) -> Result<bool, EvalErr>,
+) -> Result<bool, EvalErr> {
+    for i in indices {
+        if operand(i)? == decisive {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Applies a primitive operator to `len` operands, getting the `i`th one from `operand` only
+/// when it is needed. With any number of operands:
+///
+/// - `&`, `|`, `nand` and `nor` short-circuit from left to right; `(&)` is true and `(|)` false.
+/// - `xor` is the parity, true when an odd number of operands are true.
+/// - `iff` is true when all operands are equal.
+/// - `implies` is right-associative, so `(implies a b c)` is `(implies (& a b) c)`. It stops at
+///   the first false premise, and `(implies)` is true.
+fn apply_operator(
+    operator: parser::Operator,
+    len: usize,
+    mut operand: impl FnMut(usize) -> Result<bool, EvalErr>,
+    span: Span,
+) -> Result<bool, EvalErr> {
+    use parser::Operator;
+    match operator {
+        Operator::And => Ok(!short_circuit(0..len, false, &mut operand)?),
+        Operator::Or => short_circuit(0..len, true, &mut operand),
+        Operator::Nand => short_circuit(0..len, false, &mut operand),
+        Operator::Nor => Ok(!short_circuit(0..len, true, &mut operand)?),
+        Operator::Not if len == 1 => Ok(!operand(0)?),
+        Operator::Not => Err(EvalErr::ArityMismatch(1, len, span)),
+        Operator::Xor => {
+            let mut parity = false;
+            for i in 0..len {
+                parity ^= operand(i)?;
+            }
+            Ok(parity)
+        }
+        Operator::Iff => {
+            let mut values = Vec::with_capacity(len);
+            for i in 0..len {
+                values.push(operand(i)?);
+            }
+            Ok(values.windows(2).all(|pair| pair[0] == pair[1]))
+        }
+        Operator::Implies => match len.checked_sub(1) {
+            None => Ok(true),
+            Some(last) => Ok(short_circuit(0..last, false, &mut operand)? || operand(last)?),
         },
-        Expr::If(parser::If { cond, then, other }) => {
-            let cond = match eval(cond, env)? {
-                Value::Bool(b) => b,
-                value => {
-                    return Err(EvalErr::Eval(format!(
-                        "condition must be bool, not `{value}`"
-                    )))
+    }
+}
+
+/// Applies a primitive operator to each position of bit vectors of the same width.
+fn apply_bitwise(
+    operator: parser::Operator,
+    vectors: &[Bits],
+    span: Span,
+) -> Result<Value, EvalErr> {
+    let operand = |i: usize| move |j: usize| Ok(vectors[j].bit(i) == Some(true));
+    vectors[0]
+        .try_map(|i| apply_operator(operator, vectors.len(), operand(i), span))
+        .map(Value::Bits)
+}
+
+/// Evaluates a primitive operator call. If the first operand is a bit vector, every operand
+/// must be one of the same width and the operator applies bitwise. Otherwise the operands are
+/// bools, evaluated only as needed.
+fn eval_operator(
+    operator: parser::Operator,
+    operands: &[Expr],
+    env: &Environment,
+    depth: usize,
+    span: Span,
+) -> Result<Value, EvalErr> {
+    let first = match operands.first() {
+        Some(first) => Some(eval_at(first, env, depth)?),
+        None => None,
+    };
+    if let Some(Value::Bits(first)) = first {
+        let width = first.width();
+        let mut vectors = vec![first];
+        for operand in &operands[1..] {
+            let value = eval_at(operand, env, depth)?;
+            vectors.push(as_bits(value, width, operand.span)?);
+        }
+        return apply_bitwise(operator, &vectors, span);
+    }
+    let operand = |i: usize| match (i, &first) {
+        (0, Some(value)) => as_bool(value, operands[0].span),
+        _ => eval_bool(&operands[i], env, depth),
+    };
+    apply_operator(operator, operands.len(), operand, span).map(Value::Bool)
+}
+
+/// Applies a primitive operator to evaluated `args` like [`eval_operator`]. Errors point at the
+/// whole call at `span`.
+fn apply_values(operator: parser::Operator, args: &[Value], span: Span) -> Result<Value, EvalErr> {
+    if let Some(Value::Bits(first)) = args.first() {
+        let mut vectors = Vec::with_capacity(args.len());
+        for arg in args {
+            vectors.push(as_bits(arg.clone(), first.width(), span)?);
+        }
+        return apply_bitwise(operator, &vectors, span);
+    }
+    let operand = |i: usize| as_bool(&args[i], span);
+    apply_operator(operator, args.len(), operand, span).map(Value::Bool)
+}
+
+pub fn eval(expr: &Expr, env: &Environment) -> Result<Value, EvalErr> {
+    let _base = StackBase::enter();
+    eval_at(expr, env, 0)
+}
+
+/// Evaluates `expr` nested `depth` evaluations deep. Subexpressions are evaluated recursively
+/// at `depth + 1`, while expressions in tail position replace `expr` and loop.
+fn eval_at(expr: &Expr, env: &Environment, depth: usize) -> Result<Value, EvalErr> {
+    if depth >= RECURSION_LIMIT || stack_exhausted() {
+        return Err(EvalErr::RecursionLimit(expr.span));
+    }
+    let mut expr = expr;
+    let mut env = env.clone();
+    // Own the body of the lambda called in tail position, or the expansion of a macro call,
+    // which `expr` points into.
+    let mut callee: Rc<Lambda>;
+    let mut expansion: Box<Expr>;
+    // Macro expansions since the last call, which count toward the limit so that a macro
+    // expanding to a call of itself fails rather than looping forever.
+    let mut expansions = 0;
+    loop {
+        match step(expr, &env, depth + 1)? {
+            Step::Done(value) => return Ok(value),
+            Step::Eval(next) => expr = next,
+            Step::EvalIn(next, scope) => {
+                env = scope;
+                expr = next;
+            }
+            Step::Call(lambda, scope) => {
+                env = scope;
+                callee = lambda;
+                expr = &callee.body;
+                expansions = 0;
+            }
+            Step::Expand(next, scope) => {
+                expansions += 1;
+                if depth + expansions >= RECURSION_LIMIT {
+                    return Err(EvalErr::RecursionLimit(expr.span));
                 }
-            };
-            eval(if cond { then } else { other }, env)
+                env = scope;
+                expansion = next;
+                expr = &expansion;
+            }
+        }
+    }
+}
+
+/// What is left to do after evaluating the non-tail parts of an expression.
+enum Step<'a> {
+    Done(Value),
+    /// Evaluate an expression in tail position in the same environment.
+    Eval(&'a Expr),
+    /// Evaluate an expression in tail position in a new scope.
+    EvalIn(&'a Expr, Environment),
+    /// Evaluate the body of a lambda in the scope binding its parameters.
+    Call(Rc<Lambda>, Environment),
+    /// Evaluate the expansion of a macro call in tail position, in a scope binding the names
+    /// it took from the definition.
+    Expand(Box<Expr>, Environment),
+}
+
+/// Evaluates the subexpressions of `expr` at `depth`, stopping short of its tail position.
+///
+/// The larger forms are evaluated in separate functions to keep this frame, which is on the
+/// stack once per nested evaluation, small.
+fn step<'a>(expr: &'a Expr, env: &Environment, depth: usize) -> Result<Step<'a>, EvalErr> {
+    let span = expr.span;
+    match &expr.kind {
+        ExprKind::Bool(b) => Ok(Step::Done(Value::Bool(*b))),
+        ExprKind::Bits(bits) => Ok(Step::Done(Value::Bits(bits.clone()))),
+        ExprKind::Int(int) => Ok(Step::Done(Value::Int(*int))),
+        ExprKind::Operator(o) => Ok(Step::Done(Value::Operator(*o))),
+        ExprKind::Call(operator, operands) => step_call(operator, operands, env, depth, span),
+        ExprKind::If(parser::If { cond, then, other }) => {
+            let cond = eval_bool(cond, env, depth)?;
+            Ok(Step::Eval(if cond { then } else { other }))
         }
-        Expr::Def(ident, expr) => {
-            let result = eval(expr, env)?;
+        ExprKind::Def(ident, expr) => {
+            let result = eval_at(expr, env, depth)?;
             env.add(ident.to_string(), result.clone());
-            Ok(result)
+            Ok(Step::Done(result))
+        }
+        ExprKind::Defmacro(ident, params, template) => {
+            let definition = Macro::new(params.clone(), (**template).clone(), env.clone());
+            let value = Value::Macro(Rc::new(definition));
+            env.add(ident.to_string(), value.clone());
+            Ok(Step::Done(value))
         }
-        Expr::Lambda(args, expr) => Ok(Value::Lambda(args.clone(), (**expr).clone())),
-        Expr::Ident(ident) => {
-            if let Some(value) = env.get(ident) {
-                Ok(value.clone())
+        ExprKind::Lambda(params, body) => Ok(Step::Done(Value::Lambda(Rc::new(Lambda::new(
+            params.clone(),
+            (**body).clone(),
+            env.clone(),
+        ))))),
+        ExprKind::Let(kind, bindings, body) => step_let(*kind, bindings, body, env, depth),
+        ExprKind::Cond(clauses, other) => step_cond(clauses, other.as_deref(), env, depth, span),
+        ExprKind::Import(path, names) => {
+            module::import(path, names.as_deref(), env, span)?;
+            Ok(Step::Done(Value::List(Vec::new())))
+        }
+        ExprKind::Ident(ident) => env
+            .get(ident)
+            .or_else(|| builtin::primitive(ident))
+            .map(Step::Done)
+            .ok_or_else(|| EvalErr::UnboundIdentifier(ident.to_string(), span)),
+        ExprKind::Error => Err(EvalErr::MalformedExpression(span)),
+    }
+}
+
+fn step_call<'a>(
+    operator: &Expr,
+    operands: &[Expr],
+    env: &Environment,
+    depth: usize,
+    span: Span,
+) -> Result<Step<'a>, EvalErr> {
+    let callee = eval_at(operator, env, depth)?;
+    match callee {
+        Value::Operator(operator) => {
+            return eval_operator(operator, operands, env, depth, span).map(Step::Done)
+        }
+        Value::Macro(definition) => {
+            let (expansion, captures) = definition.expand(operands, env, span)?;
+            let scope = if captures.is_empty() {
+                env.clone()
             } else {
-                Err(EvalErr::Eval(format!("`{ident}` is not defined")))
+                env.overlay(captures)
+            };
+            return Ok(Step::Expand(Box::new(expansion), scope));
+        }
+        // The operand may be an expression with free variables, which are the inputs.
+        Value::Builtin(builtin) if builtin.takes_formula() && operands.len() == 1 => {
+            let formula = Formula::of_expr(&operands[0], env)?;
+            return builtin.call_formula(&formula, span).map(Step::Done);
+        }
+        Value::Lambda(_) | Value::Builtin(_) => (),
+        value => return Err(EvalErr::NotCallable(Box::new(value), operator.span)),
+    }
+    let mut args = Vec::with_capacity(operands.len());
+    for operand in operands {
+        args.push(eval_at(operand, env, depth)?);
+    }
+    call(callee, args, span)
+}
+
+/// Calls `callee` with the evaluated `args` and returns the result, as a call expression at
+/// `span` would.
+pub fn apply(callee: Value, args: Vec<Value>, span: Span) -> Result<Value, EvalErr> {
+    let _base = StackBase::enter();
+    match call(callee, args, span)? {
+        Step::Done(value) => Ok(value),
+        Step::Call(lambda, scope) => eval_at(&lambda.body, &scope, 0),
+        Step::Eval(_) | Step::EvalIn(..) | Step::Expand(..) => {
+            unreachable!("a call either returns a value or enters a lambda body")
+        }
+    }
+}
+
+/// Calls `callee` with the evaluated `args`. Errors about the arguments point at the whole call
+/// at `span`.
+fn call<'a>(callee: Value, mut args: Vec<Value>, span: Span) -> Result<Step<'a>, EvalErr> {
+    match callee {
+        Value::Operator(operator) => apply_values(operator, &args, span).map(Step::Done),
+        Value::Lambda(lambda) => bind(lambda, args, span),
+        Value::Builtin(Builtin::Apply) => match (args.len(), args.pop()) {
+            (2.., Some(Value::List(list))) => {
+                let callee = args.remove(0);
+                args.extend(list);
+                call(callee, args, span)
             }
+            (2.., Some(value)) => Err(EvalErr::TypeMismatch("list", Box::new(value), span)),
+            (found, _) => Err(EvalErr::ArityMismatch(2, found, span)),
+        },
+        Value::Builtin(builtin) => builtin.call(args, span).map(Step::Done),
+        value => Err(EvalErr::NotCallable(Box::new(value), span)),
+    }
+}
+
+/// Binds the parameters of `lambda` to `args` in a new scope for its body. There must be an
+/// argument for every required parameter, and no more unless there is a rest parameter.
+fn bind<'a>(lambda: Rc<Lambda>, args: Vec<Value>, span: Span) -> Result<Step<'a>, EvalErr> {
+    let Params { required, rest } = &lambda.params;
+    if args.len() < required.len() || (rest.is_none() && args.len() > required.len()) {
+        return Err(EvalErr::ArityMismatch(required.len(), args.len(), span));
+    }
+    let scope = lambda.env.child();
+    let mut args = args.into_iter();
+    for (param, arg) in required.iter().zip(&mut args) {
+        destructure(param, arg, &scope, span)?;
+    }
+    if let Some(rest) = rest {
+        scope.add(rest.clone(), Value::List(args.collect()));
+    }
+    Ok(Step::Call(lambda, scope))
+}
+
+/// `lambda` with its first parameters bound to `args`, taking the remaining ones. Only required
+/// parameters can be bound, so there may be no more `args` than those. Errors point at `span`.
+pub(crate) fn partial(lambda: &Lambda, args: Vec<Value>, span: Span) -> Result<Value, EvalErr> {
+    let Params { required, rest } = &lambda.params;
+    if args.len() > required.len() {
+        return Err(EvalErr::ArityMismatch(required.len(), args.len(), span));
+    }
+    let scope = lambda.env.child();
+    let bound = args.len();
+    for (param, arg) in required.iter().zip(args) {
+        destructure(param, arg, &scope, span)?;
+    }
+    let params = Params::new(required[bound..].to_vec(), rest.clone());
+    let partial = Lambda::new(params, lambda.body.clone(), scope);
+    Ok(Value::Lambda(Rc::new(partial)))
+}
+
+/// Binds the identifiers in `pattern` to the matching parts of `value` in `scope`. Errors point
+/// at `span`.
+fn destructure(
+    pattern: &Pattern,
+    value: Value,
+    scope: &Environment,
+    span: Span,
+) -> Result<(), EvalErr> {
+    let (required, rest) = match pattern {
+        Pattern::Ident(ident) => {
+            scope.add(ident.clone(), value);
+            return Ok(());
+        }
+        Pattern::List(Params { required, rest }) => (required, rest),
+    };
+    let values = match value {
+        Value::List(values)
+            if values.len() == required.len()
+                || (rest.is_some() && values.len() > required.len()) =>
+        {
+            values
+        }
+        value => {
+            let pattern = pattern.clone();
+            return Err(EvalErr::PatternMismatch(pattern, Box::new(value), span));
         }
+    };
+    let mut values = values.into_iter();
+    for (pattern, value) in required.iter().zip(&mut values) {
+        destructure(pattern, value, scope, span)?;
+    }
+    if let Some(rest) = rest {
+        scope.add(rest.clone(), Value::List(values.collect()));
     }
+    Ok(())
+}
+
+fn step_let<'a>(
+    kind: LetKind,
+    bindings: &[(Pattern, Expr)],
+    body: &'a Expr,
+    env: &Environment,
+    depth: usize,
+) -> Result<Step<'a>, EvalErr> {
+    let mut scope = env.child();
+    for (pattern, expr) in bindings {
+        let value = match kind {
+            LetKind::Let => eval_at(expr, env, depth)?,
+            LetKind::LetStar => {
+                let value = eval_at(expr, &scope, depth)?;
+                // A scope per binding, so closures keep seeing the bindings before them even if
+                // a later binding shadows one.
+                scope = scope.child();
+                value
+            }
+            LetKind::Letrec => eval_at(expr, &scope, depth)?,
+        };
+        destructure(pattern, value, &scope, expr.span)?;
+    }
+    Ok(Step::EvalIn(body, scope))
+}
+
+fn step_cond<'a>(
+    clauses: &'a [(Expr, Expr)],
+    other: Option<&'a Expr>,
+    env: &Environment,
+    depth: usize,
+    span: Span,
+) -> Result<Step<'a>, EvalErr> {
+    for (test, expr) in clauses {
+        if eval_bool(test, env, depth)? {
+            return Ok(Step::Eval(expr));
+        }
+    }
+    other.map(Step::Eval).ok_or(EvalErr::NoMatchingClause(span))
 }
 
 #[cfg(test)]
@@ -127,13 +723,13 @@ mod tests {
     use crate::{
         parser::{
             self,
-            tests::{and, ident},
+            tests::{and, boolean, ident, params},
         },
         test_util::TestResult,
         tokenizer,
     };
 
-    fn eval_expr(expr: &str, env: &mut Environment) -> Result<Value, Box<dyn std::error::Error>> {
+    fn eval_expr(expr: &str, env: &Environment) -> Result<Value, Box<dyn std::error::Error>> {
         let tokens = tokenizer::tokenize(expr)?;
         let expr = parser::parse(&tokens)?;
         Ok(eval(&expr, env)?)
@@ -141,7 +737,7 @@ mod tests {
 
     #[test]
     fn eval_bool_succeed() -> TestResult {
-        let value = eval_expr("T", &mut Environment::default())?;
+        let value = eval_expr("T", &Environment::default())?;
         assert_eq!(Value::Bool(true), value);
         Ok(())
     }
@@ -149,15 +745,187 @@ mod tests {
     #[test]
     fn eval_call_succeed() -> TestResult {
         // true & (false | false | true | false) & (!false) -> true
-        let value = eval_expr("(& T (| F F T F) (^ F))", &mut Environment::default())?;
+        let value = eval_expr("(& T (| F F T F) (^ F))", &Environment::default())?;
         assert_eq!(Value::Bool(true), value);
         Ok(())
     }
 
+    #[test]
+    fn eval_and_or_short_circuit() -> TestResult {
+        let env = Environment::default();
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("(| T (undefined-thing))", &env)?
+        );
+        assert_eq!(
+            Value::Bool(false),
+            eval_expr("(& F (undefined-thing))", &env)?
+        );
+        assert_eq!(Value::Bool(true), eval_expr("(| F T &)", &env)?);
+        assert_eq!(Value::Bool(true), eval_expr("(&)", &env)?);
+        assert_eq!(Value::Bool(false), eval_expr("(|)", &env)?);
+        // Operands are evaluated from left to right up to the decisive one.
+        assert_eq!(
+            Value::Bool(false),
+            eval_expr("(& (def x T) (def x F) (def x T))", &env)?
+        );
+        assert_eq!(Value::Bool(false), eval_expr("x", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn eval_extended_operators_match_truth_tables() -> TestResult {
+        let env = Environment::default();
+        let b = |b: bool| if b { "T" } else { "F" };
+        for x in [false, true] {
+            for y in [false, true] {
+                let (bx, by) = (b(x), b(y));
+                for (source, expected) in [
+                    (format!("(xor {bx} {by})"), x != y),
+                    (format!("(implies {bx} {by})"), !x || y),
+                    (format!("(iff {bx} {by})"), x == y),
+                    (format!("(nand {bx} {by})"), !(x && y)),
+                    (format!("(nor {bx} {by})"), !(x || y)),
+                ] {
+                    assert_eq!(Value::Bool(expected), eval_expr(&source, &env)?, "{source}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn eval_extended_operators_are_variadic() -> TestResult {
+        let env = Environment::default();
+        for (source, expected) in [
+            ("(xor)", false),
+            ("(xor T)", true),
+            ("(xor T T T)", true),
+            ("(xor T F T F)", false),
+            ("(iff)", true),
+            ("(iff F)", true),
+            ("(iff T T T)", true),
+            ("(iff F F T)", false),
+            ("(implies)", true),
+            ("(implies F)", false),
+            ("(implies T T F)", false),
+            ("(implies T F F)", true),
+            ("(implies F (undefined-thing) F)", true),
+            ("(nand)", false),
+            ("(nand T T F)", true),
+            ("(nand T T T)", false),
+            ("(nand F (undefined-thing))", true),
+            ("(nor)", true),
+            ("(nor F F F)", true),
+            ("(nor F T F)", false),
+            ("(nor T (undefined-thing))", false),
+        ] {
+            assert_eq!(Value::Bool(expected), eval_expr(source, &env)?, "{source}");
+        }
+        assert_eq!(Value::Bool(true), eval_expr("((if F & xor) T F)", &env)?);
+        assert_eq!(
+            Value::Operator(parser::Operator::Nand),
+            eval_expr("(def g nand)", &env)?
+        );
+        assert_eq!(Value::Bool(false), eval_expr("(g T T)", &env)?);
+        assert_eq!(
+            "primitive operator: implies",
+            eval_expr("implies", &env)?.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval_let_binds_locally() -> TestResult {
+        let env = Environment::default();
+        eval_expr("(def a F)", &env)?;
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("(let ((a T) (b a)) (^ b))", &env)?
+        );
+        assert_eq!(
+            Value::Bool(false),
+            eval_expr("(let* ((a T) (b a)) (^ b))", &env)?
+        );
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("(let ((x T)) (def y x))", &env)?
+        );
+        assert_eq!(Value::Bool(false), eval_expr("a", &env)?);
+        assert!(env.get("b").is_none());
+        assert!(env.get("x").is_none());
+        assert!(env.get("y").is_none());
+        // Closures keep the binding they saw when a later one shadows it.
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("(let* ((x T) (f (lambda () x)) (x F)) (f))", &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval_letrec_supports_mutual_recursion() -> TestResult {
+        let env = Environment::default();
+        // Whether the number of set bits, counted down from the left, is even.
+        let source = "(letrec ((even? (lambda (a b c) (if a (odd? F b c) (if b (odd? F F c) (if c (odd? F F F) T)))))
+                               (odd? (lambda (a b c) (if a (even? F b c) (if b (even? F F c) (if c (even? F F F) F))))))
+                        (even? T F T))";
+        assert_eq!(Value::Bool(true), eval_expr(source, &env)?);
+        assert!(env.get("even?").is_none());
+        let tokens = tokenizer::tokenize("(let ((f (lambda () (f)))) (f))")?;
+        assert!(matches!(
+            eval(&parser::parse(&tokens)?, &env),
+            Err(EvalErr::UnboundIdentifier(ident, _)) if ident == "f"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn eval_cond_picks_first_true_clause() -> TestResult {
+        let env = Environment::default();
+        // A 2-to-1 priority encoder.
+        eval_expr(
+            "(def encode (lambda (a b) (cond (b (lambda (o) (& o T))) (a (lambda (o) (& o F))) (else &))))",
+            &env,
+        )?;
+        assert_eq!(Value::Bool(true), eval_expr("((encode T T) T)", &env)?);
+        assert_eq!(Value::Bool(false), eval_expr("((encode T F) T)", &env)?);
+        assert_eq!(
+            Value::Operator(parser::Operator::And),
+            eval_expr("(encode F F)", &env)?
+        );
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr(
+                "(cond (F (undefined-thing)) (T T) ((undefined-thing) F))",
+                &env
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval_cond_without_match_fails() -> TestResult {
+        let env = Environment::default();
+        let tokens = tokenizer::tokenize("(cond (F T) ((^ T) T))")?;
+        let e = eval(&parser::parse(&tokens)?, &env).unwrap_err();
+        assert_eq!(EvalErr::NoMatchingClause(Span::new(0, 22, 1, 1)), e);
+        assert_eq!(
+            "no `cond` clause matched and there is no `else`",
+            e.to_string()
+        );
+        let tokens = tokenizer::tokenize("(cond (& T))")?;
+        assert!(matches!(
+            eval(&parser::parse(&tokens)?, &env),
+            Err(EvalErr::TypeMismatch("bool", _, _))
+        ));
+        Ok(())
+    }
+
     #[test]
     fn eval_if_succeed() -> TestResult {
         // if true & true { true } else { false | false } -> true
-        let value = eval_expr("(if (& T T) T (| F F))", &mut Environment::default())?;
+        let value = eval_expr("(if (& T T) T (| F F))", &Environment::default())?;
         assert_eq!(Value::Bool(true), value);
         Ok(())
     }
@@ -165,7 +933,7 @@ mod tests {
     #[test]
     fn eval_if_to_operand_succeed() -> TestResult {
         // if !(true & true) { true } else { false | false } -> true
-        let value = eval_expr("(if (^ (& T T)) & |)", &mut Environment::default())?;
+        let value = eval_expr("(if (^ (& T T)) & |)", &Environment::default())?;
         assert_eq!(Value::Operator(parser::Operator::Or), value);
         Ok(())
     }
@@ -173,7 +941,7 @@ mod tests {
     #[test]
     fn eval_if_to_operator_succeed() -> TestResult {
         // if true { true & false } else { true | false }
-        let value = eval_expr("((if T & |) T F)", &mut Environment::default())?;
+        let value = eval_expr("((if T & |) T F)", &Environment::default())?;
         assert_eq!(Value::Bool(false), value);
         Ok(())
     }
@@ -182,30 +950,70 @@ mod tests {
     fn eval_invalid_operator_fail() -> TestResult {
         let tokens = tokenizer::tokenize("(T T F)")?;
         let expr = parser::parse(&tokens)?;
-        match eval(&expr, &mut Environment::default()) {
-            Err(EvalErr::Eval(_)) => (),
+        match eval(&expr, &Environment::default()) {
+            Err(e) => {
+                assert_eq!(
+                    EvalErr::NotCallable(Box::new(Value::Bool(true)), Span::new(1, 2, 1, 2)),
+                    e
+                );
+                assert_eq!("`true` is not callable", e.to_string());
+            }
             _ => panic!(),
         }
         Ok(())
     }
 
+    #[test]
+    fn eval_errors_are_typed() -> TestResult {
+        let env = Environment::default();
+        let eval_err = |expr: &str, env: &Environment| -> EvalErr {
+            let tokens = tokenizer::tokenize(expr).unwrap();
+            eval(&parser::parse(&tokens).unwrap(), env).unwrap_err()
+        };
+        assert!(matches!(
+            eval_err("(^ T F)", &env),
+            EvalErr::ArityMismatch(1, 2, _)
+        ));
+        assert!(matches!(
+            eval_err("((lambda (a) a) T F)", &env),
+            EvalErr::ArityMismatch(1, 2, _)
+        ));
+        assert_eq!(
+            EvalErr::TypeMismatch(
+                "bool",
+                Box::new(Value::Operator(parser::Operator::And)),
+                Span::new(5, 6, 1, 6)
+            ),
+            eval_err("(& T &)", &env)
+        );
+        assert!(matches!(
+            eval_err("(if & T F)", &env),
+            EvalErr::TypeMismatch("bool", _, _)
+        ));
+        assert_eq!(
+            EvalErr::UnboundIdentifier("x".to_string(), Span::new(5, 6, 1, 6)),
+            eval_err("(& T x)", &env)
+        );
+        Ok(())
+    }
+
     #[test]
     fn eval_def_succeed() -> TestResult {
-        let mut env = Environment::default();
+        let env = Environment::default();
         {
-            let value = eval_expr("(def myvar (& T T F))", &mut env)?;
+            let value = eval_expr("(def myvar (& T T F))", &env)?;
             assert_eq!(Value::Bool(false), value);
         }
         {
-            let value = eval_expr("myvar", &mut env)?;
+            let value = eval_expr("myvar", &env)?;
             assert_eq!(Value::Bool(false), value);
         }
         {
-            let value = eval_expr("(def myvar (& T T T))", &mut env)?;
+            let value = eval_expr("(def myvar (& T T T))", &env)?;
             assert_eq!(Value::Bool(true), value);
         }
         {
-            let value = eval_expr("myvar", &mut env)?;
+            let value = eval_expr("myvar", &env)?;
             assert_eq!(Value::Bool(true), value);
         }
         Ok(())
@@ -213,12 +1021,14 @@ mod tests {
 
     #[test]
     fn eval_lambda_succeed() -> TestResult {
-        let value = eval_expr("(lambda (a b) (& a b T))", &mut Environment::default())?;
+        let env = Environment::default();
+        let value = eval_expr("(lambda (a b) (& a b T))", &env)?;
         assert_eq!(
-            Value::Lambda(
-                vec!["a".to_string(), "b".to_string()],
-                and(vec![ident("a"), ident("b"), Expr::Bool(true)])
-            ),
+            Value::Lambda(Rc::new(Lambda::new(
+                params(&["a", "b"], None),
+                and(vec![ident("a"), ident("b"), boolean(true)]),
+                env
+            ))),
             value
         );
         Ok(())
@@ -228,7 +1038,7 @@ mod tests {
     fn eval_call_lambda_succeed() -> TestResult {
         let value = eval_expr(
             "((lambda (a b c) (| a b c)) F F T)",
-            &mut Environment::default(),
+            &Environment::default(),
         )?;
         assert_eq!(Value::Bool(true), value);
         Ok(())
@@ -236,32 +1046,404 @@ mod tests {
 
     #[test]
     fn eval_def_lambda_succeed() -> TestResult {
-        let mut env = Environment::default();
+        let env = Environment::default();
         {
-            let value = eval_expr("(def nand (lambda (a b) (^ (& a b))))", &mut env)?;
-            assert!(matches!(value, Value::Lambda(_, _)));
+            let value = eval_expr("(def gate (lambda (a b) (^ (& a b))))", &env)?;
+            assert!(matches!(value, Value::Lambda(_)));
         }
         {
-            let value = eval_expr("(nand T T)", &mut env)?;
+            let value = eval_expr("(gate T T)", &env)?;
             assert_eq!(Value::Bool(false), value);
         }
         Ok(())
     }
 
+    #[test]
+    fn eval_closure_captures_defining_environment() -> TestResult {
+        let env = Environment::default();
+        eval_expr("(def mk (lambda (a) (lambda (b) (& a b))))", &env)?;
+        eval_expr("(def and-t (mk T))", &env)?;
+        eval_expr("(def and-f (mk F))", &env)?;
+        assert_eq!(Value::Bool(true), eval_expr("(and-t T)", &env)?);
+        assert_eq!(Value::Bool(false), eval_expr("(and-f T)", &env)?);
+        assert_eq!(Value::Bool(false), eval_expr("((mk T) F)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn eval_lambda_does_not_see_caller_bindings() -> TestResult {
+        let env = Environment::default();
+        eval_expr("(def f (lambda () a))", &env)?;
+        eval_expr("(def g (lambda (a) (f)))", &env)?;
+        assert!(matches!(
+            eval(&parser::parse(&tokenizer::tokenize("(g T)")?)?, &env),
+            Err(EvalErr::UnboundIdentifier(ident, _)) if ident == "a"
+        ));
+        // Later global definitions are visible, so lambdas can be recursive.
+        eval_expr("(def a F)", &env)?;
+        assert_eq!(Value::Bool(false), eval_expr("(g T)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn eval_lambda_parameters_stay_local() -> TestResult {
+        let env = Environment::default();
+        eval_expr("(def id (lambda (x) (def y x)))", &env)?;
+        assert_eq!(Value::Bool(true), eval_expr("(id T)", &env)?);
+        assert!(env.get("x").is_none());
+        assert!(env.get("y").is_none());
+        Ok(())
+    }
+
+    /// A lambda over `bits` booleans that counts up in binary until all bits are set, calling
+    /// itself in tail position `2^bits - 1` times.
+    fn counter(bits: usize) -> String {
+        let params: Vec<String> = (0..bits).map(|i| format!("b{i}")).collect();
+        let next: Vec<String> = (0..bits)
+            .map(|i| {
+                let carry = format!("(& T {})", params[..i].join(" "));
+                format!("(| (& b{i} (^ {carry})) (& (^ b{i}) {carry}))")
+            })
+            .collect();
+        format!(
+            "(def count (lambda ({params}) (if (& {params}) T (count {next}))))",
+            params = params.join(" "),
+            next = next.join(" ")
+        )
+    }
+
+    #[test]
+    fn eval_tail_calls_run_in_constant_stack() -> TestResult {
+        let env = Environment::default();
+        eval_expr(&counter(12), &env)?;
+        let zeros = ["F"; 12].join(" ");
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr(&format!("(count {zeros})"), &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval_non_tail_recursion_hits_limit() -> TestResult {
+        // Runs on the test thread's own stack: unoptimized builds reach the stack limit well
+        // before the depth limit, and must fail with an error rather than overflow.
+        let env = Environment::default();
+        for source in [
+            "(def f (lambda () (^ (f))))",
+            "(def f (lambda () (& T (f))))",
+            "(def f (lambda () (apply & (list (f)))))",
+            "(def f (lambda () (truth-table (lambda (a) (f)))))",
+        ] {
+            eval_expr(source, &env)?;
+            let tokens = tokenizer::tokenize("(f)")?;
+            let e = eval(&parser::parse(&tokens)?, &env).unwrap_err();
+            assert!(matches!(e, EvalErr::RecursionLimit(_)), "{source}");
+            assert_eq!(
+                format!(
+                    "recursion limit of {RECURSION_LIMIT} nested calls or {} KiB of stack exceeded",
+                    STACK_LIMIT >> 10
+                ),
+                e.to_string()
+            );
+        }
+        // Recursion within the limit succeeds.
+        eval_expr("(def g (lambda (a) (& T (if a (g F) T))))", &env)?;
+        assert_eq!(Value::Bool(true), eval_expr("(g T)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn eval_rest_parameters_collect_arguments() -> TestResult {
+        let env = Environment::default();
+        eval_expr("(def f (lambda (a . rest) rest))", &env)?;
+        assert_eq!(Value::List(vec![]), eval_expr("(f T)", &env)?);
+        assert_eq!(
+            Value::List(vec![Value::Bool(false), Value::Bool(true)]),
+            eval_expr("(f T F T)", &env)?
+        );
+        eval_expr(
+            "(def all-same (lambda xs (| (apply & xs) (apply nor xs))))",
+            &env,
+        )?;
+        assert_eq!(Value::Bool(true), eval_expr("(all-same)", &env)?);
+        assert_eq!(Value::Bool(true), eval_expr("(all-same F F F)", &env)?);
+        assert_eq!(Value::Bool(false), eval_expr("(all-same T F T)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn eval_apply_spreads_list() -> TestResult {
+        let env = Environment::default();
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("(apply xor T (list F F))", &env)?
+        );
+        assert_eq!(
+            Value::Bool(false),
+            eval_expr("(apply (lambda (a b) (& a b)) (list T F))", &env)?
+        );
+        assert_eq!(
+            Value::List(vec![Value::Bool(true)]),
+            eval_expr("(apply list (list T))", &env)?
+        );
+        let eval_err = |source: &str| {
+            let tokens = tokenizer::tokenize(source).unwrap();
+            eval(&parser::parse(&tokens).unwrap(), &env).unwrap_err()
+        };
+        assert!(matches!(
+            eval_err("(apply & T)"),
+            EvalErr::TypeMismatch("list", _, _)
+        ));
+        assert!(matches!(
+            eval_err("(apply &)"),
+            EvalErr::ArityMismatch(2, 1, _)
+        ));
+        assert!(matches!(
+            eval_err("(apply & (list T &))"),
+            EvalErr::TypeMismatch("bool", _, _)
+        ));
+        // Builtins can be shadowed.
+        eval_expr("(def list T)", &env)?;
+        assert_eq!(Value::Bool(true), eval_expr("list", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn eval_named_operators_can_be_shadowed() -> TestResult {
+        let env = Environment::default();
+        assert_eq!(Value::Bool(false), eval_expr("(xor T T)", &env)?);
+        eval_expr("(def nand (lambda (a b) (^ (& a b))))", &env)?;
+        assert_eq!(
+            "(lambda (a b) (^ (& a b)))",
+            eval_expr("nand", &env)?.to_string()
+        );
+        assert_eq!(Value::Bool(false), eval_expr("(nand T T)", &env)?);
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("(let ((xor &)) (xor T T))", &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval_partial_application() -> TestResult {
+        let env = Environment::default();
+        eval_expr("(def and3 (lambda (a b c) (& a b c)))", &env)?;
+        eval_expr("(def and-t (partial and3 T))", &env)?;
+        assert_eq!(
+            "(lambda (b c) (& a b c))",
+            eval_expr("and-t", &env)?.to_string()
+        );
+        assert_eq!(Value::Bool(true), eval_expr("(and-t T T)", &env)?);
+        assert_eq!(
+            Value::Bool(false),
+            eval_expr("((partial and-t T) F)", &env)?
+        );
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("((partial and3 T T T))", &env)?
+        );
+        eval_expr("(def f (lambda (a b . rest) (apply xor a b rest)))", &env)?;
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("((partial f T) F F T T)", &env)?
+        );
+        let eval_err = |source: &str| {
+            let tokens = tokenizer::tokenize(source).unwrap();
+            eval(&parser::parse(&tokens).unwrap(), &env).unwrap_err()
+        };
+        // Calls without enough arguments are errors rather than partial applications.
+        assert!(matches!(
+            eval_err("(and3 T)"),
+            EvalErr::ArityMismatch(3, 1, _)
+        ));
+        assert!(matches!(
+            eval_err("(and-t T T T)"),
+            EvalErr::ArityMismatch(2, 3, _)
+        ));
+        assert!(matches!(
+            eval_err("(partial and3 T T T T)"),
+            EvalErr::ArityMismatch(3, 4, _)
+        ));
+        // Arguments for a rest parameter are not bound in advance.
+        assert!(matches!(
+            eval_err("(partial f T F T)"),
+            EvalErr::ArityMismatch(2, 3, _)
+        ));
+        assert!(matches!(
+            eval_err("(partial & T)"),
+            EvalErr::TypeMismatch("lambda", _, _)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn eval_destructures_lists() -> TestResult {
+        let env = Environment::default();
+        eval_expr(
+            "(def half-add (lambda (a b) (list (xor a b) (& a b))))",
+            &env,
+        )?;
+        eval_expr(
+            "(def full-add (lambda (a b c) \
+               (let* (((s1 c1) (half-add a b)) \
+                      ((s c2) (half-add s1 c))) \
+                 (list s (| c1 c2)))))",
+            &env,
+        )?;
+        assert_eq!(
+            "(list false true)",
+            eval_expr("(full-add T F T)", &env)?.to_string()
+        );
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("((lambda ((s c)) c) (half-add T T))", &env)?
+        );
+        assert_eq!(
+            "(list true (list false))",
+            eval_expr(
+                "(let (((a (b) . rest) (list T (list F) F))) (list a (list b)))",
+                &env
+            )?
+            .to_string()
+        );
+        assert_eq!(
+            Value::Bool(false),
+            eval_expr("(let (((a . rest) (list T F))) (first rest))", &env)?
+        );
+        assert_eq!(
+            "(list (list true false) true)",
+            eval_expr("(list (half-add T F) (nth (half-add T T) 1))", &env)?.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval_pattern_mismatch_fails() -> TestResult {
+        let env = Environment::default();
+        let tokens = tokenizer::tokenize("(let (((a b) (list T))) a)")?;
+        let e = eval(&parser::parse(&tokens)?, &env).unwrap_err();
+        assert_eq!(Span::new(13, 21, 1, 14), e.span());
+        assert_eq!(
+            "`(list true)` does not match the pattern `(a b)`",
+            e.to_string()
+        );
+        assert!(matches!(
+            eval_expr("((lambda ((a . b)) a) T)", &env)
+                .unwrap_err()
+                .downcast_ref::<EvalErr>(),
+            Some(EvalErr::PatternMismatch(..))
+        ));
+        assert_eq!(
+            Value::Bool(true),
+            eval_expr("((lambda ((a . b)) a) (list T))", &env)?
+        );
+        Ok(())
+    }
+
     #[test]
     fn eval_display() -> TestResult {
-        let mut env = Environment::default();
+        let env = Environment::default();
         assert_eq!(
             "primitive operator: &",
-            eval_expr("&", &mut env).unwrap().to_string()
+            eval_expr("&", &env).unwrap().to_string()
         );
-        assert_eq!("true", eval_expr("T", &mut env).unwrap().to_string());
+        assert_eq!("true", eval_expr("T", &env).unwrap().to_string());
         assert_eq!(
-            "lambda: (a b) -> (^ (& a b))",
-            eval_expr("(lambda (a b) (^ (& a b)))", &mut env)
+            "(lambda (a b) (^ (& a b)))",
+            eval_expr("(lambda (a b) (^ (& a b)))", &env)
                 .unwrap()
                 .to_string()
         );
+        assert_eq!(
+            "(lambda (a . b) b)",
+            eval_expr("(lambda (a . b) b)", &env)?.to_string()
+        );
+        assert_eq!(
+            "(list true (list))",
+            eval_expr("(list T (list))", &env)?.to_string()
+        );
+        assert_eq!(
+            "builtin function: apply",
+            eval_expr("apply", &env)?.to_string()
+        );
+        assert_eq!("#b0101", eval_expr("#b0101", &env)?.to_string());
+        assert_eq!("3", eval_expr("3", &env)?.to_string());
+        Ok(())
+    }
+
+    fn bits(digits: &str) -> Value {
+        Value::Bits(Bits::from_digits(digits).unwrap())
+    }
+
+    #[test]
+    fn eval_operators_apply_bitwise() -> TestResult {
+        let env = Environment::default();
+        for (expr, expected) in [
+            ("(& #b1100 #b1010)", "1000"),
+            ("(| #b1100 #b1010 #b0001)", "1111"),
+            ("(^ #b1100)", "0011"),
+            ("(xor #b1100 #b1010)", "0110"),
+            ("(implies #b1100 #b1010)", "1011"),
+            ("(iff #b1100 #b1010)", "1001"),
+            ("(nand #b1100 #b1010)", "0111"),
+            ("(nor #b1100 #b1010)", "0001"),
+            ("(apply & (list #b11 #b01))", "01"),
+            ("((lambda (a b) (xor a b)) #b01 #b11)", "10"),
+        ] {
+            assert_eq!(bits(expected), eval_expr(expr, &env)?, "{expr}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn eval_bitwise_width_mismatch_fails() -> TestResult {
+        let env = Environment::default();
+        let tokens = tokenizer::tokenize("(& #b1100 #b101)")?;
+        assert_eq!(
+            Err(EvalErr::WidthMismatch(4, 3, Span::new(10, 15, 1, 11))),
+            eval(&parser::parse(&tokens)?, &env)
+        );
+        let tokens = tokenizer::tokenize("(| #b1100 T)")?;
+        assert_eq!(
+            Err(EvalErr::TypeMismatch(
+                "bits",
+                Box::new(Value::Bool(true)),
+                Span::new(10, 11, 1, 11)
+            )),
+            eval(&parser::parse(&tokens)?, &env)
+        );
+        assert!(matches!(
+            eval_expr("(xor T #b1)", &env)
+                .unwrap_err()
+                .downcast_ref::<EvalErr>(),
+            Some(EvalErr::TypeMismatch("bool", _, _))
+        ));
+        assert!(matches!(
+            eval_expr("(apply & (list #b11 #b1))", &env)
+                .unwrap_err()
+                .downcast_ref::<EvalErr>(),
+            Some(EvalErr::WidthMismatch(2, 1, _))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn eval_ripple_carry_adder() -> TestResult {
+        let env = Environment::default();
+        eval_expr(
+            "(def add4 (lambda (a b) \
+               (let* ((g (& a b)) (p (xor a b)) \
+                      (c1 (shl g 1)) \
+                      (c2 (shl (| g (& p c1)) 1)) \
+                      (c3 (shl (| g (& p c2)) 1))) \
+                 (xor p c3))))",
+            &env,
+        )?;
+        assert_eq!(bits("0100"), eval_expr("(add4 #b0011 #b0001)", &env)?);
+        assert_eq!(bits("0000"), eval_expr("(add4 #b1111 #b0001)", &env)?);
+        assert_eq!(bits("1101"), eval_expr("(add4 #b0110 #b0111)", &env)?);
         Ok(())
     }
 }
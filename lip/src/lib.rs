@@ -1,9 +1,26 @@
+pub mod bdd;
+pub mod bits;
+pub mod builtin;
+pub mod cnf;
+pub mod dimacs;
 pub mod environment;
+pub mod equiv;
+pub mod error;
 pub mod evaluator;
+pub mod macros;
+pub mod module;
 pub mod parser;
+pub mod prelude;
+pub mod printer;
 pub mod repl;
+pub mod sat;
+pub mod span;
+pub mod symbolic;
+pub mod table;
 pub mod tokenizer;
 
+pub use error::Error;
+
 #[cfg(test)]
 mod test_util {
     pub type TestResult = Result<(), Box<dyn std::error::Error>>;
@@ -0,0 +1,631 @@
+use std::{collections::HashMap, iter, rc::Rc};
+
+use crate::{
+    builtin,
+    environment::Environment,
+    evaluator::{EvalErr, Value, RECURSION_LIMIT},
+    parser::{Expr, ExprKind, If, Params, Pattern},
+    span::Span,
+};
+
+/// A macro defined with `defmacro`, together with the environment it was defined in.
+///
+/// A call of a macro is replaced by its template, in which the parameters stand for the
+/// unevaluated operands, and the result is evaluated in place of the call.
+#[derive(Clone)]
+pub struct Macro {
+    pub params: Params,
+    pub template: Expr,
+    pub env: Environment,
+}
+
+impl Macro {
+    pub fn new(params: Params, template: Expr, env: Environment) -> Self {
+        Self {
+            params,
+            template,
+            env,
+        }
+    }
+
+    /// Expands a call of this macro with the unevaluated `operands`, to be evaluated in `env`.
+    ///
+    /// The names that the template binds itself, with `lambda`, `let` or `defmacro`, are
+    /// renamed with [`Environment::fresh_name`], so they never capture identifiers in the
+    /// operands. Names defined with `def` are kept, so a macro can define globals. The other
+    /// identifiers in the template refer to what they are bound to where the macro was defined:
+    /// one that `env` binds to something else is renamed, and the fresh name is returned with
+    /// the value from the definition. The expansion must be evaluated in a scope binding these,
+    /// such as [`Environment::overlay`], and `env` is left as it is. The parts of the template
+    /// take the span of the call at `span`, where errors point.
+    pub fn expand(
+        &self,
+        operands: &[Expr],
+        env: &Environment,
+        span: Span,
+    ) -> Result<(Expr, HashMap<String, Value>), EvalErr> {
+        self.expand_in(operands, env, |_| false, span)
+    }
+
+    /// Like [`Macro::expand`], for a call site where the names for which `local` is true are
+    /// bound outside `env` and so shadow any binding of the definition.
+    pub(crate) fn expand_in(
+        &self,
+        operands: &[Expr],
+        env: &Environment,
+        local: impl Fn(&str) -> bool,
+        span: Span,
+    ) -> Result<(Expr, HashMap<String, Value>), EvalErr> {
+        let mut expander = Expander {
+            bindings: HashMap::new(),
+            renames: HashMap::new(),
+            span,
+        };
+        if !expander.bind_list(&self.params, operands)? {
+            let expected = self.params.required.len();
+            return Err(EvalErr::ArityMismatch(expected, operands.len(), span));
+        }
+        let mut binders = Vec::new();
+        expr_binders(&self.template, &mut binders);
+        for binder in binders {
+            if !expander.bindings.contains_key(&binder) && !expander.renames.contains_key(&binder) {
+                let fresh = env.fresh_name(&binder);
+                expander.renames.insert(binder, fresh);
+            }
+        }
+        let mut captures = HashMap::new();
+        let mut references = Vec::new();
+        expr_references(&self.template, &mut references);
+        for ident in references {
+            if expander.bindings.contains_key(&ident) || expander.renames.contains_key(&ident) {
+                continue;
+            }
+            let resolve =
+                |env: &Environment| env.get(&ident).or_else(|| builtin::primitive(&ident));
+            let Some(value) = resolve(&self.env) else {
+                continue;
+            };
+            if local(&ident) || !resolve(env).is_some_and(|here| same(&here, &value)) {
+                let fresh = env.fresh_name(&ident);
+                captures.insert(fresh.clone(), value);
+                expander.renames.insert(ident, fresh);
+            }
+        }
+        Ok((expander.expr(&self.template)?, captures))
+    }
+}
+
+/// Whether `a` and `b` are the same value, comparing lambdas and macros by identity rather than
+/// by their code, which can be large.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Lambda(a), Value::Lambda(b)) => Rc::ptr_eq(a, b),
+        (Value::Macro(a), Value::Macro(b)) => Rc::ptr_eq(a, b),
+        (a, b) => a == b,
+    }
+}
+
+/// Macros are equal when they have the same template and were defined in the same environment.
+impl PartialEq for Macro {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params
+            && self.template == other.template
+            && self.env.ptr_eq(&other.env)
+    }
+}
+
+impl Eq for Macro {}
+
+/// The environment is left out: it usually contains the macro itself.
+impl std::fmt::Debug for Macro {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Macro")
+            .field("params", &self.params)
+            .field("template", &self.template)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Expands `expr` for as long as it is a call of a macro defined in `env`, without evaluating
+/// anything or adding to `env`. Macro calls nested in the result are left as they are.
+pub fn expand(expr: &Expr, env: &Environment) -> Result<Expr, EvalErr> {
+    let mut expr = expr.clone();
+    let mut env = env.clone();
+    for _ in 0..RECURSION_LIMIT {
+        let ExprKind::Call(operator, operands) = &expr.kind else {
+            return Ok(expr);
+        };
+        let ExprKind::Ident(ident) = &operator.kind else {
+            return Ok(expr);
+        };
+        let Some(Value::Macro(definition)) = env.get(ident) else {
+            return Ok(expr);
+        };
+        let captures;
+        (expr, captures) = definition.expand(operands, &env, expr.span)?;
+        env = env.overlay(captures);
+    }
+    Err(EvalErr::RecursionLimit(expr.span))
+}
+
+/// What a macro parameter stands for in an expansion.
+enum Binding {
+    Expr(Expr),
+    /// The operands collected by a `. rest` parameter, which are spliced into the list they
+    /// appear in.
+    Splice(Vec<Expr>),
+}
+
+/// Instantiates a template.
+struct Expander {
+    bindings: HashMap<String, Binding>,
+    /// Fresh names for the names the template binds itself.
+    renames: HashMap<String, String>,
+    span: Span,
+}
+
+impl Expander {
+    /// Binds `params` to `items`, returning false if their numbers do not match.
+    fn bind_list(&mut self, params: &Params, items: &[Expr]) -> Result<bool, EvalErr> {
+        let Params { required, rest } = params;
+        if items.len() < required.len() || (rest.is_none() && items.len() > required.len()) {
+            return Ok(false);
+        }
+        for (pattern, item) in required.iter().zip(items) {
+            self.bind(pattern, item)?;
+        }
+        if let Some(rest) = rest {
+            let items = items[required.len()..].to_vec();
+            self.bindings.insert(rest.clone(), Binding::Splice(items));
+        }
+        Ok(true)
+    }
+
+    /// Binds `pattern` to `expr`. A list pattern takes apart a call, operator first.
+    fn bind(&mut self, pattern: &Pattern, expr: &Expr) -> Result<(), EvalErr> {
+        let params = match pattern {
+            Pattern::Ident(ident) => {
+                self.bindings
+                    .insert(ident.clone(), Binding::Expr(expr.clone()));
+                return Ok(());
+            }
+            Pattern::List(params) => params,
+        };
+        if let ExprKind::Call(operator, operands) = &expr.kind {
+            let items: Vec<Expr> = iter::once(&**operator).chain(operands).cloned().collect();
+            if self.bind_list(params, &items)? {
+                return Ok(());
+            }
+        }
+        Err(EvalErr::SyntaxMismatch(
+            pattern.clone(),
+            Box::new(expr.clone()),
+            expr.span,
+        ))
+    }
+
+    fn rename(&self, ident: &str) -> String {
+        self.renames
+            .get(ident)
+            .cloned()
+            .unwrap_or_else(|| ident.to_string())
+    }
+
+    /// The operands that `ident` stands for if it is a rest parameter.
+    fn splice(&self, ident: &str) -> Option<&[Expr]> {
+        match self.bindings.get(ident) {
+            Some(Binding::Splice(items)) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn expr(&self, expr: &Expr) -> Result<Expr, EvalErr> {
+        let kind = match &expr.kind {
+            ExprKind::Ident(ident) => match self.bindings.get(ident) {
+                Some(Binding::Expr(expr)) => return Ok(expr.clone()),
+                Some(Binding::Splice(_)) => {
+                    return Err(EvalErr::MisplacedSplice(ident.clone(), self.span))
+                }
+                None => ExprKind::Ident(self.rename(ident)),
+            },
+            ExprKind::Call(operator, operands) => {
+                ExprKind::Call(Box::new(self.expr(operator)?), self.exprs(operands)?)
+            }
+            ExprKind::If(If { cond, then, other }) => ExprKind::If(If::new(
+                self.expr(cond)?,
+                self.expr(then)?,
+                self.expr(other)?,
+            )),
+            ExprKind::Def(ident, value) => {
+                ExprKind::Def(self.name(ident)?, Box::new(self.expr(value)?))
+            }
+            ExprKind::Defmacro(ident, params, template) => ExprKind::Defmacro(
+                self.name(ident)?,
+                self.params(params)?,
+                Box::new(self.expr(template)?),
+            ),
+            ExprKind::Lambda(params, body) => {
+                ExprKind::Lambda(self.params(params)?, Box::new(self.expr(body)?))
+            }
+            ExprKind::Let(kind, bindings, body) => {
+                let mut expanded = Vec::with_capacity(bindings.len());
+                for (pattern, value) in bindings {
+                    expanded.push((self.pattern(pattern)?, self.expr(value)?));
+                }
+                ExprKind::Let(*kind, expanded, Box::new(self.expr(body)?))
+            }
+            ExprKind::Cond(clauses, other) => {
+                let mut expanded = Vec::with_capacity(clauses.len());
+                for (test, expr) in clauses {
+                    expanded.push((self.expr(test)?, self.expr(expr)?));
+                }
+                let other = match other {
+                    Some(other) => Some(Box::new(self.expr(other)?)),
+                    None => None,
+                };
+                ExprKind::Cond(expanded, other)
+            }
+            kind => kind.clone(),
+        };
+        Ok(Expr::new(kind, self.span))
+    }
+
+    /// Expands `exprs`, splicing in the operands collected by rest parameters.
+    fn exprs(&self, exprs: &[Expr]) -> Result<Vec<Expr>, EvalErr> {
+        let mut expanded = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            let spliced = match &expr.kind {
+                ExprKind::Ident(ident) => self.splice(ident),
+                _ => None,
+            };
+            match spliced {
+                Some(items) => expanded.extend_from_slice(items),
+                None => expanded.push(self.expr(expr)?),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Expands the name defined by `def` or `defmacro`, which a parameter may stand for.
+    fn name(&self, ident: &str) -> Result<String, EvalErr> {
+        match self.bindings.get(ident) {
+            Some(Binding::Expr(expr)) => name_of(expr),
+            Some(Binding::Splice(_)) => Err(EvalErr::MisplacedSplice(ident.to_string(), self.span)),
+            None => Ok(ident.to_string()),
+        }
+    }
+
+    fn pattern(&self, pattern: &Pattern) -> Result<Pattern, EvalErr> {
+        match pattern {
+            Pattern::Ident(ident) => match self.bindings.get(ident) {
+                Some(Binding::Expr(expr)) => pattern_of(expr),
+                Some(Binding::Splice(_)) => Err(EvalErr::MisplacedSplice(ident.clone(), self.span)),
+                None => Ok(Pattern::Ident(self.rename(ident))),
+            },
+            Pattern::List(params) => self.params(params).map(Pattern::List),
+        }
+    }
+
+    fn params(&self, params: &Params) -> Result<Params, EvalErr> {
+        let mut required = Vec::with_capacity(params.required.len());
+        for pattern in &params.required {
+            let spliced = match pattern {
+                Pattern::Ident(ident) => self.splice(ident),
+                Pattern::List(_) => None,
+            };
+            match spliced {
+                Some(items) => {
+                    for item in items {
+                        required.push(pattern_of(item)?);
+                    }
+                }
+                None => required.push(self.pattern(pattern)?),
+            }
+        }
+        let Some(rest) = &params.rest else {
+            return Ok(Params::new(required, None));
+        };
+        match self.bindings.get(rest) {
+            // A lone rest parameter, as in `(lambda args body)`, stands for the whole list.
+            Some(Binding::Expr(expr)) if required.is_empty() => params_of(expr),
+            Some(Binding::Splice(items)) if required.is_empty() => {
+                let mut required = Vec::with_capacity(items.len());
+                for item in items {
+                    required.push(pattern_of(item)?);
+                }
+                Ok(Params::new(required, None))
+            }
+            Some(Binding::Expr(expr)) => Ok(Params::new(required, Some(name_of(expr)?))),
+            Some(Binding::Splice(_)) => Err(EvalErr::MisplacedSplice(rest.clone(), self.span)),
+            None => Ok(Params::new(required, Some(self.rename(rest)))),
+        }
+    }
+}
+
+fn name_of(expr: &Expr) -> Result<String, EvalErr> {
+    match &expr.kind {
+        ExprKind::Ident(ident) => Ok(ident.clone()),
+        _ => Err(EvalErr::NotAName(Box::new(expr.clone()), expr.span)),
+    }
+}
+
+/// Reads an operand such as `(a (b c))` as a pattern.
+fn pattern_of(expr: &Expr) -> Result<Pattern, EvalErr> {
+    match &expr.kind {
+        ExprKind::Call(..) => params_of(expr).map(Pattern::List),
+        _ => name_of(expr).map(Pattern::Ident),
+    }
+}
+
+/// Reads an operand as a parameter list: `(a b)`, or a lone name that collects every argument.
+fn params_of(expr: &Expr) -> Result<Params, EvalErr> {
+    let ExprKind::Call(operator, operands) = &expr.kind else {
+        return Ok(Params::new(Vec::new(), Some(name_of(expr)?)));
+    };
+    let mut required = vec![pattern_of(operator)?];
+    for operand in operands {
+        required.push(pattern_of(operand)?);
+    }
+    Ok(Params::new(required, None))
+}
+
+/// Collects the names bound by the `lambda`, `let` and `defmacro` forms in `expr`.
+fn expr_binders(expr: &Expr, binders: &mut Vec<String>) {
+    match &expr.kind {
+        ExprKind::Call(operator, operands) => {
+            expr_binders(operator, binders);
+            for operand in operands {
+                expr_binders(operand, binders);
+            }
+        }
+        ExprKind::If(If { cond, then, other }) => {
+            for expr in [cond, then, other] {
+                expr_binders(expr, binders);
+            }
+        }
+        ExprKind::Def(_, value) => expr_binders(value, binders),
+        ExprKind::Defmacro(_, params, body) | ExprKind::Lambda(params, body) => {
+            params_binders(params, binders);
+            expr_binders(body, binders);
+        }
+        ExprKind::Let(_, bindings, body) => {
+            for (pattern, value) in bindings {
+                pattern_binders(pattern, binders);
+                expr_binders(value, binders);
+            }
+            expr_binders(body, binders);
+        }
+        ExprKind::Cond(clauses, other) => {
+            for (test, expr) in clauses {
+                expr_binders(test, binders);
+                expr_binders(expr, binders);
+            }
+            if let Some(other) = other {
+                expr_binders(other, binders);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Collects the identifiers that `expr` refers to, other than names being bound or defined.
+fn expr_references(expr: &Expr, references: &mut Vec<String>) {
+    match &expr.kind {
+        ExprKind::Ident(ident) if !references.contains(ident) => references.push(ident.clone()),
+        ExprKind::Call(operator, operands) => {
+            expr_references(operator, references);
+            for operand in operands {
+                expr_references(operand, references);
+            }
+        }
+        ExprKind::If(If { cond, then, other }) => {
+            for expr in [cond, then, other] {
+                expr_references(expr, references);
+            }
+        }
+        ExprKind::Def(_, body) | ExprKind::Defmacro(_, _, body) | ExprKind::Lambda(_, body) => {
+            expr_references(body, references)
+        }
+        ExprKind::Let(_, bindings, body) => {
+            for (_, value) in bindings {
+                expr_references(value, references);
+            }
+            expr_references(body, references);
+        }
+        ExprKind::Cond(clauses, other) => {
+            for (test, expr) in clauses {
+                expr_references(test, references);
+                expr_references(expr, references);
+            }
+            if let Some(other) = other {
+                expr_references(other, references);
+            }
+        }
+        _ => (),
+    }
+}
+
+pub(crate) fn params_binders(params: &Params, binders: &mut Vec<String>) {
+    for pattern in &params.required {
+        pattern_binders(pattern, binders);
+    }
+    binders.extend(params.rest.iter().cloned());
+}
+
+pub(crate) fn pattern_binders(pattern: &Pattern, binders: &mut Vec<String>) {
+    match pattern {
+        Pattern::Ident(ident) => binders.push(ident.clone()),
+        Pattern::List(params) => params_binders(params, binders),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cnf::Formula, evaluator::eval, parser, table, test_util::TestResult, tokenizer};
+
+    fn parse(source: &str) -> Expr {
+        parser::parse(&tokenizer::tokenize(source).unwrap()).unwrap()
+    }
+
+    fn eval_str(source: &str, env: &Environment) -> Result<Value, EvalErr> {
+        let tokens = tokenizer::tokenize(source).unwrap();
+        let mut value = Value::Bool(false);
+        for expr in parser::parse_program(&tokens).unwrap() {
+            value = eval(&expr, env)?;
+        }
+        Ok(value)
+    }
+
+    #[test]
+    fn macro_expands_template() -> TestResult {
+        let env = Environment::default();
+        eval_str(
+            "(defmacro defgate (name params body) (def name (lambda params body)))",
+            &env,
+        )?;
+        assert_eq!(
+            parse("(def nand2 (lambda (a b) (^ (& a b))))"),
+            expand(&parse("(defgate nand2 (a b) (^ (& a b)))"), &env)?
+        );
+        eval_str("(defgate nand2 (a b) (^ (& a b)))", &env)?;
+        assert_eq!(Value::Bool(false), eval_str("(nand2 T T)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn macro_renames_template_binders() -> TestResult {
+        let env = Environment::default();
+        eval_str("(defmacro my-or (a b) (let ((t a)) (if t t b)))", &env)?;
+        assert_eq!(
+            "(let ((t%1 F)) (if t%1 t%1 t))",
+            expand(&parse("(my-or F t)"), &env)?.to_string()
+        );
+        assert_eq!(
+            Value::Bool(true),
+            eval_str("(let ((t T)) (my-or F t))", &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn macro_template_refers_to_definition_bindings() -> TestResult {
+        let env = Environment::default();
+        eval_str("(def helper (lambda (a) (^ a)))", &env)?;
+        eval_str("(defmacro m (x) (helper x))", &env)?;
+        eval_str("(defmacro flip (x) (xor x T))", &env)?;
+        assert_eq!(
+            Value::Bool(false),
+            eval_str("(let ((helper (lambda (a) a))) (m T))", &env)?
+        );
+        assert_eq!(
+            Value::Bool(false),
+            eval_str("(let ((xor &)) (flip T))", &env)?
+        );
+        // Operands still see the bindings of the call site.
+        assert_eq!(
+            Value::Bool(true),
+            eval_str("(let ((helper F)) (m helper))", &env)?
+        );
+        // Unshadowed names are left as they are.
+        assert_eq!(Ok(parse("(helper T)")), expand(&parse("(m T)"), &env));
+        Ok(())
+    }
+
+    #[test]
+    fn macro_expansion_leaves_call_env_unchanged() -> TestResult {
+        let env = Environment::default();
+        eval_str("(def mm (let ((y T)) (defmacro mm () y)))", &env)?;
+        let names = |env: &Environment| {
+            let mut names: Vec<String> = env.bindings().into_iter().map(|(name, _)| name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(vec!["mm"], names(&env));
+        assert_eq!("y%1", expand(&parse("(mm)"), &env)?.to_string());
+        assert_eq!(Value::Bool(true), eval_str("(mm)", &env)?);
+        assert_eq!(Value::Bool(true), eval_str("(mm)", &env)?);
+        let expr = parse("(& (mm) a)");
+        assert_eq!(vec!["a"], table::free_variables(&expr, &env)?);
+        assert_eq!(vec!["a"], Formula::of_expr(&expr, &env)?.inputs);
+        assert_eq!(vec!["mm"], names(&env));
+        Ok(())
+    }
+
+    #[test]
+    fn macro_splices_rest_parameters() -> TestResult {
+        let env = Environment::default();
+        eval_str(
+            "(defmacro defn ((name . params) . body) (def name (lambda params (& body))))",
+            &env,
+        )?;
+        assert_eq!(
+            parse("(def f (lambda (a b) (& a (^ b))))"),
+            expand(&parse("(defn (f a b) a (^ b))"), &env)?
+        );
+        eval_str(
+            "(defmacro unless* (test . body) (if test T (| body)))",
+            &env,
+        )?;
+        assert_eq!(
+            parse("(if F T (| T F))"),
+            expand(&parse("(unless* F T F)"), &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn macro_errors_point_at_call() -> TestResult {
+        let env = Environment::default();
+        eval_str("(defmacro defgate (name (a b) body) (def name T))", &env)?;
+        eval_str("(defmacro bad (. xs) (not-spliceable xs))", &env)?;
+        eval_str("(defmacro bad2 (. xs) (let ((y xs)) y))", &env)?;
+        let span = |start, end| Span::new(start, end, 1, start + 1);
+        assert_eq!(
+            Err(EvalErr::ArityMismatch(3, 1, span(0, 11))),
+            eval_str("(defgate g)", &env)
+        );
+        assert!(matches!(
+            eval_str("(defgate g (a) T)", &env),
+            Err(EvalErr::SyntaxMismatch(_, _, s)) if s == span(11, 14)
+        ));
+        assert!(matches!(
+            eval_str("(defgate (g) (a b) T)", &env),
+            Err(EvalErr::NotAName(_, s)) if s == span(9, 12)
+        ));
+        assert_eq!(
+            Err(EvalErr::MisplacedSplice("xs".to_string(), span(0, 10))),
+            eval_str("(bad2 T F)", &env)
+        );
+        assert_eq!(
+            Value::Bool(true),
+            eval_str("(def not-spliceable (lambda xs T)) (bad T F)", &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn macro_expand_stops_at_recursion_limit() {
+        let env = Environment::default();
+        eval_str("(defmacro forever (a) (forever a))", &env).unwrap();
+        assert!(matches!(
+            expand(&parse("(forever T)"), &env),
+            Err(EvalErr::RecursionLimit(_))
+        ));
+        assert_eq!(Ok(parse("(f T)")), expand(&parse("(f T)"), &env));
+    }
+
+    #[test]
+    fn macro_evaluation_stops_at_recursion_limit() {
+        let env = Environment::default();
+        eval_str("(defmacro loop (x) (loop x))", &env).unwrap();
+        eval_str("(defmacro spin (x) (if x (spin x) F))", &env).unwrap();
+        for source in ["(loop T)", "(spin T)", "(^ (loop T))"] {
+            assert!(
+                matches!(eval_str(source, &env), Err(EvalErr::RecursionLimit(_))),
+                "{source}"
+            );
+        }
+        assert_eq!(Ok(Value::Bool(false)), eval_str("(spin F)", &env));
+    }
+}
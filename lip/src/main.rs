@@ -1,7 +1,105 @@
-use std::io;
+use std::{env, fs, io, process};
 
-use lip::repl;
+use lip::{
+    dimacs,
+    parser::{Expr, ExprKind},
+    prelude, printer, repl, Error,
+};
+
+const USAGE: &str =
+    "usage: lip [--no-prelude] [run <file> | fmt [--check] <file>... | cnf <file> | dimacs <file>]";
 
 fn main() -> io::Result<()> {
-    repl::run(&mut io::stdin().lock(), &mut io::stdout())
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let with_prelude = !args.iter().any(|arg| arg == "--no-prelude");
+    args.retain(|arg| arg != "--no-prelude");
+    match args.split_first() {
+        None => repl::run_in(
+            &mut io::stdin().lock(),
+            &mut io::stdout(),
+            prelude::environment(with_prelude),
+        ),
+        Some((command, [path])) if command == "run" => run(path, with_prelude),
+        Some((command, args)) if command == "fmt" => fmt(args),
+        Some((command, [path])) if command == "cnf" => cnf(path, with_prelude),
+        Some((command, [path])) if command == "dimacs" => dimacs(path),
+        Some(_) => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    }
+}
+
+/// Evaluates a `.lip` file, resolving its imports relative to it, and prints the value of its
+/// last expression.
+fn run(path: &str, with_prelude: bool) -> io::Result<()> {
+    let source = fs::read_to_string(path)?;
+    let env = prelude::environment(with_prelude).module(vec![fs::canonicalize(path)?]);
+    match repl::eval_str(&source, &env) {
+        Ok(Some(value)) => println!("{value}"),
+        Ok(None) => (),
+        Err(e) => {
+            eprintln!("{path}:{}: {}", e.span(), e.report(&source));
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the last expression of a `.lip` file, after evaluating the others, as CNF in the
+/// DIMACS format.
+fn cnf(path: &str, with_prelude: bool) -> io::Result<()> {
+    let source = fs::read_to_string(path)?;
+    let env = prelude::environment(with_prelude).module(vec![fs::canonicalize(path)?]);
+    match repl::dimacs_str(&source, &env) {
+        Ok(dimacs) => print!("{dimacs}"),
+        Err(e) => {
+            eprintln!("{path}:{}: {}", e.span(), e.report(&source));
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Prints a DIMACS `.cnf` file as a lambda of its variables.
+fn dimacs(path: &str) -> io::Result<()> {
+    let source = fs::read_to_string(path)?;
+    match dimacs::parse(&source) {
+        Ok(dimacs) => {
+            let body = Box::new(dimacs.expr());
+            let lambda = Expr::new(ExprKind::Lambda(dimacs.params(), body), Default::default());
+            println!("{}", printer::pretty(&lambda, printer::WIDTH));
+        }
+        Err(e) => {
+            let e = Error::from(e);
+            eprintln!("{path}:{}: {}", e.span(), e.report(&source));
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Formats `.lip` files in place. With `--check`, only lists the files that are not formatted.
+fn fmt(args: &[String]) -> io::Result<()> {
+    let check = args.iter().any(|arg| arg == "--check");
+    let mut ok = true;
+    for path in args.iter().filter(|arg| *arg != "--check") {
+        let source = fs::read_to_string(path)?;
+        match printer::format(&source, printer::WIDTH) {
+            Ok(formatted) if formatted == source => (),
+            Ok(_) if check => {
+                println!("{path} is not formatted");
+                ok = false;
+            }
+            Ok(formatted) => fs::write(path, formatted)?,
+            Err(e) => {
+                eprintln!("{path}:{}: {}", e.span(), e.report(&source));
+                ok = false;
+            }
+        }
+    }
+    if !ok {
+        process::exit(1);
+    }
+    Ok(())
 }
@@ -0,0 +1,238 @@
+//! Splitting programs across files with `(import "path")`.
+//!
+//! An imported file is a module: it is evaluated in its own environment, and the names it
+//! defines are then bound in the importing environment. By default they are namespaced by the
+//! file name, so `(import "lib/adders.lip")` binds `full-add` as `adders/full-add`. With a list
+//! of names, as in `(import "adders.lip" (full-add))`, only those are bound, without a
+//! namespace.
+//!
+//! A `.cnf` file in the DIMACS format is read with [`crate::dimacs`] instead, and binds a single
+//! lambda of its variables named after the file: `(import "uf20-01.cnf")` binds `uf20-01`.
+//!
+//! Paths are relative to the directory of the importing file, or to the working directory
+//! outside of a module, such as in the REPL. Modules see the prelude, if it is loaded, but not
+//! the definitions of the program importing them.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    dimacs,
+    environment::Environment,
+    evaluator::{EvalErr, Value},
+    repl::eval_str,
+    span::Span,
+};
+
+/// Imports the module at `path` into `env`, binding every name it defines under its namespace,
+/// or only `names` if given. Errors point at the import at `span`.
+pub fn import(
+    path: &str,
+    names: Option<&[String]>,
+    env: &Environment,
+    span: Span,
+) -> Result<(), EvalErr> {
+    if Path::new(path).extension().is_some_and(|ext| ext == "cnf") {
+        let name = namespace(path);
+        if let Some(other) = names.into_iter().flatten().find(|other| **other != name) {
+            return Err(EvalErr::NotExported(path.to_string(), other.clone(), span));
+        }
+        env.add(name, load_dimacs(path, env, span)?);
+        return Ok(());
+    }
+    let module = load(path, env, span)?;
+    let exports: Vec<(String, _)> = module
+        .bindings()
+        .into_iter()
+        .filter(|(name, _)| !name.contains('/'))
+        .collect();
+    let Some(names) = names else {
+        let namespace = namespace(path);
+        for (name, value) in exports {
+            env.add(format!("{namespace}/{name}"), value);
+        }
+        return Ok(());
+    };
+    for name in names {
+        let Some((_, value)) = exports.iter().find(|(export, _)| export == name) else {
+            return Err(EvalErr::NotExported(path.to_string(), name.clone(), span));
+        };
+        env.add(name.clone(), value.clone());
+    }
+    Ok(())
+}
+
+/// Evaluates the file at `path`, relative to the file `env` was loaded from, and returns the
+/// environment holding its definitions.
+fn load(path: &str, env: &Environment, span: Span) -> Result<Environment, EvalErr> {
+    let mut imports = env.imports();
+    let (file, source) = read(path, env, span)?;
+    if let Some(start) = imports.iter().position(|import| *import == file) {
+        let cycle = imports[start..].iter().chain([&file]);
+        let cycle = cycle.map(|file| file.display().to_string()).collect();
+        return Err(EvalErr::ImportCycle(cycle, span));
+    }
+    imports.push(file);
+    let module = env.module(imports);
+    eval_str(&source, &module)
+        .map_err(|e| EvalErr::InModule(path.to_string(), Box::new(e), span))?;
+    Ok(module)
+}
+
+/// Reads the DIMACS file at `path` into a lambda of its variables.
+fn load_dimacs(path: &str, env: &Environment, span: Span) -> Result<Value, EvalErr> {
+    let (_, source) = read(path, env, span)?;
+    let dimacs = dimacs::parse(&source)
+        .map_err(|e| EvalErr::InModule(path.to_string(), Box::new(e.into()), span))?;
+    Ok(dimacs.lambda(env))
+}
+
+/// Reads the file at `path`, relative to the file `env` was loaded from, and returns its
+/// canonical path with its contents.
+fn read(path: &str, env: &Environment, span: Span) -> Result<(PathBuf, String), EvalErr> {
+    let failed = |e: std::io::Error| EvalErr::ImportFailed(path.to_string(), e.to_string(), span);
+    let file = match env.imports().last().and_then(|file| file.parent()) {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+    let file = fs::canonicalize(file).map_err(failed)?;
+    let source = fs::read_to_string(&file).map_err(failed)?;
+    Ok((file, source))
+}
+
+/// The namespace of the module at `path`: its file name without the extension.
+fn namespace(path: &str) -> String {
+    let path = Path::new(path);
+    path.file_stem()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluator::Value, test_util::TestResult};
+
+    /// The message of the evaluation error wrapped in `e`.
+    fn cause(e: &crate::Error) -> String {
+        use std::error::Error as _;
+        e.source().unwrap().to_string()
+    }
+
+    /// Writes `files` into a new directory named after `test` and returns its path.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lip-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, source) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn import_binds_namespaced_names() -> TestResult {
+        let dir = write_files(
+            "namespaced",
+            &[
+                ("main.lip", "(import \"lib/adders.lip\")"),
+                (
+                    "lib/adders.lip",
+                    "(import \"gates.lip\")\n(def half-add (lambda (a b) (gates/both a b)))",
+                ),
+                ("lib/gates.lip", "(def both (lambda (a b) (& a b)))"),
+            ],
+        );
+        let env = Environment::default().module(vec![dir.join("main.lip")]);
+        eval_str("(import \"lib/adders.lip\")", &env)?;
+        assert_eq!(
+            Some(Value::Bool(true)),
+            eval_str("(adders/half-add T T)", &env)?
+        );
+        // Names the module imported itself are not passed on.
+        assert!(env.get("adders/gates/both").is_none());
+        assert!(env.get("half-add").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn import_list_binds_only_listed_names() -> TestResult {
+        let dir = write_files(
+            "selective",
+            &[("gates.lip", "(def both &)\n(def either |)")],
+        );
+        let env = Environment::default().module(vec![dir.join("main.lip")]);
+        eval_str("(import \"gates.lip\" (either))", &env)?;
+        assert_eq!(Some(Value::Bool(true)), eval_str("(either F T)", &env)?);
+        assert!(env.get("both").is_none());
+        assert!(env.get("gates/either").is_none());
+        let e = eval_str("(import \"gates.lip\" (both nand-ish))", &env).unwrap_err();
+        assert_eq!("`gates.lip` does not define `nand-ish`", cause(&e));
+        Ok(())
+    }
+
+    #[test]
+    fn import_cycle_fails() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("a.lip", "(import \"b.lip\")"),
+                ("b.lip", "(def x T)\n(import \"a.lip\")"),
+            ],
+        );
+        let env = Environment::default().module(vec![dir.join("a.lip")]);
+        let e = eval_str("(import \"b.lip\")", &env).unwrap_err();
+        let (a, b) = (dir.join("a.lip"), dir.join("b.lip"));
+        assert_eq!(
+            format!(
+                "in `b.lip` at 2:1: failed to evaluate: import cycle: {} -> {} -> {}",
+                a.display(),
+                b.display(),
+                a.display()
+            ),
+            cause(&e)
+        );
+    }
+
+    #[test]
+    fn import_reports_errors_in_module() {
+        let dir = write_files("module-error", &[("bad.lip", "(def x T)\n(& x y)")]);
+        let env = Environment::default().module(vec![dir.join("main.lip")]);
+        let e = eval_str("(import \"bad.lip\")", &env).unwrap_err();
+        assert_eq!(
+            "in `bad.lip` at 2:6: failed to evaluate: `y` is not defined",
+            cause(&e)
+        );
+        let e = eval_str("(import \"missing.lip\")", &env).unwrap_err();
+        assert!(cause(&e).starts_with("cannot read `missing.lip`: "));
+    }
+
+    #[test]
+    fn import_reads_dimacs() -> TestResult {
+        let dir = write_files(
+            "dimacs",
+            &[
+                ("bench/uf3.cnf", "c 3 c\np cnf 3 2\n1 -3 0\n2 3 0\n"),
+                ("bench/bad.cnf", "p cnf 3 2\n1 -3 0\n"),
+            ],
+        );
+        let env = Environment::default().module(vec![dir.join("main.lip")]);
+        eval_str("(import \"bench/uf3.cnf\")", &env)?;
+        assert_eq!(Some(Value::Bool(true)), eval_str("(uf3 T F T)", &env)?);
+        assert_eq!(Some(Value::Bool(false)), eval_str("(uf3 F F T)", &env)?);
+        eval_str("(import \"bench/uf3.cnf\" (uf3))", &env)?;
+        let e = eval_str("(import \"bench/uf3.cnf\" (c))", &env).unwrap_err();
+        assert_eq!("`bench/uf3.cnf` does not define `c`", cause(&e));
+        let e = eval_str("(import \"bench/bad.cnf\")", &env).unwrap_err();
+        assert_eq!(
+            "in `bench/bad.cnf` at 1:9: failed to read DIMACS: the problem line declares 2 \
+             clause(s), found 1",
+            cause(&e)
+        );
+        Ok(())
+    }
+}
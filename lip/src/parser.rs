@@ -1,17 +1,67 @@
-use itertools::Itertools;
+use crate::bits::Bits;
+use crate::span::Span;
+use crate::tokenizer::{Token, TokenKind};
 
-use crate::tokenizer::Token;
-
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParserErr {
-    Parse(String),
+    /// The input ended where an expression was expected.
+    UnexpectedEnd(Span),
+    /// A token that cannot appear at this position.
+    UnexpectedToken(TokenKind, Span),
+    /// A `(` without the matching `)`. The first field names the unclosed form.
+    UnclosedParen(&'static str, Span),
+    /// A special form that does not match its syntax. The first field is the expected syntax.
+    MalformedForm(&'static str, Span),
+    /// A token other than an identifier where a name is expected.
+    ExpectedIdentifier(TokenKind, Span),
+    /// A reserved word such as `if` where a name is expected.
+    ReservedWord(TokenKind, Span),
+    /// Tokens left over after a complete expression.
+    TrailingTokens(Span),
+    /// A `(` nested more than [`NESTING_LIMIT`] deep.
+    TooDeep(Span),
+}
+
+impl ParserErr {
+    pub fn span(&self) -> Span {
+        match self {
+            ParserErr::UnexpectedEnd(span)
+            | ParserErr::UnexpectedToken(_, span)
+            | ParserErr::UnclosedParen(_, span)
+            | ParserErr::MalformedForm(_, span)
+            | ParserErr::ExpectedIdentifier(_, span)
+            | ParserErr::ReservedWord(_, span)
+            | ParserErr::TrailingTokens(span)
+            | ParserErr::TooDeep(span) => *span,
+        }
+    }
 }
 
 impl std::error::Error for ParserErr {}
 
 impl std::fmt::Display for ParserErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "failed to parse")
+        match self {
+            ParserErr::UnexpectedEnd(_) => write!(f, "unexpected end of input"),
+            ParserErr::UnexpectedToken(token, _) => write!(f, "unexpected token `{token}`"),
+            ParserErr::UnclosedParen(form, _) => write!(f, "{form} is not closed with `)`"),
+            ParserErr::MalformedForm(syntax, _) => {
+                write!(f, "malformed expression, expected `{syntax}`")
+            }
+            ParserErr::ExpectedIdentifier(token, _) => {
+                write!(f, "expected an identifier, found `{token}`")
+            }
+            ParserErr::ReservedWord(token, _) => {
+                write!(
+                    f,
+                    "`{token}` is a reserved word and cannot be used as a name"
+                )
+            }
+            ParserErr::TrailingTokens(_) => write!(f, "unexpected tokens after the expression"),
+            ParserErr::TooDeep(_) => {
+                write!(f, "expressions are nested more than {NESTING_LIMIT} deep")
+            }
+        }
     }
 }
 
@@ -20,6 +70,31 @@ pub enum Operator {
     And,
     Or,
     Not,
+    Xor,
+    Implies,
+    Iff,
+    Nand,
+    Nor,
+}
+
+impl Operator {
+    /// The operators written as names rather than symbols. They are not reserved words: an
+    /// identifier that is not defined evaluates to the operator of that name, so definitions
+    /// can shadow them.
+    pub const NAMED: &'static [Operator] = &[
+        Operator::Xor,
+        Operator::Implies,
+        Operator::Iff,
+        Operator::Nand,
+        Operator::Nor,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMED
+            .iter()
+            .copied()
+            .find(|operator| operator.to_string() == name)
+    }
 }
 
 impl std::fmt::Display for Operator {
@@ -28,6 +103,11 @@ impl std::fmt::Display for Operator {
             Operator::And => write!(f, "&"),
             Operator::Or => write!(f, "|"),
             Operator::Not => write!(f, "^"),
+            Operator::Xor => write!(f, "xor"),
+            Operator::Implies => write!(f, "implies"),
+            Operator::Iff => write!(f, "iff"),
+            Operator::Nand => write!(f, "nand"),
+            Operator::Nor => write!(f, "nor"),
         }
     }
 }
@@ -49,188 +129,749 @@ impl If {
     }
 }
 
+/// Which of the `let` forms binds the variables, deciding where each value is evaluated.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LetKind {
+    /// `let`: every value is evaluated outside the new bindings.
+    Let,
+    /// `let*`: each value sees the bindings before it.
+    LetStar,
+    /// `letrec`: every value sees all the bindings, so local lambdas can call each other.
+    Letrec,
+}
+
+impl std::fmt::Display for LetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LetKind::Let => write!(f, "let"),
+            LetKind::LetStar => write!(f, "let*"),
+            LetKind::Letrec => write!(f, "letrec"),
+        }
+    }
+}
+
+/// What a value is bound to by a lambda parameter or a `let` binding.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Pattern {
+    Ident(String),
+    /// `(a (b c) . rest)` takes a list apart, binding its elements to the patterns in turn.
+    List(Params),
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Ident(ident) => write!(f, "{ident}"),
+            Pattern::List(Params {
+                required,
+                rest: Some(rest),
+            }) if required.is_empty() => write!(f, "(. {rest})"),
+            Pattern::List(params) => write!(f, "{params}"),
+        }
+    }
+}
+
+/// The parameters of a lambda: the required ones, then optionally one that collects the rest
+/// of the arguments into a list.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Params {
+    pub required: Vec<Pattern>,
+    pub rest: Option<String>,
+}
+
+impl Params {
+    pub fn new(required: Vec<Pattern>, rest: Option<String>) -> Self {
+        Self { required, rest }
+    }
+}
+
+impl std::fmt::Display for Params {
+    /// Prints `(a b)`, `(a b . rest)`, or just `rest` when there are no required parameters.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let (Some(rest), true) = (&self.rest, self.required.is_empty()) {
+            return write!(f, "{rest}");
+        }
+        write!(f, "(")?;
+        for (i, pattern) in self.required.iter().enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            write!(f, "{sep}{pattern}")?;
+        }
+        match &self.rest {
+            Some(rest) => write!(f, " . {rest})"),
+            None => write!(f, ")"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Expr {
+pub enum ExprKind {
     Bool(bool),
+    Bits(Bits),
+    Int(usize),
     Operator(Operator),
     Call(Box<Expr>, Vec<Expr>),
     If(If),
     Def(String, Box<Expr>),
-    Lambda(Vec<String>, Box<Expr>),
+    /// A macro definition: its name, its parameters and the template it expands to.
+    Defmacro(String, Params, Box<Expr>),
+    Lambda(Params, Box<Expr>),
+    /// Local bindings, in order, and the body they are visible in.
+    Let(LetKind, Vec<(Pattern, Expr)>, Box<Expr>),
+    /// `(test expr)` clauses, in order, and the `else` expression if there is one.
+    Cond(Vec<(Expr, Expr)>, Option<Box<Expr>>),
+    /// The path of a module and the names to import from it, or `None` to import every name
+    /// under the module's namespace.
+    Import(String, Option<Vec<String>>),
     Ident(String),
+    /// A malformed expression skipped by [`parse_program_recovering`].
+    Error,
+}
+
+/// An expression together with the source code it was parsed from.
+///
+/// Equality only compares the structure, so the same expression written in different places is
+/// considered equal.
+#[derive(Debug, Eq, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
 }
 
 impl std::fmt::Display for Expr {
+    /// Prints the expression on a single line as lip source code. See [`crate::printer`] for
+    /// multi-line output.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Expr::Bool(b) => write!(f, "{}", if *b { "T" } else { "F" }),
-            Expr::Operator(o) => write!(f, "{o}"),
-            Expr::Call(operator, operands) => {
-                write!(
-                    f,
-                    "({operator} {})",
-                    operands.iter().map(|o| o.to_string()).join(" ")
-                )
-            }
-            Expr::If(If { cond, then, other }) => write!(f, "(if {cond} {then} {other})"),
+        self.fmt_at(f, 0)
+    }
+}
 
-            Expr::Def(ident, expr) => write!(f, "({ident} := {expr})"),
-            Expr::Lambda(args, expr) => write!(f, "({}) -> {expr}", args.join(" ")),
-            Expr::Ident(ident) => write!(f, "{ident}"),
+impl Expr {
+    /// Prints the expression nested `depth` forms deep, with `...` in place of the forms nested
+    /// more than [`NESTING_LIMIT`] deep, such as those built by macros.
+    fn fmt_at(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        if depth > NESTING_LIMIT {
+            return write!(f, "...");
+        }
+        let depth = depth + 1;
+        match &self.kind {
+            ExprKind::Bool(b) => write!(f, "{}", if *b { "T" } else { "F" }),
+            ExprKind::Bits(bits) => write!(f, "{bits}"),
+            ExprKind::Int(int) => write!(f, "{int}"),
+            ExprKind::Operator(o) => write!(f, "{o}"),
+            ExprKind::Call(operator, operands) => {
+                write!(f, "(")?;
+                operator.fmt_at(f, depth)?;
+                for operand in operands {
+                    write!(f, " ")?;
+                    operand.fmt_at(f, depth)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::If(If { cond, then, other }) => {
+                write!(f, "(if")?;
+                for expr in [cond, then, other] {
+                    write!(f, " ")?;
+                    expr.fmt_at(f, depth)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Def(ident, expr) => {
+                write!(f, "(def {ident} ")?;
+                expr.fmt_at(f, depth)?;
+                write!(f, ")")
+            }
+            ExprKind::Defmacro(ident, params, template) => {
+                write!(f, "(defmacro {ident} {params} ")?;
+                template.fmt_at(f, depth)?;
+                write!(f, ")")
+            }
+            ExprKind::Lambda(params, expr) => {
+                write!(f, "(lambda {params} ")?;
+                expr.fmt_at(f, depth)?;
+                write!(f, ")")
+            }
+            ExprKind::Let(kind, bindings, body) => {
+                write!(f, "({kind} (")?;
+                for (i, (pattern, value)) in bindings.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { " " };
+                    write!(f, "{sep}({pattern} ")?;
+                    value.fmt_at(f, depth)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ") ")?;
+                body.fmt_at(f, depth)?;
+                write!(f, ")")
+            }
+            ExprKind::Cond(clauses, other) => {
+                write!(f, "(cond")?;
+                for (test, expr) in clauses {
+                    write!(f, " (")?;
+                    test.fmt_at(f, depth)?;
+                    write!(f, " ")?;
+                    expr.fmt_at(f, depth)?;
+                    write!(f, ")")?;
+                }
+                if let Some(other) = other {
+                    write!(f, " (else ")?;
+                    other.fmt_at(f, depth)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Import(path, names) => {
+                write!(f, "(import \"{path}\"")?;
+                if let Some(names) = names {
+                    write!(f, " ({})", names.join(" "))?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Ident(ident) => write!(f, "{ident}"),
+            ExprKind::Error => write!(f, "<error>"),
         }
     }
 }
 
+/// Parses exactly one expression, failing if any tokens are left over.
 pub fn parse(tokens: &[Token]) -> Result<Expr, ParserErr> {
-    let (expr, _) = parse_internal(tokens)?;
-    Ok(expr)
+    let (expr, cnt) = parse_internal(tokens)?;
+    match tokens.get(cnt..) {
+        Some([first, .., last]) => Err(ParserErr::TrailingTokens(first.span.to(last.span))),
+        Some([first]) => Err(ParserErr::TrailingTokens(first.span)),
+        _ => Ok(expr),
+    }
+}
+
+/// Parses a sequence of top-level expressions, such as the contents of a `.lip` file.
+pub fn parse_program(tokens: &[Token]) -> Result<Vec<Expr>, ParserErr> {
+    let mut parser = Parser::new(tokens);
+    let mut exprs = Vec::new();
+    while parser.peek().is_some() {
+        exprs.push(parser.expr()?);
+    }
+    Ok(exprs)
+}
+
+/// Parses a sequence of top-level expressions without stopping at the first error.
+///
+/// A malformed expression is reported and skipped up to its matching `)`, leaving an
+/// [`ExprKind::Error`] in its place, so the returned expressions form a partial AST and the
+/// errors cover every problem found in `tokens`.
+pub fn parse_program_recovering(tokens: &[Token]) -> (Vec<Expr>, Vec<ParserErr>) {
+    let mut parser = Parser::new(tokens);
+    parser.recover = true;
+    let mut exprs = Vec::new();
+    while parser.peek().is_some() {
+        match parser.expr() {
+            Ok(expr) => exprs.push(expr),
+            Err(e) => parser.errors.push(e),
+        }
+    }
+    (exprs, parser.errors)
 }
 
+/// Parses the expression at the beginning of `tokens` and returns it with the number of tokens
+/// it consumed.
 fn parse_internal(tokens: &[Token]) -> Result<(Expr, usize), ParserErr> {
-    if tokens.is_empty() {
-        return Err(ParserErr::Parse("no token".to_string()));
-    }
-    let first = &tokens[0];
-    if first != &Token::Lparen {
-        return match first {
-            Token::True => Ok((Expr::Bool(true), 1)),
-            Token::False => Ok((Expr::Bool(false), 1)),
-            Token::And => Ok((Expr::Operator(Operator::And), 1)),
-            Token::Or => Ok((Expr::Operator(Operator::Or), 1)),
-            Token::Not => Ok((Expr::Operator(Operator::Not), 1)),
-            Token::Ident(ident) => Ok((Expr::Ident(ident.to_string()), 1)),
-            _ => Err(ParserErr::Parse(format!("invalid token `{first}`"))),
+    let mut parser = Parser::new(tokens);
+    let expr = parser.expr()?;
+    Ok((expr, parser.pos))
+}
+
+/// The name and the syntax of a parenthesized form, used to describe errors.
+struct Form {
+    name: &'static str,
+    syntax: &'static str,
+}
+
+const CALL: Form = Form {
+    name: "call",
+    syntax: "(operator operands...)",
+};
+
+const IF: Form = Form {
+    name: "if expression",
+    syntax: "(if cond then else)",
+};
+
+const DEF: Form = Form {
+    name: "def expression",
+    syntax: "(def identifier expr)",
+};
+
+const DEFMACRO: Form = Form {
+    name: "defmacro expression",
+    syntax: "(defmacro identifier (params...) template)",
+};
+
+const LAMBDA: Form = Form {
+    name: "lambda",
+    syntax: "(lambda (params...) body)",
+};
+
+const PARAMS: Form = Form {
+    name: "parameter list",
+    syntax: "(params... [. rest])",
+};
+
+const PATTERN: Form = Form {
+    name: "pattern",
+    syntax: "(pattern... [. rest])",
+};
+
+const LET: Form = Form {
+    name: "let expression",
+    syntax: "(let ((pattern expr)...) body)",
+};
+
+const LET_STAR: Form = Form {
+    name: "let* expression",
+    syntax: "(let* ((pattern expr)...) body)",
+};
+
+const LETREC: Form = Form {
+    name: "letrec expression",
+    syntax: "(letrec ((pattern expr)...) body)",
+};
+
+const COND: Form = Form {
+    name: "cond expression",
+    syntax: "(cond (test expr)... (else expr))",
+};
+
+const CLAUSE: Form = Form {
+    name: "cond clause",
+    syntax: "(test expr)",
+};
+
+const ELSE: Form = Form {
+    name: "else clause",
+    syntax: "(else expr)",
+};
+
+const IMPORT: Form = Form {
+    name: "import expression",
+    syntax: "(import \"path\" [(names...)])",
+};
+
+const NAMES: Form = Form {
+    name: "name list",
+    syntax: "(names...)",
+};
+
+const BINDINGS: Form = Form {
+    name: "binding list",
+    syntax: "((pattern expr)...)",
+};
+
+const BINDING: Form = Form {
+    name: "binding",
+    syntax: "(pattern expr)",
+};
+
+/// The deepest that parentheses may nest. The parser and the functions walking the parsed
+/// expressions recurse once per level, so this bounds the stack they use.
+pub const NESTING_LIMIT: usize = 256;
+
+/// A cursor over tokens. Every access is bounds-checked, so no token sequence makes it panic.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    /// The number of `(` of enclosing expressions and patterns.
+    depth: usize,
+    /// Whether to record errors in `errors` and carry on instead of failing.
+    recover: bool,
+    errors: Vec<ParserErr>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            depth: 0,
+            recover: false,
+            errors: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParserErr> {
+        let start = self.pos;
+        match self.expr_strict() {
+            Err(e) if self.recover && start < self.tokens.len() => {
+                self.errors.push(e);
+                let span = self.skip_from(start);
+                Ok(Expr::new(ExprKind::Error, span))
+            }
+            result => result,
+        }
+    }
+
+    /// Rewinds to `start` and skips the token there, or everything up to the matching `)` if it
+    /// is a `(`. Returns the span of the skipped tokens.
+    fn skip_from(&mut self, start: usize) -> Span {
+        self.pos = start;
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token.kind {
+                TokenKind::Lparen => depth += 1,
+                TokenKind::Rparen => depth -= 1,
+                _ => (),
+            }
+            if depth <= 0 {
+                break;
+            }
+        }
+        let last = self.tokens[self.pos - 1].span;
+        self.tokens[start].span.to(last)
+    }
+
+    fn expr_strict(&mut self) -> Result<Expr, ParserErr> {
+        let Some(first) = self.next() else {
+            return Err(ParserErr::UnexpectedEnd(Span::default()));
         };
+        let kind = match &first.kind {
+            TokenKind::True => ExprKind::Bool(true),
+            TokenKind::False => ExprKind::Bool(false),
+            TokenKind::Bits(bits) => ExprKind::Bits(bits.clone()),
+            TokenKind::Int(int) => ExprKind::Int(*int),
+            TokenKind::And => ExprKind::Operator(Operator::And),
+            TokenKind::Or => ExprKind::Operator(Operator::Or),
+            TokenKind::Not => ExprKind::Operator(Operator::Not),
+            TokenKind::Ident(ident) => ExprKind::Ident(ident.to_string()),
+            TokenKind::Lparen => return self.nested(first, |parser| parser.form(first)),
+            kind => return Err(ParserErr::UnexpectedToken(kind.clone(), first.span)),
+        };
+        Ok(Expr::new(kind, first.span))
     }
-    if tokens.len() < 2 {
-        return Err(ParserErr::Parse("invalid expression".to_string()));
+
+    /// Parses what `open` opens with `parse`, one level deeper, failing instead if that is
+    /// deeper than [`NESTING_LIMIT`].
+    fn nested<T>(
+        &mut self,
+        open: &Token,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParserErr>,
+    ) -> Result<T, ParserErr> {
+        if self.depth >= NESTING_LIMIT {
+            return Err(ParserErr::TooDeep(open.span));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
-    if tokens[1] == Token::If {
-        return parse_if(tokens);
+
+    /// Parses the form opened by `open`, which has already been consumed.
+    fn form(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let Some(token) = self.peek() else {
+            return Err(ParserErr::UnclosedParen("expression", open.span));
+        };
+        match token.kind {
+            TokenKind::If => {
+                self.next();
+                self.parse_if(open)
+            }
+            TokenKind::Def => {
+                self.next();
+                self.parse_def(open)
+            }
+            TokenKind::Defmacro => {
+                self.next();
+                self.parse_defmacro(open)
+            }
+            TokenKind::Lambda => {
+                self.next();
+                self.parse_lambda(open)
+            }
+            TokenKind::Let => {
+                self.next();
+                self.parse_let(open, LetKind::Let)
+            }
+            TokenKind::LetStar => {
+                self.next();
+                self.parse_let(open, LetKind::LetStar)
+            }
+            TokenKind::Letrec => {
+                self.next();
+                self.parse_let(open, LetKind::Letrec)
+            }
+            TokenKind::Cond => {
+                self.next();
+                self.parse_cond(open)
+            }
+            TokenKind::Import => {
+                self.next();
+                self.parse_import(open)
+            }
+            _ => self.parse_call(open),
+        }
     }
-    if tokens[1] == Token::Def {
-        return parse_def(tokens);
+
+    /// Parses an expression inside `form`, which must not be closed yet.
+    fn operand(&mut self, open: &Token, form: &Form) -> Result<Expr, ParserErr> {
+        match self.peek() {
+            None => Err(ParserErr::UnclosedParen(form.name, open.span)),
+            Some(token) if token.kind == TokenKind::Rparen => Err(ParserErr::MalformedForm(
+                form.syntax,
+                open.span.to(token.span),
+            )),
+            Some(_) => self.expr(),
+        }
     }
-    if tokens[1] == Token::Lambda {
-        return parse_lambda(tokens);
+
+    /// Parses an identifier inside `form`.
+    fn ident(&mut self, open: &Token, form: &Form) -> Result<String, ParserErr> {
+        match self.next() {
+            None => Err(ParserErr::UnclosedParen(form.name, open.span)),
+            Some(Token {
+                kind: TokenKind::Ident(ident),
+                ..
+            }) => Ok(ident.to_string()),
+            Some(Token {
+                kind: TokenKind::Rparen,
+                span,
+            }) => Err(ParserErr::MalformedForm(form.syntax, open.span.to(*span))),
+            Some(token) if token.kind.is_reserved() => {
+                Err(ParserErr::ReservedWord(token.kind.clone(), token.span))
+            }
+            Some(token) => Err(ParserErr::ExpectedIdentifier(
+                token.kind.clone(),
+                token.span,
+            )),
+        }
     }
-    parse_call(tokens)
-}
 
-fn parse_call(tokens: &[Token]) -> Result<(Expr, usize), ParserErr> {
-    let len = tokens.len();
-    if len < 3 {
-        return Err(ParserErr::Parse("call is too short".to_string()));
+    /// Consumes the `(` opening a list inside `form`, such as the parameters of a lambda.
+    fn open_list(&mut self, open: &Token, form: &Form) -> Result<&'a Token, ParserErr> {
+        match self.next() {
+            None => Err(ParserErr::UnclosedParen(form.name, open.span)),
+            Some(token) if token.kind == TokenKind::Lparen => Ok(token),
+            Some(token) if token.kind == TokenKind::Rparen => Err(ParserErr::MalformedForm(
+                form.syntax,
+                open.span.to(token.span),
+            )),
+            Some(token) => Err(ParserErr::MalformedForm(form.syntax, token.span)),
+        }
     }
 
-    if tokens[0] != Token::Lparen {
-        return Err(ParserErr::Parse(format!(
-            "call must start with `(`, not `{:?}`",
-            tokens[0]
-        )));
+    /// Consumes the `)` closing `form` and returns the span of the whole form.
+    fn close(&mut self, open: &Token, form: &Form) -> Result<Span, ParserErr> {
+        match self.next() {
+            None => Err(ParserErr::UnclosedParen(form.name, open.span)),
+            Some(token) if token.kind == TokenKind::Rparen => Ok(open.span.to(token.span)),
+            Some(token) => Err(ParserErr::MalformedForm(form.syntax, token.span)),
+        }
     }
-    let (operator, cnt) = parse_internal(&tokens[1..])?;
-    let mut operands = Vec::new();
-    let mut p = cnt + 1;
-    while p < len && tokens[p] != Token::Rparen {
-        let (expr, cnt) = parse_internal(&tokens[p..])?;
-        operands.push(expr);
-        p += cnt;
+
+    fn parse_call(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let operator = self.operand(open, &CALL)?;
+        let mut operands = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(ParserErr::UnclosedParen(CALL.name, open.span)),
+                Some(token) if token.kind == TokenKind::Rparen => break,
+                Some(_) => operands.push(self.expr()?),
+            }
+        }
+        let span = self.close(open, &CALL)?;
+        Ok(Expr::new(
+            ExprKind::Call(Box::new(operator), operands),
+            span,
+        ))
     }
-    if p >= len || tokens[p] != Token::Rparen {
-        return Err(ParserErr::Parse("call is not closed with `)`".to_string()));
+
+    fn parse_if(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let cond = self.operand(open, &IF)?;
+        let then = self.operand(open, &IF)?;
+        let other = self.operand(open, &IF)?;
+        let span = self.close(open, &IF)?;
+        Ok(Expr::new(ExprKind::If(If::new(cond, then, other)), span))
     }
-    Ok((Expr::Call(Box::new(operator), operands), p + 1))
-}
 
-fn parse_if(tokens: &[Token]) -> Result<(Expr, usize), ParserErr> {
-    let len = tokens.len();
-    if len < 6 {
-        return Err(ParserErr::Parse("if expression is too short".to_string()));
-    }
-    if tokens[0] != Token::Lparen || tokens[1] != Token::If {
-        return Err(ParserErr::Parse(format!(
-            "if expression must start with `(if`, not `{:?} {:?}`",
-            tokens[0], tokens[1]
-        )));
-    }
-    let mut p = 2;
-    let (cond, cnt) = parse_internal(&tokens[p..])?;
-    p += cnt;
-    let (then, cnt) = parse_internal(&tokens[p..])?;
-    p += cnt;
-    let (other, cnt) = parse_internal(&tokens[p..])?;
-    p += cnt;
-    if tokens[p] != Token::Rparen {
-        return Err(ParserErr::Parse(
-            "if expression is not closed with `)`".to_string(),
-        ));
+    fn parse_def(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let ident = self.ident(open, &DEF)?;
+        let expr = self.operand(open, &DEF)?;
+        let span = self.close(open, &DEF)?;
+        Ok(Expr::new(ExprKind::Def(ident, Box::new(expr)), span))
     }
-    Ok((Expr::If(If::new(cond, then, other)), p + 1))
-}
 
-fn parse_def(tokens: &[Token]) -> Result<(Expr, usize), ParserErr> {
-    let len = tokens.len();
-    if len < 5 {
-        return Err(ParserErr::Parse("def expression is too short".to_string()));
-    }
-    if tokens[0] != Token::Lparen
-        || tokens[1] != Token::Def
-        || !matches!(tokens[2], Token::Ident(_))
-    {
-        return Err(ParserErr::Parse(format!(
-            "def expression must start with `(def identifier`, not `{:?} {:?} {:?}`",
-            tokens[0], tokens[1], tokens[2]
-        )));
-    }
-    let ident = match tokens[2] {
-        Token::Ident(ref ident) => ident,
-        _ => unreachable!(),
-    };
-    let (expr, cnt) = parse_internal(&tokens[3..])?;
-    if tokens[cnt + 3] != Token::Rparen {
-        return Err(ParserErr::Parse(
-            "def expression is not closed with `)`".to_string(),
-        ));
+    fn parse_defmacro(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let ident = self.ident(open, &DEFMACRO)?;
+        let params = self.params(open, &DEFMACRO)?;
+        let template = self.operand(open, &DEFMACRO)?;
+        let span = self.close(open, &DEFMACRO)?;
+        Ok(Expr::new(
+            ExprKind::Defmacro(ident, params, Box::new(template)),
+            span,
+        ))
     }
-    Ok((Expr::Def(ident.to_string(), Box::new(expr)), cnt + 4))
-}
 
-fn parse_lambda(tokens: &[Token]) -> Result<(Expr, usize), ParserErr> {
-    let len = tokens.len();
-    if len < 6 {
-        return Err(ParserErr::Parse("lambda is too short".to_string()));
-    }
-    if tokens[0] != Token::Lparen || tokens[1] != Token::Lambda || tokens[2] != Token::Lparen {
-        return Err(ParserErr::Parse(format!(
-            "lambda must start with `(lambda (`, not `{:?} {:?} {:?}`",
-            tokens[0], tokens[1], tokens[2]
-        )));
-    }
-    let mut p = 3;
-    let mut args = Vec::new();
-    while p < len && tokens[p] != Token::Rparen {
-        match &tokens[p] {
-            Token::Ident(arg) => args.push(arg),
-            token => return Err(ParserErr::Parse(format!("`{token}` is not an identifier"))),
-        }
-        p += 1;
-    }
-    if tokens[p] != Token::Rparen {
-        return Err(ParserErr::Parse(
-            "argument list of lambda is not closed with `)`".to_string(),
-        ));
+    fn parse_lambda(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let params = self.params(open, &LAMBDA)?;
+        let expr = self.operand(open, &LAMBDA)?;
+        let span = self.close(open, &LAMBDA)?;
+        Ok(Expr::new(ExprKind::Lambda(params, Box::new(expr)), span))
     }
-    let (expr, cnt) = parse_internal(&tokens[p + 1..])?;
-    if tokens[p + cnt] != Token::Rparen {
-        return Err(ParserErr::Parse(
-            "lambda is not closed with `)`".to_string(),
-        ));
+
+    /// Parses `(a b)`, `(a b . rest)` or a lone `rest` inside `form`.
+    fn params(&mut self, open: &Token, form: &Form) -> Result<Params, ParserErr> {
+        if let Some(Token {
+            kind: TokenKind::Ident(rest),
+            ..
+        }) = self.peek()
+        {
+            self.next();
+            return Ok(Params::new(Vec::new(), Some(rest.to_string())));
+        }
+        let params_open = self.open_list(open, form)?;
+        self.pattern_list(params_open, &PARAMS)
+    }
+
+    /// Parses an identifier, or a list of patterns, inside `form`.
+    fn pattern(&mut self, open: &Token, form: &Form) -> Result<Pattern, ParserErr> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Lparen => {
+                self.next();
+                self.nested(token, |parser| parser.pattern_list(token, &PATTERN))
+                    .map(Pattern::List)
+            }
+            _ => self.ident(open, form).map(Pattern::Ident),
+        }
+    }
+
+    /// Parses the patterns in the `form` opened by `list_open`, optionally followed by
+    /// `. rest`, up to the closing `)`.
+    fn pattern_list(&mut self, list_open: &Token, form: &Form) -> Result<Params, ParserErr> {
+        let mut params = Params::default();
+        loop {
+            match self.peek() {
+                None => return Err(ParserErr::UnclosedParen(form.name, list_open.span)),
+                Some(token) if token.kind == TokenKind::Rparen => break,
+                Some(token) if token.kind == TokenKind::Dot => {
+                    self.next();
+                    params.rest = Some(self.ident(list_open, form)?);
+                    break;
+                }
+                Some(_) => params.required.push(self.pattern(list_open, form)?),
+            }
+        }
+        self.close(list_open, form)?;
+        Ok(params)
+    }
+
+    fn parse_let(&mut self, open: &Token, kind: LetKind) -> Result<Expr, ParserErr> {
+        let form = match kind {
+            LetKind::Let => &LET,
+            LetKind::LetStar => &LET_STAR,
+            LetKind::Letrec => &LETREC,
+        };
+        let bindings_open = self.open_list(open, form)?;
+        let mut bindings = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(ParserErr::UnclosedParen(BINDINGS.name, bindings_open.span)),
+                Some(token) if token.kind == TokenKind::Rparen => break,
+                Some(_) => {
+                    let binding_open = self.open_list(bindings_open, &BINDINGS)?;
+                    let pattern = self.pattern(binding_open, &BINDING)?;
+                    let value = self.operand(binding_open, &BINDING)?;
+                    self.close(binding_open, &BINDING)?;
+                    bindings.push((pattern, value));
+                }
+            }
+        }
+        self.close(bindings_open, &BINDINGS)?;
+        let body = self.operand(open, form)?;
+        let span = self.close(open, form)?;
+        Ok(Expr::new(
+            ExprKind::Let(kind, bindings, Box::new(body)),
+            span,
+        ))
+    }
+
+    fn parse_cond(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let mut clauses = Vec::new();
+        let mut other = None;
+        loop {
+            match self.peek() {
+                None => return Err(ParserErr::UnclosedParen(COND.name, open.span)),
+                Some(token) if token.kind == TokenKind::Rparen => break,
+                Some(_) => {
+                    let clause_open = self.open_list(open, &COND)?;
+                    if self
+                        .peek()
+                        .is_some_and(|token| token.kind == TokenKind::Else)
+                    {
+                        self.next();
+                        let expr = self.operand(clause_open, &ELSE)?;
+                        self.close(clause_open, &ELSE)?;
+                        // `else` must be the last clause.
+                        other = Some(Box::new(expr));
+                        break;
+                    }
+                    let test = self.operand(clause_open, &CLAUSE)?;
+                    let expr = self.operand(clause_open, &CLAUSE)?;
+                    self.close(clause_open, &CLAUSE)?;
+                    clauses.push((test, expr));
+                }
+            }
+        }
+        let span = self.close(open, &COND)?;
+        Ok(Expr::new(ExprKind::Cond(clauses, other), span))
+    }
+
+    fn parse_import(&mut self, open: &Token) -> Result<Expr, ParserErr> {
+        let path = match self.next() {
+            None => return Err(ParserErr::UnclosedParen(IMPORT.name, open.span)),
+            Some(Token {
+                kind: TokenKind::Str(path),
+                ..
+            }) => path.to_string(),
+            Some(token) if token.kind == TokenKind::Rparen => {
+                return Err(ParserErr::MalformedForm(
+                    IMPORT.syntax,
+                    open.span.to(token.span),
+                ))
+            }
+            Some(token) => return Err(ParserErr::MalformedForm(IMPORT.syntax, token.span)),
+        };
+        let mut names = None;
+        if let Some(names_open) = self.peek().filter(|token| token.kind == TokenKind::Lparen) {
+            self.next();
+            let mut list = Vec::new();
+            while self
+                .peek()
+                .is_some_and(|token| token.kind != TokenKind::Rparen)
+            {
+                list.push(self.ident(names_open, &NAMES)?);
+            }
+            self.close(names_open, &NAMES)?;
+            names = Some(list);
+        }
+        let span = self.close(open, &IMPORT)?;
+        Ok(Expr::new(ExprKind::Import(path, names), span))
     }
-    Ok((
-        Expr::Lambda(args.into_iter().cloned().collect(), Box::new(expr)),
-        p + cnt + 2,
-    ))
 }
 
 #[cfg(test)]
@@ -238,39 +879,81 @@ pub mod tests {
     use super::*;
     use crate::{test_util::TestResult, tokenizer};
 
+    pub fn expr(kind: ExprKind) -> Expr {
+        Expr::new(kind, Span::default())
+    }
+
+    pub fn boolean(b: bool) -> Expr {
+        expr(ExprKind::Bool(b))
+    }
+
+    pub fn operator(operator: Operator) -> Expr {
+        expr(ExprKind::Operator(operator))
+    }
+
     pub fn and(exprs: Vec<Expr>) -> Expr {
-        call(Expr::Operator(Operator::And), exprs)
+        call(operator(Operator::And), exprs)
     }
 
     pub fn or(exprs: Vec<Expr>) -> Expr {
-        call(Expr::Operator(Operator::Or), exprs)
+        call(operator(Operator::Or), exprs)
     }
 
     pub fn not(exprs: Vec<Expr>) -> Expr {
-        call(Expr::Operator(Operator::Not), exprs)
+        call(operator(Operator::Not), exprs)
     }
 
     pub fn if_expr(cond: Expr, then: Expr, other: Expr) -> Expr {
-        Expr::If(If::new(cond, then, other))
+        expr(ExprKind::If(If::new(cond, then, other)))
     }
 
     pub fn call(operator: Expr, operands: Vec<Expr>) -> Expr {
-        Expr::Call(Box::new(operator), operands)
+        expr(ExprKind::Call(Box::new(operator), operands))
+    }
+
+    pub fn def(ident: &str, value: Expr) -> Expr {
+        expr(ExprKind::Def(ident.to_string(), Box::new(value)))
+    }
+
+    pub fn defmacro(ident: &str, args: &[&str], template: Expr) -> Expr {
+        expr(ExprKind::Defmacro(
+            ident.to_string(),
+            params(args, None),
+            Box::new(template),
+        ))
     }
 
-    pub fn def(ident: &str, expr: Expr) -> Expr {
-        Expr::Def(ident.to_string(), Box::new(expr))
+    pub fn lambda(args: &[&str], body: Expr) -> Expr {
+        lambda_rest(args, None, body)
     }
 
-    pub fn lambda(args: &[&str], expr: Expr) -> Expr {
-        Expr::Lambda(
-            args.iter().map(|arg| arg.to_string()).collect(),
-            Box::new(expr),
+    pub fn lambda_rest(args: &[&str], rest: Option<&str>, body: Expr) -> Expr {
+        expr(ExprKind::Lambda(params(args, rest), Box::new(body)))
+    }
+
+    pub fn params(args: &[&str], rest: Option<&str>) -> Params {
+        Params::new(
+            args.iter()
+                .map(|arg| Pattern::Ident(arg.to_string()))
+                .collect(),
+            rest.map(str::to_string),
         )
     }
 
+    pub fn let_expr(kind: LetKind, bindings: &[(&str, Expr)], body: Expr) -> Expr {
+        let bindings = bindings
+            .iter()
+            .map(|(ident, value)| (Pattern::Ident(ident.to_string()), value.clone()))
+            .collect();
+        expr(ExprKind::Let(kind, bindings, Box::new(body)))
+    }
+
+    pub fn cond(clauses: Vec<(Expr, Expr)>, other: Option<Expr>) -> Expr {
+        expr(ExprKind::Cond(clauses, other.map(Box::new)))
+    }
+
     pub fn ident(ident: &str) -> Expr {
-        Expr::Ident(ident.to_string())
+        expr(ExprKind::Ident(ident.to_string()))
     }
 
     #[test]
@@ -278,13 +961,31 @@ pub mod tests {
         let tokens = tokenizer::tokenize("T")?;
         let (expr, cnt) = parse_internal(&tokens)?;
         assert_eq!(tokens.len(), cnt);
-        assert_eq!(Expr::Bool(true), expr);
+        assert_eq!(boolean(true), expr);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_literals_succeed() -> TestResult {
+        let tokens = tokenizer::tokenize("(slice #b0110 2 1)")?;
+        let bits = Bits::from_digits("0110").unwrap();
+        assert_eq!(
+            call(
+                ident("slice"),
+                vec![
+                    expr(ExprKind::Bits(bits)),
+                    expr(ExprKind::Int(2)),
+                    expr(ExprKind::Int(1))
+                ]
+            ),
+            parse(&tokens)?
+        );
         Ok(())
     }
 
     #[test]
     fn parse_operator_succeed() -> TestResult {
-        for (str, operator) in [
+        for (str, o) in [
             ("&", Operator::And),
             ("|", Operator::Or),
             ("^", Operator::Not),
@@ -292,8 +993,14 @@ pub mod tests {
             let tokens = tokenizer::tokenize(str)?;
             let (expr, cnt) = parse_internal(&tokens)?;
             assert_eq!(tokens.len(), cnt);
-            assert_eq!(Expr::Operator(operator), expr);
+            assert_eq!(operator(o), expr);
+        }
+        for o in Operator::NAMED {
+            assert_eq!(Some(*o), Operator::from_name(&o.to_string()));
+            let tokens = tokenizer::tokenize(&o.to_string())?;
+            assert_eq!(ident(&o.to_string()), parse(&tokens)?);
         }
+        assert_eq!(None, Operator::from_name("&"));
         Ok(())
     }
 
@@ -304,9 +1011,9 @@ pub mod tests {
         assert_eq!(tokens.len(), cnt);
         assert_eq!(
             and(vec![
-                Expr::Bool(true),
-                or(vec![Expr::Bool(false), Expr::Bool(false), Expr::Bool(true)]),
-                not(vec![Expr::Bool(true)])
+                boolean(true),
+                or(vec![boolean(false), boolean(false), boolean(true)]),
+                not(vec![boolean(true)])
             ]),
             expr
         );
@@ -320,9 +1027,9 @@ pub mod tests {
         assert_eq!(tokens.len(), cnt);
         assert_eq!(
             if_expr(
-                and(vec![Expr::Bool(true), Expr::Bool(true)]),
-                Expr::Bool(true),
-                or(vec![Expr::Bool(false), Expr::Bool(false)])
+                and(vec![boolean(true), boolean(true)]),
+                boolean(true),
+                or(vec![boolean(false), boolean(false)])
             ),
             expr
         );
@@ -337,11 +1044,11 @@ pub mod tests {
         assert_eq!(
             call(
                 if_expr(
-                    Expr::Bool(true),
-                    Expr::Operator(Operator::And),
-                    Expr::Operator(Operator::Or)
+                    boolean(true),
+                    operator(Operator::And),
+                    operator(Operator::Or)
                 ),
-                vec![Expr::Bool(true), Expr::Bool(false)]
+                vec![boolean(true), boolean(false)]
             ),
             expr
         );
@@ -356,7 +1063,7 @@ pub mod tests {
         assert_eq!(
             def(
                 "myvar",
-                and(vec![Expr::Bool(true), Expr::Bool(true), Expr::Bool(false)])
+                and(vec![boolean(true), boolean(true), boolean(false)])
             ),
             expr
         );
@@ -371,13 +1078,311 @@ pub mod tests {
         assert_eq!(
             lambda(
                 &["a", "b"],
-                and(vec![ident("a"), ident("b"), Expr::Bool(true)])
+                and(vec![ident("a"), ident("b"), boolean(true)])
             ),
             expr
         );
         Ok(())
     }
 
+    #[test]
+    fn parse_let_succeed() -> TestResult {
+        for (source, kind) in [
+            ("(let ((a T) (b (^ a))) (& a b))", LetKind::Let),
+            ("(let* ((a T) (b (^ a))) (& a b))", LetKind::LetStar),
+            ("(letrec ((a T) (b (^ a))) (& a b))", LetKind::Letrec),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            let (expr, cnt) = parse_internal(&tokens)?;
+            assert_eq!(tokens.len(), cnt);
+            assert_eq!(
+                let_expr(
+                    kind,
+                    &[("a", boolean(true)), ("b", not(vec![ident("a")]))],
+                    and(vec![ident("a"), ident("b")])
+                ),
+                expr
+            );
+        }
+        let tokens = tokenizer::tokenize("(let () T)")?;
+        assert_eq!(let_expr(LetKind::Let, &[], boolean(true)), parse(&tokens)?);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_malformed_let_fail() -> TestResult {
+        for (source, e) in [
+            (
+                "(let (a T) a)",
+                ParserErr::MalformedForm("((pattern expr)...)", Span::new(6, 7, 1, 7)),
+            ),
+            (
+                "(let ((a)) a)",
+                ParserErr::MalformedForm("(pattern expr)", Span::new(6, 9, 1, 7)),
+            ),
+            (
+                "(let ((a T F)) a)",
+                ParserErr::MalformedForm("(pattern expr)", Span::new(11, 12, 1, 12)),
+            ),
+            (
+                "(let* ((a T)))",
+                ParserErr::MalformedForm("(let* ((pattern expr)...) body)", Span::new(0, 14, 1, 1)),
+            ),
+            (
+                "(letrec ((a T)",
+                ParserErr::UnclosedParen("binding list", Span::new(8, 9, 1, 9)),
+            ),
+            (
+                "(let ((if T)) T)",
+                ParserErr::ReservedWord(TokenKind::If, Span::new(7, 9, 1, 8)),
+            ),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            assert_eq!(e, parse(&tokens).unwrap_err(), "{source}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_cond_succeed() -> TestResult {
+        let tokens = tokenizer::tokenize("(cond ((& a b) F) (c T) (else (^ a)))")?;
+        let (expr, cnt) = parse_internal(&tokens)?;
+        assert_eq!(tokens.len(), cnt);
+        assert_eq!(
+            cond(
+                vec![
+                    (and(vec![ident("a"), ident("b")]), boolean(false)),
+                    (ident("c"), boolean(true))
+                ],
+                Some(not(vec![ident("a")]))
+            ),
+            expr
+        );
+        let tokens = tokenizer::tokenize("(cond (a T))")?;
+        assert_eq!(
+            cond(vec![(ident("a"), boolean(true))], None),
+            parse(&tokens)?
+        );
+        let tokens = tokenizer::tokenize("(cond)")?;
+        assert_eq!(cond(vec![], None), parse(&tokens)?);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_malformed_cond_fail() -> TestResult {
+        for (source, e) in [
+            (
+                "(cond a T)",
+                ParserErr::MalformedForm(
+                    "(cond (test expr)... (else expr))",
+                    Span::new(6, 7, 1, 7),
+                ),
+            ),
+            (
+                "(cond (a))",
+                ParserErr::MalformedForm("(test expr)", Span::new(6, 9, 1, 7)),
+            ),
+            (
+                "(cond (else T) (a F))",
+                ParserErr::MalformedForm(
+                    "(cond (test expr)... (else expr))",
+                    Span::new(15, 16, 1, 16),
+                ),
+            ),
+            (
+                "(cond (else))",
+                ParserErr::MalformedForm("(else expr)", Span::new(6, 12, 1, 7)),
+            ),
+            (
+                "(cond (a T)",
+                ParserErr::UnclosedParen("cond expression", Span::new(0, 1, 1, 1)),
+            ),
+            (
+                "else",
+                ParserErr::UnexpectedToken(TokenKind::Else, Span::new(0, 4, 1, 1)),
+            ),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            assert_eq!(e, parse(&tokens).unwrap_err(), "{source}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_import_succeed() -> TestResult {
+        let tokens = tokenizer::tokenize("(import \"adders.lip\")")?;
+        assert_eq!(
+            expr(ExprKind::Import("adders.lip".to_string(), None)),
+            parse(&tokens)?
+        );
+        let tokens = tokenizer::tokenize("(import \"lib/gates.lip\" (mux adders/full-add))")?;
+        assert_eq!(
+            expr(ExprKind::Import(
+                "lib/gates.lip".to_string(),
+                Some(vec!["mux".to_string(), "adders/full-add".to_string()])
+            )),
+            parse(&tokens)?
+        );
+        let tokens = tokenizer::tokenize("(import \"a.lip\" ())")?;
+        assert_eq!(
+            expr(ExprKind::Import("a.lip".to_string(), Some(vec![]))),
+            parse(&tokens)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_malformed_import_fail() -> TestResult {
+        for (source, e) in [
+            (
+                "(import adders)",
+                ParserErr::MalformedForm("(import \"path\" [(names...)])", Span::new(8, 14, 1, 9)),
+            ),
+            (
+                "(import)",
+                ParserErr::MalformedForm("(import \"path\" [(names...)])", Span::new(0, 8, 1, 1)),
+            ),
+            (
+                "(import \"a.lip\" (b T))",
+                ParserErr::ReservedWord(TokenKind::True, Span::new(19, 20, 1, 20)),
+            ),
+            (
+                "(import \"a.lip\" b)",
+                ParserErr::MalformedForm(
+                    "(import \"path\" [(names...)])",
+                    Span::new(16, 17, 1, 17),
+                ),
+            ),
+            (
+                "(import \"a.lip\" (b)",
+                ParserErr::UnclosedParen("import expression", Span::new(0, 1, 1, 1)),
+            ),
+            (
+                "\"a.lip\"",
+                ParserErr::UnexpectedToken(
+                    TokenKind::Str("a.lip".to_string()),
+                    Span::new(0, 7, 1, 1),
+                ),
+            ),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            assert_eq!(e, parse(&tokens).unwrap_err(), "{source}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_defmacro_succeed() -> TestResult {
+        let tokens = tokenizer::tokenize("(defmacro swap (f a b) (f b a))")?;
+        assert_eq!(
+            defmacro(
+                "swap",
+                &["f", "a", "b"],
+                call(ident("f"), vec![ident("b"), ident("a")])
+            ),
+            parse(&tokens)?
+        );
+        for (source, e) in [
+            (
+                "(defmacro m T)",
+                ParserErr::MalformedForm(
+                    "(defmacro identifier (params...) template)",
+                    Span::new(12, 13, 1, 13),
+                ),
+            ),
+            (
+                "(defmacro (m) T)",
+                ParserErr::ExpectedIdentifier(TokenKind::Lparen, Span::new(10, 11, 1, 11)),
+            ),
+            (
+                "(defmacro m (a)",
+                ParserErr::UnclosedParen("defmacro expression", Span::new(0, 1, 1, 1)),
+            ),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            assert_eq!(e, parse(&tokens).unwrap_err(), "{source}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_patterns_succeed() -> TestResult {
+        let list = |args: &[&str], rest: Option<&str>| Pattern::List(params(args, rest));
+        let tokens = tokenizer::tokenize("(lambda ((a b) c) c)")?;
+        let lambda = expr(ExprKind::Lambda(
+            Params::new(
+                vec![list(&["a", "b"], None), Pattern::Ident("c".to_string())],
+                None,
+            ),
+            Box::new(ident("c")),
+        ));
+        assert_eq!(lambda, parse(&tokens)?);
+        let tokens = tokenizer::tokenize("(let (((a (b) . c) T)) c)")?;
+        let pattern = Pattern::List(Params::new(
+            vec![Pattern::Ident("a".to_string()), list(&["b"], None)],
+            Some("c".to_string()),
+        ));
+        let let_expr = expr(ExprKind::Let(
+            LetKind::Let,
+            vec![(pattern, boolean(true))],
+            Box::new(ident("c")),
+        ));
+        assert_eq!(let_expr, parse(&tokens)?);
+        for (source, e) in [
+            (
+                "(let (((a T)) F)) a)",
+                ParserErr::ReservedWord(TokenKind::True, Span::new(10, 11, 1, 11)),
+            ),
+            (
+                "(lambda ((a . b c)) a)",
+                ParserErr::MalformedForm("(pattern... [. rest])", Span::new(16, 17, 1, 17)),
+            ),
+            (
+                "(lambda ((a b) a",
+                ParserErr::UnclosedParen("parameter list", Span::new(8, 9, 1, 9)),
+            ),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            assert_eq!(e, parse(&tokens).unwrap_err(), "{source}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_lambda_rest_succeed() -> TestResult {
+        for (source, required, rest) in [
+            ("(lambda (a b . c) c)", &["a", "b"][..], Some("c")),
+            ("(lambda (. c) c)", &[], Some("c")),
+            ("(lambda c c)", &[], Some("c")),
+            ("(lambda () c)", &[], None),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            assert_eq!(lambda_rest(required, rest, ident("c")), parse(&tokens)?);
+        }
+        for (source, e) in [
+            (
+                "(lambda (a . b c) c)",
+                ParserErr::MalformedForm("(params... [. rest])", Span::new(15, 16, 1, 16)),
+            ),
+            (
+                "(lambda (a .) a)",
+                ParserErr::MalformedForm("(params... [. rest])", Span::new(8, 13, 1, 9)),
+            ),
+            (
+                "(lambda (a . T) a)",
+                ParserErr::ReservedWord(TokenKind::True, Span::new(13, 14, 1, 14)),
+            ),
+            (
+                "(a . b)",
+                ParserErr::UnexpectedToken(TokenKind::Dot, Span::new(3, 4, 1, 4)),
+            ),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            assert_eq!(e, parse(&tokens).unwrap_err(), "{source}");
+        }
+        Ok(())
+    }
+
     #[test]
     fn parse_call_lambda_succeed() -> TestResult {
         let tokens = tokenizer::tokenize("((lambda (x) (^ x)) (& T))")?;
@@ -386,7 +1391,7 @@ pub mod tests {
         assert_eq!(
             call(
                 lambda(&["x"], not(vec![ident("x")])),
-                vec![and(vec![Expr::Bool(true)])]
+                vec![and(vec![boolean(true)])]
             ),
             expr
         );
@@ -401,7 +1406,7 @@ pub mod tests {
         assert_eq!(
             if_expr(
                 ident("a"),
-                and(vec![Expr::Bool(true), Expr::Bool(true)]),
+                and(vec![boolean(true), boolean(true)]),
                 or(vec![ident("b"), ident("c")])
             ),
             expr
@@ -413,12 +1418,28 @@ pub mod tests {
     fn parse_invalid_expr_fail() -> TestResult {
         let tokens = tokenizer::tokenize("(& T F")?;
         match parse_internal(&tokens) {
-            Err(ParserErr::Parse(_)) => (),
+            Err(e) => {
+                assert_eq!(ParserErr::UnclosedParen("call", Span::new(0, 1, 1, 1)), e);
+                assert_eq!("call is not closed with `)`", e.to_string());
+            }
             _ => panic!(),
         }
         Ok(())
     }
 
+    #[test]
+    fn parse_records_spans() -> TestResult {
+        let tokens = tokenizer::tokenize("(if a\n  (& T b)\n  F)")?;
+        let expr = parse(&tokens)?;
+        assert_eq!(Span::new(0, 20, 1, 1), expr.span);
+        let ExprKind::If(If { then, other, .. }) = expr.kind else {
+            panic!()
+        };
+        assert_eq!(Span::new(8, 15, 2, 3), then.span);
+        assert_eq!(Span::new(18, 19, 3, 3), other.span);
+        Ok(())
+    }
+
     #[test]
     fn parse_expr_display() -> TestResult {
         let expr = |s: &str| -> Expr {
@@ -427,14 +1448,182 @@ pub mod tests {
         };
         assert_eq!("T", expr("T").to_string());
         assert_eq!("&", expr("&").to_string());
+        assert_eq!("(bit #b0010 1)", expr("(bit #b0010 1)").to_string());
         assert_eq!("(& T T)", expr("(& T T)").to_string());
         assert_eq!("(if T T (& T F))", expr("(if T T (& T F))").to_string());
-        assert_eq!("(x := (^ F))", expr("(def x (^ F))").to_string());
+        assert_eq!("(def x (^ F))", expr("(def x (^ F))").to_string());
         assert_eq!(
-            "(a) -> (if a F T)",
+            "(lambda (a) (if a F T))",
             expr("(lambda (a) (if a F T))").to_string()
         );
+        assert_eq!("(lambda () T)", expr("(lambda () T)").to_string());
+        assert_eq!("(lambda (a . b) a)", expr("(lambda (a . b) a)").to_string());
+        assert_eq!("(lambda b b)", expr("(lambda (. b) b)").to_string());
+        assert_eq!(
+            "(defmacro m (a . b) (a b))",
+            expr("(defmacro m (a . b)\n  (a b))").to_string()
+        );
+        assert_eq!(
+            "(import \"a.lip\" (b c))",
+            expr("(import  \"a.lip\"\n(b c))").to_string()
+        );
+        assert_eq!(
+            "(lambda ((a (b)) (. c)) a)",
+            expr("(lambda ((a (b)) (. c)) a)").to_string()
+        );
+        assert_eq!(
+            "(let (((s c) (f a))) s)",
+            expr("(let (((s c) (f a))) s)").to_string()
+        );
+        assert_eq!(
+            "(let* ((a T) (b (^ a))) (& a b))",
+            expr("(let*  ((a T)\n (b (^ a)))  (& a b))").to_string()
+        );
+        assert_eq!("(letrec () T)", expr("(letrec () T)").to_string());
+        assert_eq!(
+            "(cond (a T) ((^ a) F) (else T))",
+            expr("(cond (a T)\n ((^ a) F)\n (else T))").to_string()
+        );
+        assert_eq!("(cond)", expr("(cond)").to_string());
+        assert_eq!("(f)", expr("(f)").to_string());
         assert_eq!("myvar", expr("myvar").to_string());
         Ok(())
     }
+
+    #[test]
+    fn parse_trailing_tokens_fail() -> TestResult {
+        let tokens = tokenizer::tokenize("T F (& T)")?;
+        assert_eq!(
+            Err(ParserErr::TrailingTokens(Span::new(2, 9, 1, 3))),
+            parse(&tokens)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_deeply_nested_fail() -> TestResult {
+        let nested = |depth: usize| format!("{}T{}", "(& ".repeat(depth), ")".repeat(depth));
+        assert!(parse(&tokenizer::tokenize(&nested(NESTING_LIMIT))?).is_ok());
+        let tokens = tokenizer::tokenize(&nested(3000))?;
+        let at = 3 * NESTING_LIMIT;
+        assert_eq!(
+            Err(ParserErr::TooDeep(Span::new(at, at + 1, 1, at + 1))),
+            parse(&tokens)
+        );
+        let (_, errors) = parse_program_recovering(&tokens);
+        assert_eq!(
+            vec![ParserErr::TooDeep(Span::new(at, at + 1, 1, at + 1))],
+            errors
+        );
+        let pattern = format!("(let (({}a{} T)) T)", "(".repeat(3000), ")".repeat(3000));
+        let e = parse(&tokenizer::tokenize(&pattern)?).unwrap_err();
+        assert!(matches!(e, ParserErr::TooDeep(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn display_elides_deeply_nested_expr() {
+        let mut nested = boolean(true);
+        for _ in 0..3000 {
+            nested = not(vec![nested]);
+        }
+        let shown = nested.to_string();
+        assert!(shown.starts_with(&"(^ ".repeat(NESTING_LIMIT)));
+        assert!(shown.contains("..."));
+    }
+
+    #[test]
+    fn parse_program_succeed() -> TestResult {
+        let tokens = tokenizer::tokenize("(def x T)\n(& x F)\nx")?;
+        assert_eq!(
+            vec![
+                def("x", boolean(true)),
+                and(vec![ident("x"), boolean(false)]),
+                ident("x")
+            ],
+            parse_program(&tokens)?
+        );
+        assert_eq!(Vec::<Expr>::new(), parse_program(&[])?);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_program_invalid_expr_fail() -> TestResult {
+        let tokens = tokenizer::tokenize("(def x T)\n(& x")?;
+        assert_eq!(
+            Err(ParserErr::UnclosedParen("call", Span::new(10, 11, 2, 1))),
+            parse_program(&tokens)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_program_recovering_reports_every_error() -> TestResult {
+        let source = "(def f (lambda (a &) a))\n(& T F)\n(if T\n(lambda T x)";
+        let tokens = tokenizer::tokenize(source)?;
+        let (exprs, errors) = parse_program_recovering(&tokens);
+        assert_eq!(
+            vec![
+                def("f", expr(ExprKind::Error)),
+                and(vec![boolean(true), boolean(false)]),
+                expr(ExprKind::Error),
+            ],
+            exprs
+        );
+        assert_eq!(
+            vec![
+                ParserErr::ExpectedIdentifier(TokenKind::And, Span::new(18, 19, 1, 19)),
+                ParserErr::MalformedForm("(lambda (params...) body)", Span::new(47, 48, 4, 9)),
+                ParserErr::UnclosedParen("if expression", Span::new(33, 34, 3, 1)),
+            ],
+            errors
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_program_recovering_skips_stray_tokens() -> TestResult {
+        let tokens = tokenizer::tokenize("T ) (^ F) if")?;
+        let (exprs, errors) = parse_program_recovering(&tokens);
+        assert_eq!(
+            vec![
+                boolean(true),
+                expr(ExprKind::Error),
+                not(vec![boolean(false)]),
+                expr(ExprKind::Error)
+            ],
+            exprs
+        );
+        assert_eq!(
+            vec![
+                ParserErr::UnexpectedToken(TokenKind::Rparen, Span::new(2, 3, 1, 3)),
+                ParserErr::UnexpectedToken(TokenKind::If, Span::new(10, 12, 1, 11)),
+            ],
+            errors
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_bind_reserved_word_fail() -> TestResult {
+        for (source, token, span) in [
+            ("(def if T)", TokenKind::If, Span::new(5, 7, 1, 6)),
+            ("(def T F)", TokenKind::True, Span::new(5, 6, 1, 6)),
+            (
+                "(lambda (a lambda) a)",
+                TokenKind::Lambda,
+                Span::new(11, 17, 1, 12),
+            ),
+        ] {
+            let tokens = tokenizer::tokenize(source)?;
+            let e = parse(&tokens).unwrap_err();
+            assert_eq!(ParserErr::ReservedWord(token, span), e);
+        }
+        let tokens = tokenizer::tokenize("(def def T)")?;
+        assert_eq!(
+            "`def` is a reserved word and cannot be used as a name",
+            parse(&tokens).unwrap_err().to_string()
+        );
+        Ok(())
+    }
 }
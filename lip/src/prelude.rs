@@ -0,0 +1,151 @@
+//! The prelude: circuits written in lip that programs start with, such as multiplexers,
+//! decoders, comparators and adders. See `prelude.lip` for the definitions.
+
+use crate::{environment::Environment, repl::eval_str};
+
+/// The source code of the prelude, embedded in the crate.
+pub const SOURCE: &str = include_str!("prelude.lip");
+
+/// Makes the environment a program starts in: an empty scope for its definitions, nested in a
+/// scope holding the prelude, or in an empty one if `with_prelude` is false.
+///
+/// Definitions in the program shadow those of the prelude, and modules it imports see the
+/// prelude too.
+pub fn environment(with_prelude: bool) -> Environment {
+    let base = Environment::default();
+    if with_prelude {
+        eval_str(SOURCE, &base).expect("the prelude evaluates without errors");
+    }
+    base.child()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bits::Bits, evaluator::Value, test_util::TestResult};
+
+    fn eval(source: &str, env: &Environment) -> Result<Value, crate::Error> {
+        Ok(eval_str(source, env)?.unwrap())
+    }
+
+    fn b(b: bool) -> &'static str {
+        if b {
+            "T"
+        } else {
+            "F"
+        }
+    }
+
+    fn bools(values: &[bool]) -> Value {
+        Value::List(values.iter().map(|&b| Value::Bool(b)).collect())
+    }
+
+    /// Every assignment of `n` bools, counting up in binary with the first one most significant.
+    fn inputs(n: usize) -> impl Iterator<Item = Vec<bool>> {
+        (0..1usize << n).map(move |i| (0..n).rev().map(|bit| i >> bit & 1 == 1).collect())
+    }
+
+    /// `n` written as a bit vector literal of `width` bits.
+    fn literal(n: usize, width: usize) -> String {
+        format!("#b{n:0width$b}")
+    }
+
+    #[test]
+    fn prelude_can_be_left_out() {
+        assert!(environment(true).get("mux2").is_some());
+        assert!(environment(false).get("mux2").is_none());
+    }
+
+    #[test]
+    fn prelude_definitions_can_be_shadowed() -> TestResult {
+        let env = environment(true);
+        eval("(def mux2 (lambda (s a b) a))", &env)?;
+        assert_eq!(Value::Bool(false), eval("(mux2 T F T)", &env)?);
+        // Prelude definitions keep using their own `mux2`.
+        assert_eq!(Value::Bool(true), eval("(mux4 F T F T F F)", &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn prelude_muxes_match_truth_tables() -> TestResult {
+        let env = environment(true);
+        for input in inputs(3) {
+            let [s, x, y] = input[..] else { unreachable!() };
+            let source = format!("(mux2 {} {} {})", b(s), b(x), b(y));
+            assert_eq!(Value::Bool(if s { y } else { x }), eval(&source, &env)?);
+        }
+        for input in inputs(6) {
+            let (select, data) = input.split_at(2);
+            let source = format!(
+                "(mux4 {})",
+                input.iter().map(|&i| b(i)).collect::<Vec<_>>().join(" ")
+            );
+            let index = usize::from(select[0]) * 2 + usize::from(select[1]);
+            assert_eq!(Value::Bool(data[index]), eval(&source, &env)?, "{source}");
+        }
+        assert_eq!(
+            eval("#b10", &env)?,
+            eval("(mux4 T F #b00 #b01 #b10 #b11)", &env)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn prelude_decoders_match_truth_tables() -> TestResult {
+        let env = environment(true);
+        for (name, n) in [("decoder1", 1), ("decoder2", 2), ("decoder3", 3)] {
+            for (index, input) in inputs(n).enumerate() {
+                let args: Vec<&str> = input.iter().map(|&i| b(i)).collect();
+                let source = format!("({name} {})", args.join(" "));
+                let expected: Vec<bool> = (0..1 << n).map(|i| i == index).collect();
+                assert_eq!(bools(&expected), eval(&source, &env)?, "{source}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn prelude_comparators_match_truth_tables() -> TestResult {
+        let env = environment(true);
+        for input in inputs(2) {
+            let [x, y] = input[..] else { unreachable!() };
+            let source = format!("(comparator {} {})", b(x), b(y));
+            assert_eq!(bools(&[!x && y, x == y, x && !y]), eval(&source, &env)?);
+        }
+        for x in 0..8 {
+            for y in 0..8 {
+                let (lx, ly) = (literal(x, 3), literal(y, 3));
+                for (name, expected) in [("eq?", x == y), ("lt?", x < y), ("gt?", x > y)] {
+                    let source = format!("({name} {lx} {ly})");
+                    assert_eq!(Value::Bool(expected), eval(&source, &env)?, "{source}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn prelude_adders_match_truth_tables() -> TestResult {
+        let env = environment(true);
+        for input in inputs(2) {
+            let [x, y] = input[..] else { unreachable!() };
+            let source = format!("(half-adder {} {})", b(x), b(y));
+            assert_eq!(bools(&[x ^ y, x && y]), eval(&source, &env)?);
+        }
+        for input in inputs(3) {
+            let sum = input.iter().filter(|&&i| i).count();
+            let args: Vec<&str> = input.iter().map(|&i| b(i)).collect();
+            let source = format!("(full-adder {})", args.join(" "));
+            assert_eq!(bools(&[sum % 2 == 1, sum >= 2]), eval(&source, &env)?);
+        }
+        for x in 0..16 {
+            for y in 0..16 {
+                let source = format!("(add {} {})", literal(x, 4), literal(y, 4));
+                let sum = Bits::from_digits(&format!("{:04b}", (x + y) % 16)).unwrap();
+                let expected = Value::List(vec![Value::Bits(sum), Value::Bool(x + y >= 16)]);
+                assert_eq!(expected, eval(&source, &env)?, "{source}");
+            }
+        }
+        Ok(())
+    }
+}
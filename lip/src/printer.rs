@@ -0,0 +1,501 @@
+use std::collections::VecDeque;
+
+use crate::{
+    parser::{self, Expr, ExprKind, If},
+    span::Span,
+    tokenizer::{self, Comment},
+    Error,
+};
+
+/// The line width [`format`] tries to keep within.
+pub const WIDTH: usize = 80;
+
+/// Prints `expr` as lip source code, breaking lines where it does not fit in `width` columns.
+/// The output parses back to the same expression.
+///
+/// ```text
+/// (if (& T T F)
+///   (^ F)
+///   (| T F F))
+/// ```
+pub fn pretty(expr: &Expr, width: usize) -> String {
+    let mut printer = Printer::new("", Vec::new(), width);
+    printer.expr(expr);
+    printer.out
+}
+
+/// Formats a program with [`pretty`], keeping its comments and the blank lines between
+/// top-level expressions.
+pub fn format(source: &str, width: usize) -> Result<String, Error> {
+    let (tokens, comments) = tokenizer::tokenize_with_comments(source)?;
+    let exprs = parser::parse_program(&tokens)?;
+    let mut printer = Printer::new(source, comments, width);
+    let mut prev = None;
+    for expr in &exprs {
+        while let Some(comment) = printer.comments.front() {
+            if comment.span.start >= expr.span.start {
+                break;
+            }
+            let span = comment.span;
+            printer.separate(prev, span);
+            printer.comments_before(span.end, 0);
+            prev = Some(span.end);
+        }
+        printer.separate(prev, expr.span);
+        printer.expr(expr);
+        prev = Some(expr.span.end);
+    }
+    while let Some(comment) = printer.comments.front() {
+        let span = comment.span;
+        printer.separate(prev, span);
+        printer.comments_before(span.end, 0);
+        prev = Some(span.end);
+    }
+    let mut out = printer.out.trim_end().to_string();
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+struct Printer<'a> {
+    source: &'a str,
+    comments: VecDeque<Comment>,
+    width: usize,
+    out: String,
+    /// The number of expressions being printed that enclose the current one.
+    depth: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(source: &'a str, comments: Vec<Comment>, width: usize) -> Self {
+        Self {
+            source,
+            comments: comments.into(),
+            width,
+            out: String::new(),
+            depth: 0,
+        }
+    }
+
+    /// The column, counted in characters, at which the next character will be printed.
+    fn col(&self) -> usize {
+        let line = self.out.rsplit('\n').next().unwrap_or("");
+        line.chars().count()
+    }
+
+    fn line_is_blank(&self) -> bool {
+        let line = self.out.rsplit('\n').next().unwrap_or("");
+        line.trim().is_empty()
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(indent));
+    }
+
+    /// Starts a new line before a top-level item, keeping one blank line if the source had any.
+    fn separate(&mut self, prev: Option<usize>, next: Span) {
+        let Some(prev) = prev else {
+            return;
+        };
+        if self.is_trailing(next) {
+            return;
+        }
+        if !self.line_is_blank() {
+            self.newline(0);
+        }
+        let between = self.source.get(prev..next.start).unwrap_or("");
+        if between.matches('\n').count() >= 2 {
+            self.newline(0);
+        }
+    }
+
+    /// Whether `span` comes after some code on the same source line.
+    fn is_trailing(&self, span: Span) -> bool {
+        let before = self.source.get(..span.start).unwrap_or("");
+        let line = before.rsplit('\n').next().unwrap_or("");
+        !line.trim().is_empty()
+    }
+
+    /// Prints the comments that start before `pos`, each followed by a line break.
+    fn comments_before(&mut self, pos: usize, indent: usize) {
+        while self.comments.front().is_some_and(|c| c.span.start < pos) {
+            let comment = self.comments.pop_front().unwrap();
+            self.comment(comment, indent);
+        }
+    }
+
+    /// Prints a comment after the code on the current line if it trails code in the source, or
+    /// on its own line otherwise.
+    fn comment(&mut self, comment: Comment, indent: usize) {
+        if self.is_trailing(comment.span) && !self.line_is_blank() {
+            self.out.truncate(self.out.trim_end_matches(' ').len());
+            self.out.push(' ');
+        } else if !self.line_is_blank() {
+            self.newline(indent);
+        } else {
+            self.out.truncate(self.out.trim_end_matches(' ').len());
+            self.out.push_str(&" ".repeat(indent));
+        }
+        self.out.push_str(&comment.text);
+        self.newline(indent);
+    }
+
+    /// Starts a new line at `indent` for the expression at `next`. Comments trailing the current
+    /// line in the source stay on it.
+    fn break_before(&mut self, next: Span, indent: usize) {
+        while let Some(comment) = self.comments.front() {
+            if comment.span.start >= next.start || !self.is_trailing(comment.span) {
+                break;
+            }
+            let comment = self.comments.pop_front().unwrap();
+            self.comment(comment, indent);
+        }
+        if !self.line_is_blank() {
+            self.newline(indent);
+        }
+    }
+
+    /// Prints the comments before the `(` enclosing `pos`, such as those before a `cond` clause
+    /// or a `let` binding, so that they stay outside of it.
+    fn comments_before_open(&mut self, pos: usize, indent: usize) {
+        let open = self.open_paren(pos).unwrap_or(pos);
+        self.comments_before(open, indent);
+    }
+
+    /// The position of the `(` enclosing `pos` in the source, scanning back over balanced
+    /// parens and comments.
+    fn open_paren(&self, pos: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut i = pos;
+        while i > 0 {
+            i -= 1;
+            let comment = self
+                .comments
+                .iter()
+                .find(|c| c.span.start <= i && i < c.span.end);
+            if let Some(comment) = comment {
+                i = comment.span.start;
+                continue;
+            }
+            match self.source.as_bytes().get(i)? {
+                b')' => depth += 1,
+                b'(' if depth == 0 => return Some(i),
+                b'(' => depth -= 1,
+                _ => (),
+            }
+        }
+        None
+    }
+
+    fn has_comments_within(&self, span: Span) -> bool {
+        self.comments
+            .iter()
+            .any(|c| span.start <= c.span.start && c.span.start < span.end)
+    }
+
+    /// Prints `expr`, with `...` in its place if it is nested more than
+    /// [`parser::NESTING_LIMIT`] deep, as only expressions built by macros can be.
+    fn expr(&mut self, expr: &Expr) {
+        if self.depth > parser::NESTING_LIMIT {
+            self.out.push_str("...");
+            return;
+        }
+        self.depth += 1;
+        self.print(expr);
+        self.depth -= 1;
+    }
+
+    fn print(&mut self, expr: &Expr) {
+        let indent = self.col();
+        self.comments_before(expr.span.start, indent);
+        let indent = self.col();
+        let flat = expr.to_string();
+        let fits = indent + flat.chars().count() <= self.width;
+        if fits && !self.has_comments_within(expr.span) {
+            self.out.push_str(&flat);
+            return;
+        }
+        match &expr.kind {
+            ExprKind::Call(operator, operands) => {
+                self.out.push('(');
+                self.expr(operator);
+                if let Some((first, rest)) = operands.split_first() {
+                    let align = if is_atom(operator) {
+                        self.out.push(' ');
+                        self.col()
+                    } else {
+                        self.break_before(first.span, indent + 1);
+                        indent + 1
+                    };
+                    self.expr(first);
+                    for operand in rest {
+                        self.break_before(operand.span, align);
+                        self.expr(operand);
+                    }
+                }
+                self.close(expr.span, indent + 1);
+            }
+            ExprKind::If(If { cond, then, other }) => {
+                self.out.push_str("(if ");
+                self.expr(cond);
+                self.break_before(then.span, indent + 2);
+                self.expr(then);
+                self.break_before(other.span, indent + 2);
+                self.expr(other);
+                self.close(expr.span, indent + 2);
+            }
+            ExprKind::Def(ident, value) => {
+                self.out.push_str(&format!("(def {ident} "));
+                self.expr(value);
+                self.close(expr.span, indent + 2);
+            }
+            ExprKind::Defmacro(ident, params, template) => {
+                self.out.push_str(&format!("(defmacro {ident} {params}"));
+                self.break_before(template.span, indent + 2);
+                self.expr(template);
+                self.close(expr.span, indent + 2);
+            }
+            ExprKind::Lambda(params, body) => {
+                self.out.push_str(&format!("(lambda {params}"));
+                self.break_before(body.span, indent + 2);
+                self.expr(body);
+                self.close(expr.span, indent + 2);
+            }
+            ExprKind::Let(kind, bindings, body) => {
+                self.out.push_str(&format!("({kind} ("));
+                let align = self.col();
+                for (i, (pattern, value)) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        self.break_before(value.span, align);
+                    }
+                    self.comments_before_open(value.span.start, align);
+                    self.out.push_str(&format!("({pattern} "));
+                    self.expr(value);
+                    self.out.push(')');
+                }
+                self.out.push(')');
+                self.break_before(body.span, indent + 2);
+                self.expr(body);
+                self.close(expr.span, indent + 2);
+            }
+            ExprKind::Cond(clauses, other) => {
+                self.out.push_str("(cond ");
+                let align = self.col();
+                for (i, (test, then)) in clauses.iter().enumerate() {
+                    if i > 0 {
+                        self.break_before(test.span, align);
+                    }
+                    self.comments_before_open(test.span.start, align);
+                    self.out.push('(');
+                    self.expr(test);
+                    let fits = self.col() + then.to_string().chars().count() + 2 <= self.width;
+                    if fits && !self.has_comments_within(then.span) {
+                        self.out.push(' ');
+                    } else {
+                        self.break_before(then.span, align + 1);
+                    }
+                    self.expr(then);
+                    self.out.push(')');
+                }
+                if let Some(other) = other {
+                    if !clauses.is_empty() {
+                        self.break_before(other.span, align);
+                    }
+                    self.comments_before_open(other.span.start, align);
+                    self.out.push_str("(else ");
+                    self.expr(other);
+                    self.out.push(')');
+                }
+                self.close(expr.span, indent + 2);
+            }
+            ExprKind::Import(path, names) => {
+                // The path and the names have no spans, so find them among the tokens of the
+                // form in the source.
+                let tokens = self
+                    .source
+                    .get(expr.span.start..expr.span.end)
+                    .and_then(|form| tokenizer::tokenize(form).ok())
+                    .unwrap_or_default();
+                let start = |i: usize| {
+                    tokens
+                        .get(i)
+                        .map_or(expr.span.end, |token| expr.span.start + token.span.start)
+                };
+                self.out.push_str("(import ");
+                self.comments_before(start(2), self.col());
+                self.out.push_str(&format!("\"{path}\""));
+                if let Some(names) = names {
+                    self.out.push(' ');
+                    let align = self.col();
+                    self.comments_before(start(3), align);
+                    self.out.push('(');
+                    for (i, name) in names.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push(' ');
+                        }
+                        self.comments_before(start(4 + i), align + 1);
+                        self.out.push_str(name);
+                    }
+                    self.comments_before(start(4 + names.len()), align + 1);
+                    self.out.push(')');
+                }
+                self.close(expr.span, indent + 2);
+            }
+            _ => self.out.push_str(&flat),
+        }
+    }
+
+    /// Prints the comments left inside the form at `span`, then its closing `)`.
+    fn close(&mut self, span: Span, indent: usize) {
+        self.comments_before(span.end, indent);
+        self.out.push(')');
+    }
+}
+
+fn is_atom(expr: &Expr) -> bool {
+    !matches!(
+        expr.kind,
+        ExprKind::Call(..)
+            | ExprKind::If(_)
+            | ExprKind::Def(..)
+            | ExprKind::Defmacro(..)
+            | ExprKind::Lambda(..)
+            | ExprKind::Let(..)
+            | ExprKind::Cond(..)
+            | ExprKind::Import(..)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::TestResult, tokenizer::tokenize};
+
+    fn parse(source: &str) -> Expr {
+        parser::parse(&tokenize(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pretty_fitting_expr_stays_on_one_line() {
+        let expr = parse("(def gate (lambda (a b) (^ (& a b))))");
+        assert_eq!("(def gate (lambda (a b) (^ (& a b))))", pretty(&expr, 80));
+    }
+
+    #[test]
+    fn pretty_breaks_long_expr() {
+        let expr = parse("(if (& T T F) (^ F) (| T F F))");
+        assert_eq!("(if (& T T F)\n  (^ F)\n  (| T F F))", pretty(&expr, 20));
+        let expr = parse("(def gate (lambda (a b) (^ (& a b))))");
+        assert_eq!(
+            "(def gate (lambda (a b)\n            (^ (& a b))))",
+            pretty(&expr, 30)
+        );
+        let expr = parse("(& long-name (| another-name yet-another-name))");
+        assert_eq!(
+            "(& long-name\n   (| another-name\n      yet-another-name))",
+            pretty(&expr, 20)
+        );
+    }
+
+    #[test]
+    fn pretty_breaks_long_let() {
+        let expr = parse("(let* ((carry (& a b)) (sum (xor a b))) (| carry sum))");
+        assert_eq!(
+            "(let* ((carry (& a b))\n       (sum (xor a b)))\n  (| carry sum))",
+            pretty(&expr, 30)
+        );
+    }
+
+    #[test]
+    fn pretty_breaks_long_cond() {
+        let expr = parse("(cond ((& s1 s0) d) ((& s1 (^ s0)) (| c d)) (s0 b) (else a))");
+        assert_eq!(
+            "(cond ((& s1 s0) d)\n      ((& s1 (^ s0))\n       (| c d))\n      (s0 b)\n      (else a))",
+            pretty(&expr, 25)
+        );
+    }
+
+    #[test]
+    fn pretty_round_trips() {
+        let source = "((lambda (a b) (if a (& a b) (| (^ a) b))) (def x T) ((if T & |) F T) (letrec ((f (lambda () (f)))) (let () x)) (cond (a (cond)) (else (cond (b F)))) (slice #b1010 2 0) (let (((a (b) . c) x)) (lambda ((d . e)) d)) (defmacro m (f . xs) (f xs)) (import \"a.lip\" (b)))";
+        let expr = parse(source);
+        for width in [0, 10, 40, 80] {
+            assert_eq!(expr, parse(&pretty(&expr, width)));
+        }
+    }
+
+    #[test]
+    fn pretty_elides_deeply_nested_expr() {
+        let mut nested = parse("T");
+        for _ in 0..3000 {
+            nested = parser::tests::not(vec![nested]);
+        }
+        let printed = pretty(&nested, 80);
+        assert!(printed.starts_with(&"(^ ".repeat(parser::NESTING_LIMIT)));
+        assert!(printed.contains("..."));
+    }
+
+    #[test]
+    fn format_keeps_comments() -> TestResult {
+        let source =
+            "; nand gate\n(def gate   (lambda (a b) ; inputs\n  (^ (& a #;b b))))\n\n\n#| end |#";
+        assert_eq!(
+            "; nand gate\n(def gate (lambda (a b) ; inputs\n            (^ (& a #;b\n                  b))))\n\n#| end |#\n",
+            format(source, 80)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn format_keeps_comments_outside_cond_clauses() -> TestResult {
+        let source = "(cond ; c\n (a b)\n ; second\n ((& a b) c)\n ; otherwise\n (else d))";
+        let formatted = format(source, 80)?;
+        assert_eq!(
+            "(cond ; c\n      (a b)\n      ; second\n      ((& a b) c)\n      ; otherwise\n      (else d))\n",
+            formatted
+        );
+        assert_eq!(formatted, format(&formatted, 80)?);
+        Ok(())
+    }
+
+    #[test]
+    fn format_keeps_comments_before_let_bindings() -> TestResult {
+        let source = "(let* (; first\n (a T)\n ;; pair\n ((b c) (list a a)))\n (& a b c))";
+        let formatted = format(source, 80)?;
+        assert_eq!(
+            "(let* ( ; first\n       (a T)\n       ;; pair\n       ((b c) (list a a)))\n  (& a b c))\n",
+            formatted
+        );
+        assert_eq!(formatted, format(&formatted, 80)?);
+        assert_eq!(parse(source), parse(&formatted));
+        Ok(())
+    }
+
+    #[test]
+    fn format_keeps_comments_inside_import() -> TestResult {
+        let source = "(import ; adders\n \"adders.lip\" (half-adder ; one bit\n full-adder))";
+        let formatted = format(source, 80)?;
+        assert_eq!(
+            "(import ; adders\n        \"adders.lip\" (half-adder ; one bit\n                      full-adder))\n",
+            formatted
+        );
+        assert_eq!(formatted, format(&formatted, 80)?);
+        assert_eq!(parse(source), parse(&formatted));
+        Ok(())
+    }
+
+    #[test]
+    fn format_is_idempotent() -> TestResult {
+        let source = "(def x T) ; trailing\n;; leading\n(if x\n  ; then\n  F T)\n(& x\n   F)";
+        let formatted = format(source, 80)?;
+        assert_eq!(
+            "(def x T) ; trailing\n;; leading\n(if x\n  ; then\n  F\n  T)\n(& x F)\n",
+            formatted
+        );
+        assert_eq!(formatted, format(&formatted, 80)?);
+        Ok(())
+    }
+}
@@ -2,12 +2,52 @@ use std::io::{self, BufRead, Write};
 use wasm_bindgen::prelude::*;
 
 use crate::{
+    cnf::Formula,
     environment::Environment,
-    evaluator::{self, eval, Value},
-    parser::{self, parse},
-    tokenizer::{self, tokenize},
+    equiv,
+    evaluator::{eval, Value},
+    macros,
+    parser::{parse, parse_program},
+    prelude, printer,
+    span::{SourceId, Span},
+    table::{Format, TruthTable},
+    tokenizer::tokenize,
+    Error,
 };
 
+/// A REPL command used with the wrong arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandErr {
+    /// Arguments that do not match the usage of the command, such as `:equiv <f> <g>`.
+    Usage(&'static str, Span),
+    /// No expression to convert to CNF.
+    NothingToExport(Span),
+}
+
+impl CommandErr {
+    pub fn span(&self) -> Span {
+        match self {
+            CommandErr::Usage(_, span) | CommandErr::NothingToExport(span) => *span,
+        }
+    }
+}
+
+impl std::error::Error for CommandErr {}
+
+impl std::fmt::Display for CommandErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandErr::Usage(usage, _) => write!(f, "expected `{usage}`"),
+            CommandErr::NothingToExport(_) => {
+                write!(
+                    f,
+                    "nothing to export, expected an expression to convert to CNF"
+                )
+            }
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub struct Repl {
     env: Environment,
@@ -15,23 +55,49 @@ pub struct Repl {
 
 #[wasm_bindgen]
 impl Repl {
+    /// Starts a session with the prelude loaded.
     pub fn new() -> Self {
         Self {
-            env: Environment::default(),
+            env: prelude::environment(true),
+        }
+    }
+
+    /// Starts a session without the prelude.
+    pub fn without_prelude() -> Self {
+        Self {
+            env: prelude::environment(false),
         }
     }
 
     pub fn eval(&mut self, expr: &str) -> Result<String, String> {
-        match self.eval_internal(expr) {
-            Ok(value) => Ok(format!("{value}")),
-            Err(e) => Err(format!("{e:?}")),
+        match eval_str(expr, &self.env) {
+            Ok(value) => Ok(value.map(|value| value.to_string()).unwrap_or_default()),
+            Err(e) => Err(e.report(expr)),
         }
     }
 
-    fn eval_internal(&mut self, expr: &str) -> Result<Value, Box<dyn std::error::Error>> {
-        let tokens = tokenizer::tokenize(expr)?;
-        let expr = parser::parse(&tokens)?;
-        Ok(evaluator::eval(&expr, &mut self.env)?)
+    /// Shows what the macro call `expr` expands to, like the `:expand` command of the CLI.
+    pub fn expand(&mut self, expr: &str) -> Result<String, String> {
+        expand_str(expr, &self.env).map_err(|e| e.report(expr))
+    }
+
+    /// Renders the truth table of `expr`, like the `:table` command of the CLI, so `expr` may
+    /// start with `--markdown` or `--csv`.
+    pub fn table(&mut self, expr: &str) -> Result<String, String> {
+        let (format, expr) = table_format(expr);
+        table_str(expr, format, &self.env).map_err(|e| e.report(expr))
+    }
+
+    /// Converts the last expression in `source` to CNF in the DIMACS format, like the `:cnf`
+    /// command of the CLI.
+    pub fn cnf(&mut self, source: &str) -> Result<String, String> {
+        dimacs_str(source, &self.env).map_err(|e| e.report(source))
+    }
+
+    /// Checks whether the two lambdas in `exprs` are equivalent, like the `:equiv` command of the
+    /// CLI.
+    pub fn equiv(&mut self, exprs: &str) -> Result<String, String> {
+        equiv_str(exprs, &self.env).map_err(|e| e.report(exprs))
     }
 }
 
@@ -41,18 +107,105 @@ impl std::default::Default for Repl {
     }
 }
 
+/// Evaluates every expression in `source` and returns the value of the last one.
+pub fn eval_str(source: &str, env: &Environment) -> Result<Option<Value>, Error> {
+    let tokens = tokenize(source)?;
+    let mut value = None;
+    for expr in parse_program(&tokens)? {
+        value = Some(eval(&expr, env)?);
+    }
+    Ok(value)
+}
+
+/// Expands the macro call in `source` without evaluating it, and formats the result.
+fn expand_str(source: &str, env: &Environment) -> Result<String, Error> {
+    let tokens = tokenize(source)?;
+    let expr = macros::expand(&parse(&tokens)?, env)?;
+    Ok(printer::pretty(&expr, printer::WIDTH))
+}
+
+/// Splits a leading `--markdown` or `--csv` off the arguments of `:table`.
+fn table_format(args: &str) -> (Format, &str) {
+    let args = args.trim_start();
+    for (flag, format) in [("--markdown", Format::Markdown), ("--csv", Format::Csv)] {
+        if let Some(rest) = args.strip_prefix(flag) {
+            if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                return (format, rest.trim_start());
+            }
+        }
+    }
+    (Format::Text, args)
+}
+
+/// Renders the truth table of the lambda or the expression with free variables in `source`.
+fn table_str(source: &str, format: Format, env: &Environment) -> Result<String, Error> {
+    let tokens = tokenize(source)?;
+    let table = TruthTable::of_expr(&parse(&tokens)?, env)?;
+    Ok(table.render(format))
+}
+
+/// Evaluates the two expressions in `source` and checks whether the lambdas they evaluate to
+/// are equivalent.
+fn equiv_str(source: &str, env: &Environment) -> Result<String, Error> {
+    let tokens = tokenize(source)?;
+    let exprs = parse_program(&tokens)?;
+    let [left, right] = &exprs[..] else {
+        let span = match (exprs.first(), exprs.last()) {
+            (Some(first), Some(last)) => first.span.to(last.span),
+            _ => Span::default().in_source(SourceId::of(source)),
+        };
+        return Err(CommandErr::Usage(":equiv <f> <g>", span).into());
+    };
+    let (left_value, right_value) = (eval(left, env)?, eval(right, env)?);
+    let result = equiv::check(&left_value, &right_value, left.span.to(right.span))?;
+    Ok(result.to_string())
+}
+
+/// Evaluates every expression in `source` but the last, and converts the last one, a lambda or
+/// an expression with free variables, to an equisatisfiable CNF in the DIMACS format.
+pub fn dimacs_str(source: &str, env: &Environment) -> Result<String, Error> {
+    let tokens = tokenize(source)?;
+    let exprs = parse_program(&tokens)?;
+    let Some((last, exprs)) = exprs.split_last() else {
+        let span = Span::default().in_source(SourceId::of(source));
+        return Err(CommandErr::NothingToExport(span).into());
+    };
+    for expr in exprs {
+        eval(expr, env)?;
+    }
+    Ok(Formula::of_expr(last, env)?.dimacs())
+}
+
+/// The arguments of `input` if it is the command `name`, which must be followed by whitespace
+/// or the end of the input.
+fn command<'a>(input: &'a str, name: &str) -> Option<&'a str> {
+    let args = input.strip_prefix(name)?;
+    (args.is_empty() || args.starts_with(char::is_whitespace)).then(|| args.trim_start())
+}
+
+/// Runs the REPL with the prelude loaded.
 pub fn run<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> io::Result<()> {
+    run_in(input, output, prelude::environment(true))
+}
+
+/// Runs the REPL in `env`, such as one made by [`prelude::environment`].
+pub fn run_in<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    env: Environment,
+) -> io::Result<()> {
     let mut print = move |s: &str| {
         write!(output, "{s}")?;
         output.flush()
     };
 
-    let mut env = Environment::default();
     loop {
         print("lip> ")?;
 
         let mut buf = String::new();
-        input.read_line(&mut buf)?;
+        if input.read_line(&mut buf)? == 0 {
+            break;
+        }
         let input = buf.trim();
         if input == ":exit" {
             break;
@@ -61,22 +214,40 @@ pub fn run<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> io::Result<()
             print(&format!("{env:?}\n"))?;
             continue;
         }
-        let tokens = tokenize(input);
-        if let Err(e) = tokens {
-            print(&format!("Failed to tokenize: {e:?}\n"))?;
+        if let Some(expr) = command(input, ":expand") {
+            match expand_str(expr, &env) {
+                Ok(expansion) => print(&format!("{expansion}\n"))?,
+                Err(e) => print(&format!("{}\n", e.report(expr)))?,
+            }
             continue;
         }
-        let expr = parse(&tokens.unwrap());
-        if let Err(e) = expr {
-            print(&format!("Failed to parse: {e:?}\n"))?;
+        if let Some(args) = command(input, ":table") {
+            let (format, expr) = table_format(args);
+            match table_str(expr, format, &env) {
+                Ok(table) => print(&table)?,
+                Err(e) => print(&format!("{}\n", e.report(expr)))?,
+            }
             continue;
         }
-        let value = eval(&expr.unwrap(), &mut env);
-        if let Err(e) = value {
-            print(&format!("Failed to evalueate: {e:?}\n"))?;
+        if let Some(source) = command(input, ":cnf") {
+            match dimacs_str(source, &env) {
+                Ok(dimacs) => print(&dimacs)?,
+                Err(e) => print(&format!("{}\n", e.report(source)))?,
+            }
             continue;
         }
-        print(&format!("{}\n", value.unwrap()))?;
+        if let Some(exprs) = command(input, ":equiv") {
+            match equiv_str(exprs, &env) {
+                Ok(result) => print(&format!("{result}\n"))?,
+                Err(e) => print(&format!("{}\n", e.report(exprs)))?,
+            }
+            continue;
+        }
+        match eval_str(input, &env) {
+            Ok(Some(value)) => print(&format!("{value}\n"))?,
+            Ok(None) => (),
+            Err(e) => print(&format!("{}\n", e.report(input)))?,
+        }
     }
     Ok(())
 }
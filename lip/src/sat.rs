@@ -0,0 +1,437 @@
+//! A conflict-driven clause learning SAT solver.
+//!
+//! The solver assigns variables one decision at a time and propagates the clauses that become
+//! unit, watching two literals of each clause. When a clause is falsified it learns the clause
+//! that cuts the first unique implication point of the conflict, jumps back to the latest
+//! decision that clause depends on, and prefers deciding the variables involved in recent
+//! conflicts. Clauses can be added between calls to [`Solver::solve`], which is how
+//! [`all_solutions`] blocks the solutions found so far.
+
+use crate::{
+    cnf::{Cnf, Formula, Lit},
+    evaluator::EvalErr,
+    span::Span,
+};
+
+/// Activities of variables are multiplied by this when a conflict involves them, relative to
+/// the others.
+const ACTIVITY_GROWTH: f64 = 1.0 / 0.95;
+
+/// The conflicts before the first restart, a number that grows by half after each.
+const FIRST_RESTART: usize = 100;
+
+/// The most solutions [`all_solutions`] lists. Each solution found is blocked with a clause,
+/// which slows down the search for the next, so listing more would take too long.
+pub const MAX_SOLUTIONS: usize = 4096;
+
+/// A solver for a [`Cnf`] that grows with [`Solver::add_clause`].
+#[derive(Debug, Default)]
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    /// The clauses whose first two literals include a literal, indexed by [`Lit::index`].
+    watches: Vec<Vec<usize>>,
+    assignment: Vec<Option<bool>>,
+    /// The decision level each assigned variable was assigned at.
+    level: Vec<usize>,
+    /// The clause that implied each assigned variable, or `None` for decisions.
+    reason: Vec<Option<usize>>,
+    /// The value each variable was last assigned, which decisions try first.
+    phase: Vec<bool>,
+    activity: Vec<f64>,
+    activity_increment: f64,
+    /// The literals made true, in order.
+    trail: Vec<Lit>,
+    /// Where the trail of each decision level starts.
+    levels: Vec<usize>,
+    /// How much of the trail has been propagated.
+    propagated: usize,
+    /// Whether a clause is false without any decisions.
+    unsatisfiable: bool,
+}
+
+impl Solver {
+    pub fn new(cnf: &Cnf) -> Self {
+        let mut solver = Self {
+            activity_increment: 1.0,
+            ..Self::default()
+        };
+        while solver.vars() < cnf.vars {
+            solver.add_var();
+        }
+        for clause in &cnf.clauses {
+            solver.add_clause(clause.clone());
+        }
+        solver
+    }
+
+    pub fn vars(&self) -> usize {
+        self.assignment.len()
+    }
+
+    /// Adds a variable, numbered after the others.
+    pub fn add_var(&mut self) -> usize {
+        self.watches.extend([Vec::new(), Vec::new()]);
+        self.assignment.push(None);
+        self.level.push(0);
+        self.reason.push(None);
+        self.phase.push(false);
+        self.activity.push(0.0);
+        self.vars() - 1
+    }
+
+    /// Requires one of `clause`, whose variables must exist, to be true.
+    pub fn add_clause(&mut self, mut clause: Vec<Lit>) {
+        self.backjump(0);
+        clause.sort();
+        clause.dedup();
+        if clause.windows(2).any(|pair| pair[0] == !pair[1])
+            || clause.iter().any(|&lit| self.value(lit) == Some(true))
+        {
+            return;
+        }
+        clause.retain(|&lit| self.value(lit).is_none());
+        match clause.len() {
+            0 => self.unsatisfiable = true,
+            1 => {
+                self.assign(clause[0], None);
+                if self.propagate().is_some() {
+                    self.unsatisfiable = true;
+                }
+            }
+            _ => {
+                self.attach(clause);
+            }
+        }
+    }
+
+    /// An assignment of every variable satisfying every clause, or `None` if there is none.
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        let mut restart = FIRST_RESTART;
+        let mut conflicts = 0;
+        while !self.unsatisfiable {
+            if let Some(conflict) = self.propagate() {
+                if self.levels.is_empty() {
+                    self.unsatisfiable = true;
+                    break;
+                }
+                let (learnt, level) = self.analyze(conflict);
+                self.backjump(level);
+                let implied = learnt[0];
+                let reason = (learnt.len() > 1).then(|| self.attach(learnt));
+                self.assign(implied, reason);
+                self.activity_increment *= ACTIVITY_GROWTH;
+                conflicts += 1;
+            } else if conflicts >= restart {
+                self.backjump(0);
+                conflicts = 0;
+                restart += restart / 2;
+            } else if let Some(var) = self.decision() {
+                self.levels.push(self.trail.len());
+                self.assign(Lit::new(var, self.phase[var]), None);
+            } else {
+                let model = self.assignment.iter().map(|value| value == &Some(true));
+                return Some(model.collect());
+            }
+        }
+        None
+    }
+
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.assignment[lit.var()].map(|value| value == lit.is_positive())
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.assignment[var] = Some(lit.is_positive());
+        self.phase[var] = lit.is_positive();
+        self.level[var] = self.levels.len();
+        self.reason[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Adds a clause of at least two literals, watching the first two.
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[clause[0].index()].push(index);
+        self.watches[clause[1].index()].push(index);
+        self.clauses.push(clause);
+        index
+    }
+
+    /// Assigns the literals of clauses that become unit until none do, and returns a clause
+    /// that became false if one does. A clause that implies a literal has it first.
+    fn propagate(&mut self) -> Option<usize> {
+        while let Some(&lit) = self.trail.get(self.propagated) {
+            self.propagated += 1;
+            let false_lit = !lit;
+            let mut watching = std::mem::take(&mut self.watches[false_lit.index()]);
+            let mut conflict = None;
+            let mut i = 0;
+            while i < watching.len() {
+                let index = watching[i];
+                let clause = &mut self.clauses[index];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.assignment[first.var()] == Some(first.is_positive()) {
+                    i += 1;
+                    continue;
+                }
+                let assignment = &self.assignment;
+                let unassigned_or_true =
+                    |lit: Lit| assignment[lit.var()] != Some(!lit.is_positive());
+                if let Some(k) = (2..clause.len()).find(|&k| unassigned_or_true(clause[k])) {
+                    clause.swap(1, k);
+                    self.watches[clause[1].index()].push(index);
+                    watching.swap_remove(i);
+                    continue;
+                }
+                i += 1;
+                if self.value(first) == Some(false) {
+                    conflict = Some(index);
+                    break;
+                }
+                self.assign(first, Some(index));
+            }
+            self.watches[false_lit.index()] = watching;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    /// The clause learnt from `conflict`, with the literal it implies first and one from the
+    /// level to jump back to second, and that level.
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let current = self.levels.len();
+        let mut seen = vec![false; self.vars()];
+        let mut learnt = vec![Lit::new(0, true)];
+        // The literals of the current level still to resolve.
+        let mut pending = 0;
+        let mut clause = conflict;
+        let mut skip = 0;
+        let mut position = self.trail.len();
+        loop {
+            for k in skip..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                let var = lit.var();
+                if seen[var] || self.level[var] == 0 {
+                    continue;
+                }
+                seen[var] = true;
+                self.bump(var);
+                if self.level[var] == current {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+            let lit = loop {
+                position -= 1;
+                if seen[self.trail[position].var()] {
+                    break self.trail[position];
+                }
+            };
+            pending -= 1;
+            if pending == 0 {
+                learnt[0] = !lit;
+                break;
+            }
+            clause = self.reason[lit.var()].expect("only the decision of a level has no reason");
+            skip = 1;
+        }
+        let mut level = 0;
+        for k in 1..learnt.len() {
+            if self.level[learnt[k].var()] > level {
+                level = self.level[learnt[k].var()];
+                learnt.swap(1, k);
+            }
+        }
+        (learnt, level)
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.activity_increment;
+        if self.activity[var] > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.activity_increment *= 1e-100;
+        }
+    }
+
+    /// The unassigned variable with the highest activity.
+    fn decision(&self) -> Option<usize> {
+        (0..self.vars())
+            .filter(|&var| self.assignment[var].is_none())
+            .max_by(|&a, &b| {
+                self.activity[a]
+                    .total_cmp(&self.activity[b])
+                    .then(b.cmp(&a))
+            })
+    }
+
+    /// Undoes the assignments made after decision level `level`.
+    fn backjump(&mut self, level: usize) {
+        if self.levels.len() <= level {
+            return;
+        }
+        let start = self.levels[level];
+        for lit in self.trail.drain(start..) {
+            self.assignment[lit.var()] = None;
+        }
+        self.levels.truncate(level);
+        self.propagated = start;
+    }
+}
+
+/// An assignment of the inputs of `formula` that makes it true, or `None` if there is none.
+pub fn solve(formula: &Formula) -> Option<Vec<bool>> {
+    let mut model = Solver::new(&formula.cnf).solve()?;
+    model.truncate(formula.inputs.len());
+    Some(model)
+}
+
+/// Every assignment of the inputs of `formula` that makes it true, in truth table order. Fails
+/// with an error pointing at `span` if there are more than [`MAX_SOLUTIONS`].
+pub fn all_solutions(formula: &Formula, span: Span) -> Result<Vec<Vec<bool>>, EvalErr> {
+    let inputs = formula.inputs.len();
+    let mut solver = Solver::new(&formula.cnf);
+    let mut solutions = Vec::new();
+    while let Some(mut model) = solver.solve() {
+        if solutions.len() == MAX_SOLUTIONS {
+            return Err(EvalErr::TooManySolutions(span));
+        }
+        model.truncate(inputs);
+        // Some input must differ from this solution in the next.
+        let blocking = model
+            .iter()
+            .enumerate()
+            .map(|(var, &value)| Lit::new(var, !value))
+            .collect();
+        solver.add_clause(blocking);
+        solutions.push(model);
+    }
+    solutions.sort();
+    Ok(solutions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `pigeons` pigeons each in one of `holes` holes, no two in the same hole. Variable
+    /// `p * holes + h` is pigeon `p` being in hole `h`.
+    fn pigeonhole(pigeons: usize, holes: usize) -> Cnf {
+        let var = |p: usize, h: usize| p * holes + h;
+        let mut clauses: Vec<Vec<Lit>> = (0..pigeons)
+            .map(|p| (0..holes).map(|h| Lit::new(var(p, h), true)).collect())
+            .collect();
+        for h in 0..holes {
+            for p in 0..pigeons {
+                for q in p + 1..pigeons {
+                    clauses.push(vec![Lit::new(var(p, h), false), Lit::new(var(q, h), false)]);
+                }
+            }
+        }
+        Cnf {
+            vars: pigeons * holes,
+            clauses,
+        }
+    }
+
+    fn satisfies(cnf: &Cnf, model: &[bool]) -> bool {
+        cnf.clauses.iter().all(|clause| {
+            clause
+                .iter()
+                .any(|lit| model[lit.var()] == lit.is_positive())
+        })
+    }
+
+    #[test]
+    fn sat_refutes_pigeonhole() {
+        for holes in 1..=6 {
+            assert_eq!(None, Solver::new(&pigeonhole(holes + 1, holes)).solve());
+            let cnf = pigeonhole(holes, holes);
+            let model = Solver::new(&cnf).solve().expect("as many holes as pigeons");
+            assert!(satisfies(&cnf, &model));
+        }
+    }
+
+    #[test]
+    fn sat_handles_units_and_empty_clauses() {
+        let (a, b) = (Lit::new(0, true), Lit::new(1, true));
+        let mut solver = Solver::new(&Cnf {
+            vars: 2,
+            clauses: vec![vec![!a], vec![a, b], vec![a, a, !b, b]],
+        });
+        assert_eq!(Some(vec![false, true]), solver.solve());
+        solver.add_clause(vec![!b]);
+        assert_eq!(None, solver.solve());
+        let empty = Cnf {
+            vars: 1,
+            clauses: vec![Vec::new()],
+        };
+        assert_eq!(None, Solver::new(&empty).solve());
+        assert_eq!(Some(Vec::new()), Solver::new(&Cnf::default()).solve());
+    }
+
+    #[test]
+    fn sat_solves_chains_of_implications() {
+        // x0 -> x1 -> ... -> x49, x49 -> not x0, together with x0 or x25: only x25..x49 can be
+        // true, which takes learning that x0 is false.
+        let n = 50;
+        let x = |i: usize| Lit::new(i, true);
+        let mut clauses: Vec<Vec<Lit>> = (0..n - 1).map(|i| vec![!x(i), x(i + 1)]).collect();
+        clauses.push(vec![!x(n - 1), !x(0)]);
+        clauses.push(vec![x(0), x(25)]);
+        let cnf = Cnf { vars: n, clauses };
+        let model = Solver::new(&cnf).solve().expect("satisfiable");
+        assert!(satisfies(&cnf, &model));
+        assert!(!model[0] && model[25]);
+    }
+
+    #[test]
+    fn sat_enumerates_solutions() {
+        let (a, b, c) = (Lit::new(0, true), Lit::new(1, true), Lit::new(2, true));
+        // Exactly one of a, b and c.
+        let cnf = Cnf {
+            vars: 3,
+            clauses: vec![vec![a, b, c], vec![!a, !b], vec![!a, !c], vec![!b, !c]],
+        };
+        let formula = Formula {
+            inputs: vec!["a".into(), "b".into(), "c".into()],
+            cnf,
+        };
+        assert_eq!(
+            vec![
+                vec![false, false, true],
+                vec![false, true, false],
+                vec![true, false, false]
+            ],
+            all_solutions(&formula, Span::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn sat_limits_solutions() {
+        // Any but the all-false assignment of 13 inputs, so 8191 solutions.
+        let inputs: Vec<String> = (0..13).map(|i| format!("x{i}")).collect();
+        let cnf = Cnf {
+            vars: inputs.len(),
+            clauses: vec![(0..inputs.len()).map(|var| Lit::new(var, true)).collect()],
+        };
+        let formula = Formula { inputs, cnf };
+        let span = Span::new(0, 1, 1, 1);
+        assert_eq!(
+            Err(EvalErr::TooManySolutions(span)),
+            all_solutions(&formula, span)
+        );
+        assert_eq!(
+            "the formula has too many solutions to list, the limit is 4096",
+            EvalErr::TooManySolutions(span).to_string()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Identifies source code by a hash of its text, so that spans taken from different source code,
+/// such as two lines of the REPL, can be told apart. The default id is that of no source.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceId(u64);
+
+impl SourceId {
+    pub fn of(source: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+/// A region of source code: a byte range plus the line and column where it starts.
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub col: usize,
+    /// The source code the span was taken from.
+    pub source: SourceId,
+}
+
+impl Span {
+    /// Creates a span in no source. See [`Span::in_source`].
+    pub fn new(start: usize, end: usize, line: usize, col: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            col,
+            source: SourceId::default(),
+        }
+    }
+
+    /// Returns the same region of the source code `source`.
+    pub fn in_source(self, source: SourceId) -> Self {
+        Self { source, ..self }
+    }
+
+    /// Returns a span covering both `self` and `other`, where `other` ends after `self`.
+    pub fn to(self, other: Span) -> Self {
+        Self {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+
+    /// Whether the span was taken from `source`, rather than from other source code such as the
+    /// body of a lambda defined on an earlier REPL line.
+    pub fn is_in(&self, source: &str) -> bool {
+        if self.source != SourceId::of(source) {
+            return false;
+        }
+        let Some(before) = source.get(..self.start) else {
+            return false;
+        };
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().map_or(0, |s| s.chars().count()) + 1;
+        self.end <= source.len() && (line, col) == (self.line, self.col)
+    }
+
+    /// Renders the source line containing the span with a caret underline.
+    ///
+    /// ```text
+    /// 1 | (& T x)
+    ///   |      ^
+    /// ```
+    pub fn highlight(&self, source: &str) -> String {
+        let line = source
+            .lines()
+            .nth(self.line.saturating_sub(1))
+            .unwrap_or("");
+        let number = self.line.max(1).to_string();
+        let indent = " ".repeat(number.len());
+        let offset = self.col.saturating_sub(1);
+        let rest = line.chars().count().saturating_sub(offset);
+        let width = source
+            .get(self.start..self.end)
+            .map_or(0, |s| s.chars().count())
+            .clamp(1, rest.max(1));
+        format!(
+            "{number} | {line}\n{indent} | {}{}",
+            " ".repeat(offset),
+            "^".repeat(width)
+        )
+    }
+}
+
+/// Spans are equal when they cover the same region, whatever source code they were taken from.
+impl PartialEq for Span {
+    fn eq(&self, other: &Self) -> bool {
+        (self.start, self.end, self.line, self.col)
+            == (other.start, other.end, other.line, other.col)
+    }
+}
+
+impl Eq for Span {}
+
+impl Default for Span {
+    /// An empty span at the beginning of the source.
+    fn default() -> Self {
+        Self::new(0, 0, 1, 1)
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span_highlight_single_line() {
+        let span = Span::new(5, 6, 1, 6);
+        assert_eq!("1 | (& T x)\n  |      ^", span.highlight("(& T x)"));
+    }
+
+    #[test]
+    fn span_highlight_multi_line() {
+        let source = "(if T\n  (& T abc)\n  F)";
+        let span = Span::new(13, 16, 2, 8);
+        assert_eq!("2 |   (& T abc)\n  |        ^^^", span.highlight(source));
+    }
+
+    #[test]
+    fn span_is_in_source() {
+        let source = "(if T\n  (& T abc)\n  F)";
+        let id = SourceId::of(source);
+        assert!(Span::new(13, 16, 2, 8).in_source(id).is_in(source));
+        assert!(Span::new(22, 22, 3, 5).in_source(id).is_in(source));
+        assert!(Span::default().in_source(SourceId::of("")).is_in(""));
+        assert!(!Span::new(13, 16, 2, 8).is_in(source));
+        assert!(!Span::new(13, 16, 2, 8)
+            .in_source(id)
+            .is_in("(if T\n  (& T xyz)\n  F)"));
+        assert!(!Span::new(13, 16, 1, 14).in_source(id).is_in(source));
+        assert!(!Span::new(29, 30, 1, 30).in_source(id).is_in("(deep T)"));
+    }
+
+    #[test]
+    fn span_highlight_end_of_input() {
+        let span = Span::new(6, 6, 1, 7);
+        assert_eq!("1 | (& T F\n  |       ^", span.highlight("(& T F"));
+    }
+}
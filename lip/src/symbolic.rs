@@ -0,0 +1,967 @@
+//! Symbolic evaluation: running a lambda on every input at once.
+//!
+//! The inputs are bools that stand for unknown values, and every bool computed from them is a
+//! boolean function of the inputs, represented by a [`Logic`] such as a [`crate::bdd::Bdd`].
+//! Everything else, such as list lengths, indices and which lambda is called, must not depend
+//! on the inputs. An `if` whose condition does depend on them evaluates both branches and
+//! merges their results.
+//!
+//! This is a subset of the language, and anything that does not depend on the inputs is left
+//! to the [`evaluator`]: a lambda defined outside the function being evaluated, called with
+//! arguments that are all known, is applied by it, as are builtins. Symbolically, lambdas,
+//! operators, `if`, `cond`, the `let` forms, macro calls and the list and bit vector builtins
+//! are supported. `def`, `defmacro` and `import` are not, nor are the other builtins with
+//! arguments that depend on the inputs.
+//!
+//! Every evaluation nests, tail calls included, so evaluations share the limits of the
+//! evaluator: [`RECURSION_LIMIT`] nested evaluations, or less where the stack would grow too
+//! large.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    bits::Bits,
+    builtin::{self, Builtin},
+    environment::Environment,
+    evaluator::{self, EvalErr, Lambda, StackBase, Value, RECURSION_LIMIT},
+    parser::{self, Expr, ExprKind, LetKind, Operator, Params, Pattern},
+    span::Span,
+};
+
+/// A representation of boolean functions of the inputs.
+pub trait Logic {
+    /// A boolean function of the inputs.
+    type Bit: Copy + Eq;
+
+    fn constant(&mut self, b: bool) -> Self::Bit;
+
+    /// The value of `bit` if it is known not to depend on the inputs.
+    fn as_constant(&self, bit: Self::Bit) -> Option<bool>;
+
+    /// The value of `bit` when every input is false, used to show symbolic values in errors.
+    fn sample(&self, bit: Self::Bit) -> bool;
+
+    fn not(&mut self, a: Self::Bit) -> Self::Bit;
+
+    fn and(&mut self, a: Self::Bit, b: Self::Bit) -> Self::Bit;
+
+    fn or(&mut self, a: Self::Bit, b: Self::Bit) -> Self::Bit {
+        let (a, b) = (self.not(a), self.not(b));
+        let and = self.and(a, b);
+        self.not(and)
+    }
+
+    fn xor(&mut self, a: Self::Bit, b: Self::Bit) -> Self::Bit {
+        let (not_a, not_b) = (self.not(a), self.not(b));
+        let (left, right) = (self.and(a, not_b), self.and(not_a, b));
+        self.or(left, right)
+    }
+
+    /// "If `cond` then `then` else `other`".
+    fn ite(&mut self, cond: Self::Bit, then: Self::Bit, other: Self::Bit) -> Self::Bit {
+        let not_cond = self.not(cond);
+        let (then, other) = (self.and(cond, then), self.and(not_cond, other));
+        self.or(then, other)
+    }
+}
+
+/// The value of an expression for every input, mirroring [`Value`].
+#[derive(Debug, Clone)]
+pub enum Sym<B> {
+    Bool(B),
+    /// The bits of a bit vector, least significant first.
+    Bits(Vec<B>),
+    Int(usize),
+    List(Vec<Sym<B>>),
+    /// A lambda with the scope it was made in, which may bind symbolic values.
+    Lambda(Rc<Lambda>, Scope<B>),
+    /// Operators, builtins and macros, which never depend on the inputs.
+    Value(Value),
+}
+
+impl<B: Copy> Sym<B> {
+    /// The value this has when every input is false, to show in errors.
+    pub fn sample(&self, logic: &impl Logic<Bit = B>) -> Value {
+        match self {
+            Sym::Bool(b) => Value::Bool(logic.sample(*b)),
+            Sym::Bits(bits) => {
+                let bits = bits.iter().map(|b| logic.sample(*b)).collect();
+                Value::Bits(Bits::new(bits).expect("bit vectors are not empty"))
+            }
+            Sym::Int(int) => Value::Int(*int),
+            Sym::List(syms) => Value::List(syms.iter().map(|s| s.sample(logic)).collect()),
+            Sym::Lambda(lambda, _) => Value::Lambda(lambda.clone()),
+            Sym::Value(value) => value.clone(),
+        }
+    }
+}
+
+/// A scope binding symbolic values, nested in an [`Environment`] for the names defined outside
+/// the lambda being evaluated.
+#[derive(Debug)]
+pub struct Scope<B>(Rc<Frame<B>>);
+
+#[derive(Debug)]
+struct Frame<B> {
+    vars: RefCell<HashMap<String, Sym<B>>>,
+    parent: Option<Scope<B>>,
+    env: Environment,
+}
+
+impl<B> Clone for Scope<B> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<B: Clone> Scope<B> {
+    fn new(env: Environment) -> Self {
+        Self(Rc::new(Frame {
+            vars: RefCell::new(HashMap::new()),
+            parent: None,
+            env,
+        }))
+    }
+
+    fn child(&self) -> Self {
+        Self(Rc::new(Frame {
+            vars: RefCell::new(HashMap::new()),
+            parent: Some(self.clone()),
+            env: self.0.env.clone(),
+        }))
+    }
+
+    fn add(&self, var: String, value: Sym<B>) {
+        self.0.vars.borrow_mut().insert(var, value);
+    }
+
+    /// Looks up `var` in the symbolic scopes, leaving out the environment.
+    fn get(&self, var: &str) -> Option<Sym<B>> {
+        match self.0.vars.borrow().get(var) {
+            Some(value) => Some(value.clone()),
+            None => self.0.parent.as_ref()?.get(var),
+        }
+    }
+
+    /// Whether this scope binds nothing itself, so that it is only its environment.
+    fn is_env(&self) -> bool {
+        self.0.parent.is_none() && self.0.vars.borrow().is_empty()
+    }
+}
+
+/// Calls `callee` with `args`, bools that may depend on the inputs. Errors point at the whole
+/// call at `span`.
+pub fn apply<L: Logic>(
+    logic: &mut L,
+    callee: &Value,
+    args: Vec<L::Bit>,
+    span: Span,
+) -> Result<Sym<L::Bit>, EvalErr> {
+    let _base = StackBase::enter();
+    let mut evaluator = Evaluator { logic };
+    let callee = evaluator.lift(callee.clone());
+    let args = args.into_iter().map(Sym::Bool).collect();
+    evaluator.call(callee, args, span, 0)
+}
+
+/// Evaluates `expr` in `env` with the names in `inputs` bound to bools that may depend on the
+/// inputs.
+pub fn eval<L: Logic>(
+    logic: &mut L,
+    expr: &Expr,
+    env: &Environment,
+    inputs: Vec<(String, L::Bit)>,
+) -> Result<Sym<L::Bit>, EvalErr> {
+    let _base = StackBase::enter();
+    let scope = Scope::new(env.clone()).child();
+    for (name, input) in inputs {
+        scope.add(name, Sym::Bool(input));
+    }
+    Evaluator { logic }.eval(expr, &scope, 0)
+}
+
+struct Evaluator<'l, L> {
+    logic: &'l mut L,
+}
+
+type Result<T, E = EvalErr> = std::result::Result<T, E>;
+
+impl<L: Logic> Evaluator<'_, L> {
+    /// Turns a value into the symbolic value that is it for every input.
+    fn lift(&mut self, value: Value) -> Sym<L::Bit> {
+        match value {
+            Value::Bool(b) => Sym::Bool(self.logic.constant(b)),
+            Value::Bits(bits) => Sym::Bits(bits.iter().map(|b| self.logic.constant(b)).collect()),
+            Value::Int(int) => Sym::Int(int),
+            Value::List(values) => Sym::List(values.into_iter().map(|v| self.lift(v)).collect()),
+            Value::Lambda(lambda) => {
+                let scope = Scope::new(lambda.env.clone());
+                Sym::Lambda(lambda, scope)
+            }
+            value => Sym::Value(value),
+        }
+    }
+
+    /// Turns a symbolic value back into a value if it does not depend on the inputs.
+    fn lower(&self, sym: &Sym<L::Bit>) -> Option<Value> {
+        Some(match sym {
+            Sym::Bool(b) => Value::Bool(self.logic.as_constant(*b)?),
+            Sym::Bits(bits) => {
+                let bits = bits.iter().map(|b| self.logic.as_constant(*b));
+                Value::Bits(Bits::new(bits.collect::<Option<_>>()?)?)
+            }
+            Sym::Int(int) => Value::Int(*int),
+            Sym::List(syms) => {
+                Value::List(syms.iter().map(|s| self.lower(s)).collect::<Option<_>>()?)
+            }
+            Sym::Lambda(lambda, scope) if scope.is_env() => Value::Lambda(lambda.clone()),
+            Sym::Lambda(..) => return None,
+            Sym::Value(value) => value.clone(),
+        })
+    }
+
+    fn sample(&self, sym: &Sym<L::Bit>) -> Box<Value> {
+        Box::new(sym.sample(self.logic))
+    }
+
+    fn as_bool(&self, sym: Sym<L::Bit>, span: Span) -> Result<L::Bit> {
+        match sym {
+            Sym::Bool(b) => Ok(b),
+            sym => Err(EvalErr::TypeMismatch("bool", self.sample(&sym), span)),
+        }
+    }
+
+    /// Checks that `sym` is a bit vector of `width` bits.
+    fn as_bits(&self, sym: Sym<L::Bit>, width: usize, span: Span) -> Result<Vec<L::Bit>> {
+        match sym {
+            Sym::Bits(bits) if bits.len() == width => Ok(bits),
+            Sym::Bits(bits) => Err(EvalErr::WidthMismatch(width, bits.len(), span)),
+            sym => Err(EvalErr::TypeMismatch("bits", self.sample(&sym), span)),
+        }
+    }
+
+    /// Evaluates `expr` nested `depth` evaluations deep. The larger forms are evaluated in
+    /// separate functions to keep this frame, which is on the stack once per nested evaluation,
+    /// small.
+    fn eval(&mut self, expr: &Expr, scope: &Scope<L::Bit>, depth: usize) -> Result<Sym<L::Bit>> {
+        if depth >= RECURSION_LIMIT || evaluator::stack_exhausted() {
+            return Err(EvalErr::RecursionLimit(expr.span));
+        }
+        let depth = depth + 1;
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Bool(b) => Ok(Sym::Bool(self.logic.constant(*b))),
+            ExprKind::Int(int) => Ok(Sym::Int(*int)),
+            ExprKind::Operator(o) => Ok(Sym::Value(Value::Operator(*o))),
+            ExprKind::Call(operator, operands) => {
+                self.eval_call(operator, operands, scope, depth, span)
+            }
+            ExprKind::If(parser::If { cond, then, other }) => {
+                self.eval_if(cond, then, other, scope, depth, span)
+            }
+            ExprKind::Cond(clauses, other) => {
+                self.eval_cond(clauses, other.as_deref(), scope, depth, span)
+            }
+            ExprKind::Let(kind, bindings, body) => {
+                self.eval_let(*kind, bindings, body, scope, depth)
+            }
+            _ => self.eval_value(expr, scope),
+        }
+    }
+
+    /// Evaluates the expressions that do not evaluate others.
+    fn eval_value(&mut self, expr: &Expr, scope: &Scope<L::Bit>) -> Result<Sym<L::Bit>> {
+        match &expr.kind {
+            ExprKind::Def(..) => Err(EvalErr::NotSymbolic("`def`".to_string(), expr.span)),
+            ExprKind::Defmacro(..) => {
+                Err(EvalErr::NotSymbolic("`defmacro`".to_string(), expr.span))
+            }
+            ExprKind::Import(..) => Err(EvalErr::NotSymbolic("`import`".to_string(), expr.span)),
+            ExprKind::Error => Err(EvalErr::MalformedExpression(expr.span)),
+            ExprKind::Bits(bits) => Ok(self.lift(Value::Bits(bits.clone()))),
+            ExprKind::Ident(ident) => match scope.get(ident) {
+                Some(sym) => Ok(sym),
+                None => scope
+                    .0
+                    .env
+                    .get(ident)
+                    .or_else(|| builtin::primitive(ident))
+                    .map(|value| self.lift(value))
+                    .ok_or_else(|| EvalErr::UnboundIdentifier(ident.to_string(), expr.span)),
+            },
+            ExprKind::Lambda(params, body) => {
+                let lambda = Lambda::new(params.clone(), (**body).clone(), scope.0.env.clone());
+                Ok(Sym::Lambda(Rc::new(lambda), scope.clone()))
+            }
+            _ => unreachable!("`eval` handles the other expressions"),
+        }
+    }
+
+    fn eval_call(
+        &mut self,
+        operator: &Expr,
+        operands: &[Expr],
+        scope: &Scope<L::Bit>,
+        depth: usize,
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        let callee = self.eval(operator, scope, depth)?;
+        match callee {
+            Sym::Value(Value::Operator(o)) => {
+                return self.eval_operator(o, operands, scope, depth, span)
+            }
+            Sym::Value(Value::Macro(definition)) => {
+                let local = |ident: &str| scope.get(ident).is_some();
+                let (expansion, captures) =
+                    definition.expand_in(operands, &scope.0.env, local, span)?;
+                if captures.is_empty() {
+                    return self.eval(&expansion, scope, depth);
+                }
+                let scope = scope.child();
+                for (name, value) in captures {
+                    scope.add(name, self.lift(value));
+                }
+                return self.eval(&expansion, &scope, depth);
+            }
+            Sym::Lambda(..) | Sym::Value(Value::Builtin(_)) => (),
+            sym => return Err(EvalErr::NotCallable(self.sample(&sym), operator.span)),
+        }
+        let mut args = Vec::with_capacity(operands.len());
+        for operand in operands {
+            args.push(self.eval(operand, scope, depth)?);
+        }
+        self.call(callee, args, span, depth)
+    }
+
+    fn eval_if(
+        &mut self,
+        cond: &Expr,
+        then: &Expr,
+        other: &Expr,
+        scope: &Scope<L::Bit>,
+        depth: usize,
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        let value = self.eval(cond, scope, depth)?;
+        let value = self.as_bool(value, cond.span)?;
+        self.branch(
+            value,
+            span,
+            |this| this.eval(then, scope, depth),
+            |this| this.eval(other, scope, depth),
+        )
+    }
+
+    fn eval_let(
+        &mut self,
+        kind: LetKind,
+        bindings: &[(Pattern, Expr)],
+        body: &Expr,
+        scope: &Scope<L::Bit>,
+        depth: usize,
+    ) -> Result<Sym<L::Bit>> {
+        let mut inner = scope.child();
+        for (pattern, expr) in bindings {
+            let value = match kind {
+                LetKind::Let => self.eval(expr, scope, depth)?,
+                LetKind::LetStar => {
+                    let value = self.eval(expr, &inner, depth)?;
+                    inner = inner.child();
+                    value
+                }
+                LetKind::Letrec => self.eval(expr, &inner, depth)?,
+            };
+            self.destructure(pattern, value, &inner, expr.span)?;
+        }
+        self.eval(body, &inner, depth)
+    }
+
+    /// Evaluates `then` or `other` depending on `cond`, or both and merges them if `cond`
+    /// depends on the inputs.
+    fn branch(
+        &mut self,
+        cond: L::Bit,
+        span: Span,
+        then: impl FnOnce(&mut Self) -> Result<Sym<L::Bit>>,
+        other: impl FnOnce(&mut Self) -> Result<Sym<L::Bit>>,
+    ) -> Result<Sym<L::Bit>> {
+        match self.logic.as_constant(cond) {
+            Some(true) => then(self),
+            Some(false) => other(self),
+            None => {
+                let (then, other) = (then(self)?, other(self)?);
+                self.merge(cond, then, other, span)
+            }
+        }
+    }
+
+    fn eval_cond(
+        &mut self,
+        clauses: &[(Expr, Expr)],
+        other: Option<&Expr>,
+        scope: &Scope<L::Bit>,
+        depth: usize,
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        let Some(((test, expr), clauses)) = clauses.split_first() else {
+            return match other {
+                Some(other) => self.eval(other, scope, depth),
+                None => Err(EvalErr::NoMatchingClause(span)),
+            };
+        };
+        let cond = self.eval(test, scope, depth)?;
+        let cond = self.as_bool(cond, test.span)?;
+        self.branch(
+            cond,
+            span,
+            |this| this.eval(expr, scope, depth),
+            |this| this.eval_cond(clauses, other, scope, depth, span),
+        )
+    }
+
+    /// The value that is `then` where `cond` is true and `other` where it is false. Both must
+    /// have the same shape, such as bit vectors of the same width.
+    fn merge(
+        &mut self,
+        cond: L::Bit,
+        then: Sym<L::Bit>,
+        other: Sym<L::Bit>,
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        Ok(match (then, other) {
+            (Sym::Bool(a), Sym::Bool(b)) => Sym::Bool(self.logic.ite(cond, a, b)),
+            (Sym::Bits(a), Sym::Bits(b)) if a.len() == b.len() => {
+                let bits = a.into_iter().zip(b);
+                Sym::Bits(bits.map(|(a, b)| self.logic.ite(cond, a, b)).collect())
+            }
+            (Sym::Int(a), Sym::Int(b)) if a == b => Sym::Int(a),
+            (Sym::List(a), Sym::List(b)) if a.len() == b.len() => {
+                let mut merged = Vec::with_capacity(a.len());
+                for (a, b) in a.into_iter().zip(b) {
+                    merged.push(self.merge(cond, a, b, span)?);
+                }
+                Sym::List(merged)
+            }
+            (Sym::Lambda(a, a_scope), Sym::Lambda(b, b_scope))
+                if Rc::ptr_eq(&a, &b) && Rc::ptr_eq(&a_scope.0, &b_scope.0) =>
+            {
+                Sym::Lambda(a, a_scope)
+            }
+            (Sym::Value(a), Sym::Value(b)) if a == b => Sym::Value(a),
+            _ => {
+                let reason = "branches giving values of different shapes depending on the inputs";
+                return Err(EvalErr::NotSymbolic(reason.to_string(), span));
+            }
+        })
+    }
+
+    /// Evaluates a primitive operator call like the evaluator, stopping at a decisive operand
+    /// only if it does not depend on the inputs.
+    fn eval_operator(
+        &mut self,
+        operator: Operator,
+        operands: &[Expr],
+        scope: &Scope<L::Bit>,
+        depth: usize,
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        let first = match operands.first() {
+            Some(first) => Some(self.eval(first, scope, depth)?),
+            None => None,
+        };
+        if let Some(Sym::Bits(first)) = first {
+            let width = first.len();
+            let mut vectors = vec![first];
+            for operand in &operands[1..] {
+                let value = self.eval(operand, scope, depth)?;
+                vectors.push(self.as_bits(value, width, operand.span)?);
+            }
+            return self.apply_bitwise(operator, &vectors, span);
+        }
+        let mut first = first;
+        let mut operand = |this: &mut Self, i: usize| match (i, first.take()) {
+            (0, Some(value)) => this.as_bool(value, operands[0].span),
+            _ => {
+                let value = this.eval(&operands[i], scope, depth)?;
+                this.as_bool(value, operands[i].span)
+            }
+        };
+        let len = operands.len();
+        self.apply_operator(operator, len, &mut operand, span)
+            .map(Sym::Bool)
+    }
+
+    /// Applies a primitive operator to evaluated `args`. Errors point at the whole call.
+    fn apply_values(
+        &mut self,
+        operator: Operator,
+        args: Vec<Sym<L::Bit>>,
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        if let Some(Sym::Bits(first)) = args.first() {
+            let width = first.len();
+            let mut vectors = Vec::with_capacity(args.len());
+            for arg in args {
+                vectors.push(self.as_bits(arg, width, span)?);
+            }
+            return self.apply_bitwise(operator, &vectors, span);
+        }
+        let len = args.len();
+        let mut args = args.into_iter();
+        let mut operand = |this: &mut Self, _| this.as_bool(args.next().expect("an arg"), span);
+        self.apply_operator(operator, len, &mut operand, span)
+            .map(Sym::Bool)
+    }
+
+    fn apply_bitwise(
+        &mut self,
+        operator: Operator,
+        vectors: &[Vec<L::Bit>],
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        let mut bits = Vec::with_capacity(vectors[0].len());
+        for i in 0..vectors[0].len() {
+            let mut operand = |_: &mut Self, j: usize| Ok(vectors[j][i]);
+            bits.push(self.apply_operator(operator, vectors.len(), &mut operand, span)?);
+        }
+        Ok(Sym::Bits(bits))
+    }
+
+    /// Gets operands `0..len` from `operand` in order, and-ing them together until the result
+    /// is false for every input.
+    fn all(
+        &mut self,
+        len: usize,
+        operand: &mut impl FnMut(&mut Self, usize) -> Result<L::Bit>,
+    ) -> Result<L::Bit> {
+        let mut all = self.logic.constant(true);
+        for i in 0..len {
+            if self.logic.as_constant(all) == Some(false) {
+                break;
+            }
+            let value = operand(self, i)?;
+            all = self.logic.and(all, value);
+        }
+        Ok(all)
+    }
+
+    /// Like [`Evaluator::all`] but or-ing the operands until the result is true.
+    fn any(
+        &mut self,
+        len: usize,
+        operand: &mut impl FnMut(&mut Self, usize) -> Result<L::Bit>,
+    ) -> Result<L::Bit> {
+        let mut any = self.logic.constant(false);
+        for i in 0..len {
+            if self.logic.as_constant(any) == Some(true) {
+                break;
+            }
+            let value = operand(self, i)?;
+            any = self.logic.or(any, value);
+        }
+        Ok(any)
+    }
+
+    /// Applies a primitive operator to `len` operands, like the evaluator does to bools.
+    fn apply_operator(
+        &mut self,
+        operator: Operator,
+        len: usize,
+        operand: &mut impl FnMut(&mut Self, usize) -> Result<L::Bit>,
+        span: Span,
+    ) -> Result<L::Bit> {
+        Ok(match operator {
+            Operator::And => self.all(len, operand)?,
+            Operator::Or => self.any(len, operand)?,
+            Operator::Nand => {
+                let all = self.all(len, operand)?;
+                self.logic.not(all)
+            }
+            Operator::Nor => {
+                let any = self.any(len, operand)?;
+                self.logic.not(any)
+            }
+            Operator::Not if len == 1 => {
+                let value = operand(self, 0)?;
+                self.logic.not(value)
+            }
+            Operator::Not => return Err(EvalErr::ArityMismatch(1, len, span)),
+            Operator::Xor => {
+                let mut parity = self.logic.constant(false);
+                for i in 0..len {
+                    let value = operand(self, i)?;
+                    parity = self.logic.xor(parity, value);
+                }
+                parity
+            }
+            Operator::Iff => {
+                let mut values = Vec::with_capacity(len);
+                for i in 0..len {
+                    values.push(operand(self, i)?);
+                }
+                let mut equal = self.logic.constant(true);
+                for pair in values.windows(2) {
+                    let differ = self.logic.xor(pair[0], pair[1]);
+                    let same = self.logic.not(differ);
+                    equal = self.logic.and(equal, same);
+                }
+                equal
+            }
+            Operator::Implies => match len.checked_sub(1) {
+                None => self.logic.constant(true),
+                Some(last) => {
+                    let premises = self.all(last, operand)?;
+                    if self.logic.as_constant(premises) == Some(false) {
+                        return Ok(self.logic.constant(true));
+                    }
+                    let conclusion = operand(self, last)?;
+                    let not_premises = self.logic.not(premises);
+                    self.logic.or(not_premises, conclusion)
+                }
+            },
+        })
+    }
+
+    fn call(
+        &mut self,
+        callee: Sym<L::Bit>,
+        args: Vec<Sym<L::Bit>>,
+        span: Span,
+        depth: usize,
+    ) -> Result<Sym<L::Bit>> {
+        match callee {
+            Sym::Lambda(lambda, scope) => self.call_lambda(lambda, scope, args, span, depth),
+            Sym::Value(Value::Operator(operator)) => self.apply_values(operator, args, span),
+            Sym::Value(Value::Builtin(Builtin::Apply)) => self.call_apply(args, span, depth),
+            Sym::Value(Value::Builtin(builtin)) => self.call_builtin(builtin, args, span),
+            sym => Err(EvalErr::NotCallable(self.sample(&sym), span)),
+        }
+    }
+
+    /// Calls the first of `args` with the rest followed by the elements of the last one.
+    fn call_apply(
+        &mut self,
+        mut args: Vec<Sym<L::Bit>>,
+        span: Span,
+        depth: usize,
+    ) -> Result<Sym<L::Bit>> {
+        match (args.len(), args.pop()) {
+            (2.., Some(Sym::List(list))) => {
+                let callee = args.remove(0);
+                args.extend(list);
+                self.call(callee, args, span, depth)
+            }
+            (2.., Some(sym)) => Err(EvalErr::TypeMismatch("list", self.sample(&sym), span)),
+            (found, _) => Err(EvalErr::ArityMismatch(2, found, span)),
+        }
+    }
+
+    /// Calls `lambda` in `scope` with `args` like the evaluator.
+    fn call_lambda(
+        &mut self,
+        lambda: Rc<Lambda>,
+        scope: Scope<L::Bit>,
+        args: Vec<Sym<L::Bit>>,
+        span: Span,
+        depth: usize,
+    ) -> Result<Sym<L::Bit>> {
+        if scope.is_env() {
+            let values: Option<Vec<Value>> = args.iter().map(|arg| self.lower(arg)).collect();
+            if let Some(values) = values {
+                let value = evaluator::apply(Value::Lambda(lambda), values, span)?;
+                return Ok(self.lift(value));
+            }
+        }
+        let Params { required, rest } = &lambda.params;
+        if args.len() < required.len() || (rest.is_none() && args.len() > required.len()) {
+            return Err(EvalErr::ArityMismatch(required.len(), args.len(), span));
+        }
+        let inner = scope.child();
+        let mut args = args.into_iter();
+        for (param, arg) in required.iter().zip(&mut args) {
+            self.destructure(param, arg, &inner, span)?;
+        }
+        if let Some(rest) = rest {
+            inner.add(rest.clone(), Sym::List(args.collect()));
+        }
+        self.eval(&lambda.body, &inner, depth)
+    }
+
+    /// Binds the identifiers in `pattern` to the matching parts of `value` in `scope`.
+    fn destructure(
+        &mut self,
+        pattern: &Pattern,
+        value: Sym<L::Bit>,
+        scope: &Scope<L::Bit>,
+        span: Span,
+    ) -> Result<()> {
+        let (required, rest) = match pattern {
+            Pattern::Ident(ident) => {
+                scope.add(ident.clone(), value);
+                return Ok(());
+            }
+            Pattern::List(Params { required, rest }) => (required, rest),
+        };
+        let values = match value {
+            Sym::List(values)
+                if values.len() == required.len()
+                    || (rest.is_some() && values.len() > required.len()) =>
+            {
+                values
+            }
+            value => {
+                let pattern = pattern.clone();
+                return Err(EvalErr::PatternMismatch(pattern, self.sample(&value), span));
+            }
+        };
+        let mut values = values.into_iter();
+        for (pattern, value) in required.iter().zip(&mut values) {
+            self.destructure(pattern, value, scope, span)?;
+        }
+        if let Some(rest) = rest {
+            scope.add(rest.clone(), Sym::List(values.collect()));
+        }
+        Ok(())
+    }
+
+    /// Calls a builtin. If no argument depends on the inputs, this is the builtin itself.
+    fn call_builtin(
+        &mut self,
+        builtin: Builtin,
+        args: Vec<Sym<L::Bit>>,
+        span: Span,
+    ) -> Result<Sym<L::Bit>> {
+        let values: Option<Vec<Value>> = args.iter().map(|arg| self.lower(arg)).collect();
+        if let Some(values) = values {
+            let value = builtin.call(values, span)?;
+            return Ok(self.lift(value));
+        }
+        let int = |this: &Self, sym| match sym {
+            Sym::Int(int) => Ok(int),
+            sym => Err(EvalErr::TypeMismatch("int", this.sample(&sym), span)),
+        };
+        let bits = |this: &Self, sym| match sym {
+            Sym::Bits(bits) => Ok(bits),
+            sym => Err(EvalErr::TypeMismatch("bits", this.sample(&sym), span)),
+        };
+        match builtin {
+            Builtin::List => Ok(Sym::List(args)),
+            Builtin::Partial => {
+                let mut args = args.into_iter();
+                let (lambda, scope) = match args.next() {
+                    Some(Sym::Lambda(lambda, scope)) => (lambda, scope),
+                    Some(sym) => {
+                        return Err(EvalErr::TypeMismatch("lambda", self.sample(&sym), span))
+                    }
+                    None => return Err(EvalErr::ArityMismatch(1, 0, span)),
+                };
+                let required = &lambda.params.required;
+                let args: Vec<_> = args.collect();
+                if args.len() > required.len() {
+                    return Err(EvalErr::ArityMismatch(required.len(), args.len(), span));
+                }
+                let inner = scope.child();
+                let bound = args.len();
+                for (param, arg) in required.iter().zip(args) {
+                    self.destructure(param, arg, &inner, span)?;
+                }
+                Ok(partial(&lambda, bound, inner))
+            }
+            Builtin::First | Builtin::Nth => {
+                let (list, index) = if builtin == Builtin::First {
+                    let [list] = builtin::arity(args, span)?;
+                    (list, 0)
+                } else {
+                    let [list, index] = builtin::arity(args, span)?;
+                    (list, int(self, index)?)
+                };
+                let list = match list {
+                    Sym::List(list) => list,
+                    sym => return Err(EvalErr::TypeMismatch("list", self.sample(&sym), span)),
+                };
+                let len = list.len();
+                list.into_iter()
+                    .nth(index)
+                    .ok_or(EvalErr::IndexOutOfRange(index, len, span))
+            }
+            Builtin::Length => {
+                let [list] = builtin::arity(args, span)?;
+                match list {
+                    Sym::List(list) => Ok(Sym::Int(list.len())),
+                    sym => Err(EvalErr::TypeMismatch("list", self.sample(&sym), span)),
+                }
+            }
+            Builtin::Concat => {
+                let mut concatenated = Vec::new();
+                for arg in args.into_iter().rev() {
+                    match arg {
+                        Sym::Bool(b) => concatenated.push(b),
+                        arg => concatenated.extend(bits(self, arg)?),
+                    }
+                }
+                Ok(Sym::Bits(concatenated))
+            }
+            Builtin::Slice => {
+                let [value, hi, lo] = builtin::arity(args, span)?;
+                let (value, hi, lo) = (bits(self, value)?, int(self, hi)?, int(self, lo)?);
+                if hi >= value.len() {
+                    return Err(EvalErr::IndexOutOfRange(hi, value.len(), span));
+                }
+                if lo > hi {
+                    return Err(EvalErr::EmptySlice(hi, lo, span));
+                }
+                Ok(Sym::Bits(value[lo..=hi].to_vec()))
+            }
+            Builtin::Bit => {
+                let [value, index] = builtin::arity(args, span)?;
+                let (value, index) = (bits(self, value)?, int(self, index)?);
+                let width = value.len();
+                value
+                    .get(index)
+                    .map(|&bit| Sym::Bool(bit))
+                    .ok_or(EvalErr::IndexOutOfRange(index, width, span))
+            }
+            Builtin::Shl | Builtin::Shr => {
+                let [value, amount] = builtin::arity(args, span)?;
+                let (value, amount) = (bits(self, value)?, int(self, amount)?);
+                let width = value.len();
+                let amount = amount.min(width);
+                let zero = self.logic.constant(false);
+                let mut shifted = Vec::with_capacity(width);
+                if builtin == Builtin::Shl {
+                    shifted.resize(amount, zero);
+                    shifted.extend_from_slice(&value[..width - amount]);
+                } else {
+                    shifted.extend_from_slice(&value[amount..]);
+                    shifted.resize(width, zero);
+                }
+                Ok(Sym::Bits(shifted))
+            }
+            Builtin::AndReduce | Builtin::OrReduce | Builtin::XorReduce | Builtin::Width => {
+                let [value] = builtin::arity(args, span)?;
+                let value = bits(self, value)?;
+                let mut operand = |_: &mut Self, i: usize| Ok(value[i]);
+                let operator = match builtin {
+                    Builtin::AndReduce => Operator::And,
+                    Builtin::OrReduce => Operator::Or,
+                    Builtin::XorReduce => Operator::Xor,
+                    _ => return Ok(Sym::Int(value.len())),
+                };
+                self.apply_operator(operator, value.len(), &mut operand, span)
+                    .map(Sym::Bool)
+            }
+            builtin => {
+                let reason = format!("`{builtin}` with arguments that depend on the inputs");
+                Err(EvalErr::NotSymbolic(reason, span))
+            }
+        }
+    }
+}
+
+/// `lambda` with its first `bound` parameters bound in `scope`.
+fn partial<B>(lambda: &Lambda, bound: usize, scope: Scope<B>) -> Sym<B> {
+    let Params { required, rest } = &lambda.params;
+    let params = Params::new(required[bound..].to_vec(), rest.clone());
+    let partial = Lambda::new(params, lambda.body.clone(), scope.0.env.clone());
+    Sym::Lambda(Rc::new(partial), scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bdd::Bdd, prelude, repl::eval_str, test_util::TestResult};
+
+    /// Evaluates the lambda `source` symbolically with a BDD and returns the BDD of each input
+    /// assignment's output as a bool per assignment, in truth table order.
+    fn outputs(source: &str, env: &Environment) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let lambda = eval_str(source, env)?.unwrap();
+        let Value::Lambda(l) = &lambda else {
+            panic!("not a lambda");
+        };
+        let n = l.params.required.len();
+        let mut bdd = Bdd::default();
+        let inputs: Vec<_> = (0..n).map(|i| bdd.var(i)).collect();
+        let sym = apply(&mut bdd, &lambda, inputs, Span::default())?;
+        Ok((0..1usize << n)
+            .map(|i| {
+                let assignment: Vec<bool> = (0..n).rev().map(|bit| i >> bit & 1 == 1).collect();
+                concrete(&bdd, &sym, &assignment)
+            })
+            .collect())
+    }
+
+    fn concrete(bdd: &Bdd, sym: &Sym<crate::bdd::Node>, assignment: &[bool]) -> Value {
+        match sym {
+            Sym::Bool(b) => Value::Bool(bdd.evaluate(*b, assignment)),
+            Sym::Bits(bits) => {
+                let bits = bits.iter().map(|b| bdd.evaluate(*b, assignment)).collect();
+                Value::Bits(Bits::new(bits).unwrap())
+            }
+            Sym::Int(int) => Value::Int(*int),
+            Sym::List(syms) => {
+                Value::List(syms.iter().map(|s| concrete(bdd, s, assignment)).collect())
+            }
+            Sym::Lambda(lambda, _) => Value::Lambda(lambda.clone()),
+            Sym::Value(value) => value.clone(),
+        }
+    }
+
+    /// The outputs of calling the lambda `source` with every assignment, in the evaluator.
+    fn expected(source: &str, env: &Environment) -> Result<Vec<Value>, crate::Error> {
+        let table =
+            crate::table::TruthTable::of_value(eval_str(source, env)?.unwrap(), Span::default())?;
+        Ok(table.rows.into_iter().map(|row| row.output).collect())
+    }
+
+    #[test]
+    fn symbolic_matches_evaluator() -> TestResult {
+        let env = prelude::environment(true);
+        for source in [
+            "(lambda (a b) (nand a b))",
+            "(lambda (a b c) (implies a b c))",
+            "(lambda (a b c) (iff a b c))",
+            "(lambda (a b) (if a (list b T) (list F b)))",
+            "(lambda (a b c) (cond (a b) ((^ b) c) (else (xor a c))))",
+            "(lambda (a b c) (let* ((x (& a b)) (y (| x c))) (nor x y)))",
+            "(lambda (s a b) (mux2 s a b))",
+            "full-adder",
+            "decoder2",
+            "(lambda (a b c d) (lt? (concat a b) (concat c d)))",
+            "(lambda (a b c d) (add (concat a b) (concat c d)))",
+            "(lambda (a b) (apply xor-reduce (list (concat a b T))))",
+            "(lambda (a b) (slice (shl (concat a b F) 1) 2 1))",
+            "(lambda (a b c) ((partial (lambda (x y z) (mux2 x y z)) a b) c))",
+        ] {
+            assert_eq!(expected(source, &env)?, outputs(source, &env)?, "{source}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn symbolic_stops_at_constant_operands() -> TestResult {
+        let env = Environment::default();
+        // The undefined operand is never reached, as in the evaluator.
+        let source = "(lambda (a) (| a T (undefined-thing)))";
+        assert_eq!(vec![Value::Bool(true); 2], outputs(source, &env)?);
+        Ok(())
+    }
+
+    #[test]
+    fn symbolic_reports_unsupported_values() {
+        let env = Environment::default();
+        let e = outputs("(lambda (a) (if a (list) T))", &env).unwrap_err();
+        assert_eq!(
+            "symbolic evaluation does not support branches giving values of different shapes \
+             depending on the inputs",
+            e.to_string()
+        );
+        let e = outputs("(lambda (a) (& a 1))", &env).unwrap_err();
+        assert_eq!("expected bool, found `1`", e.to_string());
+        let e = outputs("(lambda (a) (first a))", &env).unwrap_err();
+        assert_eq!("expected list, found `false`", e.to_string());
+        let e = outputs("(lambda (a) (letrec ((f (lambda (x) (f x)))) (f a)))", &env).unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(EvalErr::RecursionLimit(_))));
+        let e = outputs("(lambda (a) (& (def x a) x))", &env).unwrap_err();
+        assert_eq!("symbolic evaluation does not support `def`", e.to_string());
+    }
+}
@@ -0,0 +1,439 @@
+//! Truth tables of lambdas and of expressions with free variables.
+
+use std::rc::Rc;
+
+use crate::{
+    builtin,
+    environment::Environment,
+    evaluator::{self, EvalErr, Lambda, StackBase, Value},
+    macros,
+    parser::{Expr, ExprKind, If, LetKind, Params, Pattern},
+    span::Span,
+};
+
+/// The most inputs a truth table can have, which makes for 65536 rows.
+pub const MAX_INPUTS: usize = 16;
+
+/// The output of a function for every assignment of its boolean inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable {
+    /// The names of the inputs: the parameters of a lambda, or the free variables of an
+    /// expression in the order they are evaluated.
+    pub inputs: Vec<String>,
+    /// One row per assignment, counting up in binary from all false with the first input as
+    /// the most significant bit.
+    pub rows: Vec<Row>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub inputs: Vec<bool>,
+    pub output: Value,
+}
+
+impl Row {
+    /// The output split into columns: the elements of a list, or the output itself.
+    pub fn outputs(&self) -> &[Value] {
+        match &self.output {
+            Value::List(values) => values,
+            value => std::slice::from_ref(value),
+        }
+    }
+}
+
+/// How [`TruthTable::render`] lays out a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Columns aligned with spaces, the inputs separated from the outputs by `|`.
+    Text,
+    Markdown,
+    Csv,
+}
+
+impl TruthTable {
+    /// Tabulates `value`, which must be a lambda. Errors point at `span`.
+    pub fn of_value(value: Value, span: Span) -> Result<Self, EvalErr> {
+        match value {
+            Value::Lambda(lambda) => Self::of_lambda(&lambda, span),
+            value => Err(EvalErr::TypeMismatch("lambda", Box::new(value), span)),
+        }
+    }
+
+    /// Calls `lambda` with every assignment of its parameters, which must be plain names.
+    pub fn of_lambda(lambda: &Rc<Lambda>, span: Span) -> Result<Self, EvalErr> {
+        let inputs = input_names(lambda, span)?;
+        Self::tabulate(inputs, span, |assignment| {
+            let args = assignment.iter().copied().map(Value::Bool).collect();
+            evaluator::apply(Value::Lambda(lambda.clone()), args, span)
+        })
+    }
+
+    /// Evaluates `expr` in `env` with every assignment of its free variables. Without free
+    /// variables, an expression evaluating to a lambda is tabulated as that lambda.
+    pub fn of_expr(expr: &Expr, env: &Environment) -> Result<Self, EvalErr> {
+        let inputs = free_variables(expr, env)?;
+        if inputs.is_empty() {
+            if let Value::Lambda(lambda) = evaluator::eval(expr, env)? {
+                return Self::of_lambda(&lambda, expr.span);
+            }
+        }
+        let names = inputs.clone();
+        Self::tabulate(inputs, expr.span, |assignment| {
+            let scope = env.child();
+            for (name, value) in names.iter().zip(assignment) {
+                scope.add(name.clone(), Value::Bool(*value));
+            }
+            evaluator::eval(expr, &scope)
+        })
+    }
+
+    fn tabulate(
+        inputs: Vec<String>,
+        span: Span,
+        mut output: impl FnMut(&[bool]) -> Result<Value, EvalErr>,
+    ) -> Result<Self, EvalErr> {
+        let n = inputs.len();
+        if n > MAX_INPUTS {
+            return Err(EvalErr::TooManyInputs(n, span));
+        }
+        let mut rows = Vec::with_capacity(1 << n);
+        for i in 0..1usize << n {
+            let inputs: Vec<bool> = (0..n).rev().map(|bit| i >> bit & 1 == 1).collect();
+            let output = output(&inputs)?;
+            rows.push(Row { inputs, output });
+        }
+        Ok(Self { inputs, rows })
+    }
+
+    /// The names of the output columns: `out` for a single output, or `out0`, `out1` and so on
+    /// for the elements of list outputs.
+    pub fn outputs(&self) -> Vec<String> {
+        let single = |row: &Row| !matches!(row.output, Value::List(_));
+        if self.rows.iter().all(single) {
+            return vec!["out".to_string()];
+        }
+        let columns = self.rows.iter().map(|row| row.outputs().len()).max();
+        (0..columns.unwrap_or(0))
+            .map(|i| format!("out{i}"))
+            .collect()
+    }
+
+    /// Renders the table in `format`, writing bools as `T` and `F`. Each line ends with `\n`.
+    pub fn render(&self, format: Format) -> String {
+        let outputs = self.outputs();
+        let header: Vec<String> = self.inputs.iter().cloned().chain(outputs).collect();
+        let mut lines = vec![header];
+        for row in &self.rows {
+            let inputs = row.inputs.iter().map(|&b| Value::Bool(b));
+            let mut cells: Vec<String> = inputs
+                .chain(row.outputs().iter().cloned())
+                .map(|value| cell(&value))
+                .collect();
+            cells.resize(lines[0].len(), String::new());
+            lines.push(cells);
+        }
+        let widths: Vec<usize> = (0..lines[0].len())
+            .map(|i| {
+                lines
+                    .iter()
+                    .map(|cells| cells[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let pad = |cells: &[String]| -> Vec<String> {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:width$}"))
+                .collect()
+        };
+        let inputs = self.inputs.len();
+        let mut out = String::new();
+        for (i, cells) in lines.iter().enumerate() {
+            let line = match format {
+                Format::Text => {
+                    let cells = pad(cells);
+                    let (ins, outs) = cells.split_at(inputs);
+                    [ins.join(" "), outs.join(" ")]
+                        .into_iter()
+                        .filter(|group| !group.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                }
+                Format::Markdown => format!("| {} |", pad(cells).join(" | ")),
+                Format::Csv => cells
+                    .iter()
+                    .map(|cell| csv(cell))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            };
+            out.push_str(line.trim_end());
+            out.push('\n');
+            if i > 0 {
+                continue;
+            }
+            match format {
+                Format::Text => {
+                    let dashes: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+                    let (ins, outs) = dashes.split_at(inputs);
+                    let groups = [ins.join("-"), outs.join("-")];
+                    let groups = groups.into_iter().filter(|group| !group.is_empty());
+                    out.push_str(&groups.collect::<Vec<_>>().join("-+-"));
+                    out.push('\n');
+                }
+                Format::Markdown => {
+                    let dashes: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+                    out.push_str(&format!("|-{}-|\n", dashes.join("-|-")));
+                }
+                Format::Csv => (),
+            }
+        }
+        out
+    }
+}
+
+/// The parameters of `lambda`, which must all be plain names, with no rest parameter.
+pub(crate) fn input_names(lambda: &Rc<Lambda>, span: Span) -> Result<Vec<String>, EvalErr> {
+    let Params { required, rest } = &lambda.params;
+    let mut inputs = Vec::with_capacity(required.len());
+    for param in required {
+        match (param, rest) {
+            (Pattern::Ident(ident), None) => inputs.push(ident.clone()),
+            _ => {
+                let value = Value::Lambda(lambda.clone());
+                return Err(EvalErr::TypeMismatch(
+                    "lambda with named parameters",
+                    Box::new(value),
+                    span,
+                ));
+            }
+        }
+    }
+    Ok(inputs)
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Bool(true) => "T".to_string(),
+        Value::Bool(false) => "F".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break.
+fn csv(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// The identifiers in `expr` that are neither bound in `env`, bound locally by a `lambda` or a
+/// `let`, nor builtins, in the order they are evaluated. Macro calls are expanded first.
+pub fn free_variables(expr: &Expr, env: &Environment) -> Result<Vec<String>, EvalErr> {
+    let _base = StackBase::enter();
+    let mut free = Vec::new();
+    collect_free(expr, env, &mut Vec::new(), &mut free, 0)?;
+    Ok(free)
+}
+
+fn collect_free(
+    expr: &Expr,
+    env: &Environment,
+    bound: &mut Vec<String>,
+    free: &mut Vec<String>,
+    depth: usize,
+) -> Result<(), EvalErr> {
+    // Macro expansions can nest deeper than parsed expressions, or forever.
+    if depth >= evaluator::RECURSION_LIMIT || evaluator::stack_exhausted() {
+        return Err(EvalErr::RecursionLimit(expr.span));
+    }
+    let depth = depth + 1;
+    let outer = bound.len();
+    match &expr.kind {
+        ExprKind::Ident(ident)
+            if !bound.contains(ident)
+                && !free.contains(ident)
+                && env.get(ident).is_none()
+                && builtin::primitive(ident).is_none() =>
+        {
+            free.push(ident.clone());
+        }
+        ExprKind::Call(operator, operands) => {
+            if let ExprKind::Ident(ident) = &operator.kind {
+                if let (false, Some(Value::Macro(definition))) =
+                    (bound.contains(ident), env.get(ident))
+                {
+                    let local = |ident: &str| bound.contains(&ident.to_string());
+                    let (expansion, captures) =
+                        definition.expand_in(operands, env, local, expr.span)?;
+                    return collect_free(&expansion, &env.overlay(captures), bound, free, depth);
+                }
+            }
+            collect_free(operator, env, bound, free, depth)?;
+            for operand in operands {
+                collect_free(operand, env, bound, free, depth)?;
+            }
+        }
+        ExprKind::If(If { cond, then, other }) => {
+            for expr in [cond, then, other] {
+                collect_free(expr, env, bound, free, depth)?;
+            }
+        }
+        ExprKind::Def(ident, value) => {
+            collect_free(value, env, bound, free, depth)?;
+            // Later references see the definition.
+            bound.push(ident.clone());
+            return Ok(());
+        }
+        ExprKind::Lambda(params, body) => {
+            macros::params_binders(params, bound);
+            collect_free(body, env, bound, free, depth)?;
+        }
+        ExprKind::Let(kind, bindings, body) => {
+            if *kind == LetKind::Letrec {
+                for (pattern, _) in bindings {
+                    macros::pattern_binders(pattern, bound);
+                }
+            }
+            let mut patterns = Vec::new();
+            for (pattern, value) in bindings {
+                collect_free(value, env, bound, free, depth)?;
+                match kind {
+                    LetKind::Let => macros::pattern_binders(pattern, &mut patterns),
+                    LetKind::LetStar => macros::pattern_binders(pattern, bound),
+                    LetKind::Letrec => (),
+                }
+            }
+            bound.extend(patterns);
+            collect_free(body, env, bound, free, depth)?;
+        }
+        ExprKind::Cond(clauses, other) => {
+            for (test, expr) in clauses {
+                collect_free(test, env, bound, free, depth)?;
+                collect_free(expr, env, bound, free, depth)?;
+            }
+            if let Some(other) = other {
+                collect_free(other, env, bound, free, depth)?;
+            }
+        }
+        _ => (),
+    }
+    bound.truncate(outer);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, prelude, test_util::TestResult, tokenizer};
+
+    fn table(source: &str, env: &Environment) -> Result<TruthTable, Box<dyn std::error::Error>> {
+        let expr = parser::parse(&tokenizer::tokenize(source)?)?;
+        Ok(TruthTable::of_expr(&expr, env)?)
+    }
+
+    #[test]
+    fn table_of_expression_enumerates_free_variables() -> TestResult {
+        let env = Environment::default();
+        assert_eq!(vec!["a", "b"], table("(xor a b)", &env)?.inputs);
+        let table = table("(if c (& a b) (let ((x a)) (| x d)))", &env)?;
+        assert_eq!(vec!["c", "a", "b", "d"], table.inputs);
+        assert_eq!(16, table.rows.len());
+        assert_eq!(vec![false, false, false, true], table.rows[1].inputs);
+        assert_eq!(Value::Bool(true), table.rows[1].output);
+        assert_eq!(vec![true, true, false, true], table.rows[13].inputs);
+        assert_eq!(Value::Bool(false), table.rows[13].output);
+        Ok(())
+    }
+
+    #[test]
+    fn table_of_lambda_uses_parameters() -> TestResult {
+        let env = prelude::environment(true);
+        let table = table("half-adder", &env)?;
+        assert_eq!(vec!["a", "b"], table.inputs);
+        assert_eq!(vec!["out0", "out1"], table.outputs());
+        let outputs: Vec<&[Value]> = table.rows.iter().map(Row::outputs).collect();
+        let bools = |values: &[bool]| values.iter().map(|&b| Value::Bool(b)).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                bools(&[false, false]),
+                bools(&[true, false]),
+                bools(&[true, false]),
+                bools(&[false, true])
+            ],
+            outputs
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn table_rejects_unsupported_functions() {
+        let env = Environment::default();
+        let e = table("(lambda (a . b) a)", &env).unwrap_err();
+        assert_eq!(
+            "expected lambda with named parameters, found `(lambda (a . b) a)`",
+            e.to_string()
+        );
+        let many: Vec<String> = (0..=MAX_INPUTS).map(|i| format!("x{i}")).collect();
+        let e = table(&format!("(& {})", many.join(" ")), &env).unwrap_err();
+        assert_eq!(
+            "a truth table over 17 inputs is too large, the limit is 16",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn table_rejects_endless_macro_expansion() -> TestResult {
+        let env = Environment::default();
+        let expr = parser::parse(&tokenizer::tokenize("(defmacro forever (x) (forever x))")?)?;
+        evaluator::eval(&expr, &env)?;
+        let e = table("(forever a)", &env).unwrap_err();
+        assert_eq!(
+            EvalErr::RecursionLimit(Span::new(0, 11, 1, 1)).to_string(),
+            e.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn table_renders_formats() -> TestResult {
+        let env = prelude::environment(true);
+        let table = table("(lambda (a carry-in) (half-adder a carry-in))", &env)?;
+        assert_eq!(
+            "a carry-in | out0 out1
+-----------+----------
+F F        | F    F
+F T        | T    F
+T F        | T    F
+T T        | F    T
+",
+            table.render(Format::Text)
+        );
+        assert_eq!(
+            "| a | carry-in | out0 | out1 |
+|---|----------|------|------|
+| F | F        | F    | F    |
+| F | T        | T    | F    |
+| T | F        | T    | F    |
+| T | T        | F    | T    |
+",
+            table.render(Format::Markdown)
+        );
+        assert_eq!(
+            "a,carry-in,out0,out1\nF,F,F,F\nF,T,T,F\nT,F,T,F\nT,T,F,T\n",
+            table.render(Format::Csv)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn table_without_inputs_has_one_row() -> TestResult {
+        let env = Environment::default();
+        let table = table("(& T F)", &env)?;
+        assert_eq!("out\n---\nF\n", table.render(Format::Text));
+        Ok(())
+    }
+}
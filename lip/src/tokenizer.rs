@@ -1,20 +1,55 @@
-#[derive(Debug)]
+use crate::{
+    bits::Bits,
+    span::{SourceId, Span},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenizeErr {
-    Parse(String),
+    /// A word that is neither a keyword, an operator, a literal nor a valid identifier.
+    InvalidToken(String, Span),
+    /// A `#|` without the matching `|#`.
+    UnterminatedComment(Span),
+    /// A `#;` that is not followed by an expression to comment out.
+    DanglingDatumComment(Span),
+    /// A `"` without the matching `"` on the same line.
+    UnterminatedString(Span),
+}
+
+impl TokenizeErr {
+    pub fn span(&self) -> Span {
+        match self {
+            TokenizeErr::InvalidToken(_, span)
+            | TokenizeErr::UnterminatedComment(span)
+            | TokenizeErr::DanglingDatumComment(span)
+            | TokenizeErr::UnterminatedString(span) => *span,
+        }
+    }
 }
 
 impl std::error::Error for TokenizeErr {}
 
 impl std::fmt::Display for TokenizeErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "failed to tokenize")
+        match self {
+            TokenizeErr::InvalidToken(token, _) => write!(f, "invalid token `{token}`"),
+            TokenizeErr::UnterminatedComment(_) => {
+                write!(f, "block comment is not closed with `|#`")
+            }
+            TokenizeErr::DanglingDatumComment(_) => {
+                write!(f, "`#;` is not followed by an expression")
+            }
+            TokenizeErr::UnterminatedString(_) => {
+                write!(f, "string is not closed with `\"` on the same line")
+            }
+        }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Token {
+pub enum TokenKind {
     Lparen,
     Rparen,
+    Dot,
     And,
     Or,
     Not,
@@ -22,16 +57,30 @@ pub enum Token {
     False,
     If,
     Def,
+    Defmacro,
     Lambda,
+    Let,
+    LetStar,
+    Letrec,
+    Cond,
+    Else,
+    Import,
+    /// A string literal such as `"adders.lip"`, without the quotes.
+    Str(String),
+    /// A bit vector literal such as `#b1011`.
+    Bits(Bits),
+    /// A non-negative integer literal, used for bit indices and shift amounts.
+    Int(usize),
     Ident(String),
 }
 
-impl Token {
-    fn parse(str: &str) -> Result<Self, TokenizeErr> {
-        use Token::*;
+impl TokenKind {
+    fn parse(str: &str, span: Span) -> Result<Self, TokenizeErr> {
+        use TokenKind::*;
         match str {
             "(" => Ok(Lparen),
             ")" => Ok(Rparen),
+            "." => Ok(Dot),
             "&" => Ok(And),
             "|" => Ok(Or),
             "^" => Ok(Not),
@@ -39,50 +88,322 @@ impl Token {
             "F" => Ok(False),
             "if" => Ok(If),
             "def" => Ok(Def),
+            "defmacro" => Ok(Defmacro),
             "lambda" => Ok(Lambda),
-            str if str.chars().all(|c| c.is_ascii_lowercase()) => Ok(Ident(str.to_string())),
-            _ => Err(TokenizeErr::Parse(format!("Invalid token `{str}`"))),
+            "let" => Ok(Let),
+            "let*" => Ok(LetStar),
+            "letrec" => Ok(Letrec),
+            "cond" => Ok(Cond),
+            "else" => Ok(Else),
+            "import" => Ok(Import),
+            str if str.starts_with("#b") => crate::bits::Bits::from_digits(&str[2..])
+                .map(Bits)
+                .ok_or_else(|| TokenizeErr::InvalidToken(str.to_string(), span)),
+            str if str.chars().all(|c| c.is_ascii_digit()) => str
+                .parse()
+                .map(Int)
+                .map_err(|_| TokenizeErr::InvalidToken(str.to_string(), span)),
+            str if is_identifier(str) => Ok(Ident(str.to_string())),
+            _ => Err(TokenizeErr::InvalidToken(str.to_string(), span)),
         }
     }
+
+    /// Whether this is a word that looks like an identifier but cannot be bound to a value.
+    pub fn is_reserved(&self) -> bool {
+        use TokenKind::*;
+        matches!(
+            self,
+            True | False
+                | If
+                | Def
+                | Defmacro
+                | Lambda
+                | Let
+                | LetStar
+                | Letrec
+                | Cond
+                | Else
+                | Import
+        )
+    }
 }
 
-impl std::fmt::Display for Token {
+/// An identifier starts with a letter or `_`, followed by letters, digits and `-_?!*/`.
+/// Letters are not limited to ASCII. A `/` separates a module name from the name it defines,
+/// as in `adders/full-add`.
+fn is_identifier(str: &str) -> bool {
+    let mut chars = str.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || "-_?!*/".contains(c))
+}
+
+impl std::fmt::Display for TokenKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Token::Lparen => write!(f, "("),
-            Token::Rparen => write!(f, ")"),
-            Token::And => write!(f, "&"),
-            Token::Or => write!(f, "|"),
-            Token::Not => write!(f, "^"),
-            Token::True => write!(f, "T"),
-            Token::False => write!(f, "F"),
-            Token::If => write!(f, "if"),
-            Token::Def => write!(f, "def"),
-            Token::Lambda => write!(f, "lambda"),
-            Token::Ident(ident) => write!(f, "`{ident}`"),
+            TokenKind::Lparen => write!(f, "("),
+            TokenKind::Rparen => write!(f, ")"),
+            TokenKind::Dot => write!(f, "."),
+            TokenKind::And => write!(f, "&"),
+            TokenKind::Or => write!(f, "|"),
+            TokenKind::Not => write!(f, "^"),
+            TokenKind::True => write!(f, "T"),
+            TokenKind::False => write!(f, "F"),
+            TokenKind::If => write!(f, "if"),
+            TokenKind::Def => write!(f, "def"),
+            TokenKind::Defmacro => write!(f, "defmacro"),
+            TokenKind::Lambda => write!(f, "lambda"),
+            TokenKind::Let => write!(f, "let"),
+            TokenKind::LetStar => write!(f, "let*"),
+            TokenKind::Letrec => write!(f, "letrec"),
+            TokenKind::Cond => write!(f, "cond"),
+            TokenKind::Else => write!(f, "else"),
+            TokenKind::Import => write!(f, "import"),
+            TokenKind::Str(str) => write!(f, "\"{str}\""),
+            TokenKind::Bits(bits) => write!(f, "{bits}"),
+            TokenKind::Int(int) => write!(f, "{int}"),
+            TokenKind::Ident(ident) => write!(f, "{ident}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// Walks over the source while keeping track of the current line and column.
+struct Scanner<'a> {
+    source: &'a str,
+    id: SourceId,
+    pos: usize,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            id: SourceId::of(source),
+            pos: 0,
+            line: 1,
+            col: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn starts_with(&self, prefix: &str) -> bool {
+        self.source[self.pos..].starts_with(prefix)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    /// Returns an empty span at the current position.
+    fn here(&self) -> Span {
+        Span::new(self.pos, self.pos, self.line, self.col).in_source(self.id)
+    }
+
+    /// Returns the span from `start` to the current position.
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.pos,
+            ..start
+        }
+    }
+
+    /// Skips a `#| ... |#` comment, which may contain nested block comments.
+    fn skip_block_comment(&mut self) -> Result<(), TokenizeErr> {
+        let mut opened = Vec::new();
+        loop {
+            if self.starts_with("#|") {
+                opened.push(self.here());
+                self.bump();
+                self.bump();
+            } else if self.starts_with("|#") {
+                self.bump();
+                self.bump();
+                opened.pop();
+                if opened.is_empty() {
+                    return Ok(());
+                }
+            } else if self.bump().is_none() {
+                let start = opened.pop().unwrap_or_default();
+                return Err(TokenizeErr::UnterminatedComment(Span {
+                    end: start.start + 2,
+                    ..start
+                }));
+            }
         }
     }
 }
 
+impl<'a> Scanner<'a> {
+    /// Skips a `"..."` string literal and returns its contents. Strings have no escapes and
+    /// cannot span lines.
+    fn string(&mut self) -> Result<&'a str, TokenizeErr> {
+        let start = self.here();
+        self.bump();
+        while self.peek().is_some_and(|c| c != '"' && c != '\n') {
+            self.bump();
+        }
+        if self.peek() != Some('"') {
+            return Err(TokenizeErr::UnterminatedString(Span {
+                end: start.start + 1,
+                ..start
+            }));
+        }
+        self.bump();
+        Ok(&self.source[start.start + 1..self.pos - 1])
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == ';'
+}
+
+/// A comment kept by [`tokenize_with_comments`]. For `#;`, the text includes the commented-out
+/// expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
 pub fn tokenize(expr: &str) -> Result<Vec<Token>, TokenizeErr> {
-    expr.replace('(', "( ")
-        .replace(')', " )")
-        .split_whitespace()
-        .map(Token::parse)
-        .collect()
+    let (tokens, _) = tokenize_with_comments(expr)?;
+    Ok(tokens)
+}
+
+/// Tokenizes `expr` like [`tokenize`], and also returns its comments in source order.
+pub fn tokenize_with_comments(expr: &str) -> Result<(Vec<Token>, Vec<Comment>), TokenizeErr> {
+    let mut scanner = Scanner::new(expr);
+    let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    let comment = |span: Span| Comment {
+        text: expr[span.start..span.end].to_string(),
+        span,
+    };
+    let mut depth = 0;
+    // `#;` comments waiting for the next expression at their depth: (depth, span, first token).
+    let mut datum_comments: Vec<(usize, Span, usize)> = Vec::new();
+    while let Some(c) = scanner.peek() {
+        let start = scanner.here();
+        if c.is_whitespace() {
+            scanner.bump();
+            continue;
+        }
+        if c == ';' {
+            while scanner.peek().is_some_and(|c| c != '\n') {
+                scanner.bump();
+            }
+            comments.push(comment(scanner.span_from(start)));
+            continue;
+        }
+        if scanner.starts_with("#|") {
+            scanner.skip_block_comment()?;
+            comments.push(comment(scanner.span_from(start)));
+            continue;
+        }
+        if scanner.starts_with("#;") {
+            scanner.bump();
+            scanner.bump();
+            datum_comments.push((depth, scanner.span_from(start), tokens.len()));
+            continue;
+        }
+        let kind = if c == '"' {
+            TokenKind::Str(scanner.string()?.to_string())
+        } else {
+            scanner.bump();
+            if c != '(' && c != ')' {
+                while scanner.peek().is_some_and(|c| !is_delimiter(c)) {
+                    scanner.bump();
+                }
+            }
+            let span = scanner.span_from(start);
+            TokenKind::parse(&expr[span.start..span.end], span)?
+        };
+        let span = scanner.span_from(start);
+        match kind {
+            TokenKind::Lparen => {
+                depth += 1;
+                tokens.push(Token::new(kind, span));
+                continue;
+            }
+            TokenKind::Rparen => {
+                if let Some(&(d, span, _)) = datum_comments.last() {
+                    if d == depth {
+                        return Err(TokenizeErr::DanglingDatumComment(span));
+                    }
+                }
+                depth = depth.saturating_sub(1);
+            }
+            _ => (),
+        }
+        tokens.push(Token::new(kind, span));
+        // An expression at `depth` is now complete; drop it if it was commented out.
+        if let Some(&(d, start, first)) = datum_comments.last() {
+            if d == depth {
+                tokens.truncate(first);
+                datum_comments.pop();
+                // Comments inside the commented-out expression become part of this one.
+                comments.retain(|c: &Comment| c.span.start < start.start);
+                comments.push(comment(start.to(span)));
+            }
+        }
+    }
+    if let Some((_, span, _)) = datum_comments.pop() {
+        return Err(TokenizeErr::DanglingDatumComment(span));
+    }
+    Ok((tokens, comments))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Token::*;
+    use super::TokenKind::*;
     use super::*;
 
+    fn kinds(tokens: Vec<Token>) -> Vec<TokenKind> {
+        tokens.into_iter().map(|token| token.kind).collect()
+    }
+
     #[test]
     fn tokenize_valid_tokens_parsed_successfully() {
-        let tokens = tokenize("( ) & | ^ T F if def lambda");
+        let tokens = tokenize(
+            "( ) . & | ^ T F if def defmacro lambda let let* letrec cond else \
+             import",
+        );
         assert_eq!(
-            vec![Lparen, Rparen, And, Or, Not, True, False, If, Def, Lambda],
-            tokens.unwrap()
+            vec![
+                Lparen, Rparen, Dot, And, Or, Not, True, False, If, Def, Defmacro, Lambda, Let,
+                LetStar, Letrec, Cond, Else, Import
+            ],
+            kinds(tokens.unwrap())
         );
     }
 
@@ -91,16 +412,204 @@ mod tests {
         let tokens = tokenize("myvar abc");
         assert_eq!(
             vec![Ident("myvar".to_string()), Ident("abc".to_string())],
-            tokens.unwrap()
+            kinds(tokens.unwrap())
         );
+        // Named operators are bound like builtins rather than reserved.
+        let tokens = tokenize("xor nand");
+        assert_eq!(
+            vec![Ident("xor".to_string()), Ident("nand".to_string())],
+            kinds(tokens.unwrap())
+        );
+    }
+
+    #[test]
+    fn tokenize_rich_identifier_succeed() {
+        let idents = [
+            "half-adder",
+            "carry_out",
+            "x1",
+            "even?",
+            "mux2",
+            "set!",
+            "a*",
+            "_",
+            "Cin",
+            "入力",
+            "adders/full-add",
+        ];
+        let tokens = tokenize(&idents.join(" ")).unwrap();
+        assert_eq!(
+            idents.map(|ident| Ident(ident.to_string())).to_vec(),
+            kinds(tokens)
+        );
+    }
+
+    #[test]
+    fn tokenize_invalid_identifier_cannot_be_parsed() {
+        for ident in ["1x", "-a", "?", "a.b", "/a", "a$"] {
+            assert_eq!(
+                Err(TokenizeErr::InvalidToken(
+                    ident.to_string(),
+                    Span::new(0, ident.len(), 1, 1)
+                )),
+                tokenize(ident)
+            );
+        }
     }
 
     #[test]
     fn tokenize_invalid_token_cannot_be_parsed() {
         let tokens = tokenize("( ) & | ^ T F $");
-        match tokens {
-            Err(TokenizeErr::Parse(e)) => assert_eq!("Invalid token `$`", e),
-            _ => panic!(),
-        };
+        assert_eq!(
+            Err(TokenizeErr::InvalidToken(
+                "$".to_string(),
+                Span::new(14, 15, 1, 15)
+            )),
+            tokens
+        );
+        assert_eq!("invalid token `$`", tokens.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn tokenize_literals_succeed() {
+        let tokens = tokenize("#b1011 #b0 0 42").unwrap();
+        assert_eq!(
+            vec![
+                Bits(crate::bits::Bits::from_digits("1011").unwrap()),
+                Bits(crate::bits::Bits::from_digits("0").unwrap()),
+                Int(0),
+                Int(42)
+            ],
+            kinds(tokens)
+        );
+        for literal in ["#b", "#b102", "#x1f", "99999999999999999999999"] {
+            assert_eq!(
+                Err(TokenizeErr::InvalidToken(
+                    literal.to_string(),
+                    Span::new(0, literal.len(), 1, 1)
+                )),
+                tokenize(literal)
+            );
+        }
+    }
+
+    #[test]
+    fn tokenize_strings_succeed() {
+        let tokens = tokenize("(import \"lib/adders.lip\" \"\")").unwrap();
+        assert_eq!(
+            vec![
+                Lparen,
+                Import,
+                Str("lib/adders.lip".to_string()),
+                Str(String::new()),
+                Rparen
+            ],
+            kinds(tokens.clone())
+        );
+        assert_eq!(Span::new(8, 24, 1, 9), tokens[2].span);
+        for source in ["\"adders.lip", "(import \"a\nb\")"] {
+            let start = source.find('"').unwrap();
+            assert_eq!(
+                Err(TokenizeErr::UnterminatedString(Span::new(
+                    start,
+                    start + 1,
+                    1,
+                    start + 1
+                ))),
+                tokenize(source)
+            );
+        }
+        let tokens = tokenize("#;\"skipped\" x").unwrap();
+        assert_eq!(vec![Ident("x".to_string())], kinds(tokens));
+    }
+
+    #[test]
+    fn tokenize_records_spans() {
+        let tokens = tokenize("(& T\n  abc)").unwrap();
+        let spans: Vec<Span> = tokens.into_iter().map(|token| token.span).collect();
+        assert_eq!(
+            vec![
+                Span::new(0, 1, 1, 1),
+                Span::new(1, 2, 1, 2),
+                Span::new(3, 4, 1, 4),
+                Span::new(7, 10, 2, 3),
+                Span::new(10, 11, 2, 6),
+            ],
+            spans
+        );
+    }
+
+    #[test]
+    fn tokenize_skips_line_comments() {
+        let tokens = tokenize("; full adder\n(& a ; carry\n b);").unwrap();
+        assert_eq!(
+            vec![
+                Lparen,
+                And,
+                Ident("a".to_string()),
+                Ident("b".to_string()),
+                Rparen
+            ],
+            kinds(tokens)
+        );
+    }
+
+    #[test]
+    fn tokenize_skips_nested_block_comments() {
+        let tokens = tokenize("T #| outer #| inner |# still outer |# F").unwrap();
+        assert_eq!(vec![True, False], kinds(tokens));
+        assert_eq!(
+            Err(TokenizeErr::UnterminatedComment(Span::new(2, 4, 1, 3))),
+            tokenize("T #| #| |# F")
+        );
+    }
+
+    #[test]
+    fn tokenize_skips_datum_comments() {
+        let tokens = tokenize("(& T #;(| F (^ T)) F)").unwrap();
+        assert_eq!(vec![Lparen, And, True, False, Rparen], kinds(tokens));
+        let tokens = tokenize("#; #; T F (^ T)").unwrap();
+        assert_eq!(vec![Lparen, Not, True, Rparen], kinds(tokens));
+        assert_eq!(
+            Err(TokenizeErr::DanglingDatumComment(Span::new(5, 7, 1, 6))),
+            tokenize("(& T #;)")
+        );
+        assert_eq!(
+            Err(TokenizeErr::DanglingDatumComment(Span::new(2, 4, 1, 3))),
+            tokenize("T #;")
+        );
+    }
+
+    #[test]
+    fn tokenize_with_comments_keeps_comments() {
+        let (tokens, comments) =
+            tokenize_with_comments("; adder\n(& a #| x |# #;(| b ; y\n c) d)").unwrap();
+        assert_eq!(
+            vec![
+                Lparen,
+                And,
+                Ident("a".to_string()),
+                Ident("d".to_string()),
+                Rparen
+            ],
+            kinds(tokens)
+        );
+        assert_eq!(
+            vec![
+                Comment {
+                    text: "; adder".to_string(),
+                    span: Span::new(0, 7, 1, 1)
+                },
+                Comment {
+                    text: "#| x |#".to_string(),
+                    span: Span::new(13, 20, 2, 6)
+                },
+                Comment {
+                    text: "#;(| b ; y\n c)".to_string(),
+                    span: Span::new(21, 35, 2, 14)
+                },
+            ],
+            comments
+        );
     }
 }
@@ -0,0 +1,315 @@
+//! Pins down the semantics of the language. Every case runs through both the CLI REPL and the
+//! [`Repl`] exported to WASM, which must agree with the expected output.
+
+use std::io::Cursor;
+
+use lip::repl::{self, Repl};
+
+/// Single-line programs and the output of their last expression, or the error report.
+const CASES: &[(&str, &str)] = &[
+    // Literals and primitives.
+    ("T", "true"),
+    ("F", "false"),
+    ("&", "primitive operator: &"),
+    ("(^ T)", "false"),
+    ("(& T T F)", "false"),
+    ("(| F F T)", "true"),
+    ("(&)", "true"),
+    ("(|)", "false"),
+    ("(xor T T T)", "true"),
+    ("(implies T F)", "false"),
+    ("(iff F F)", "true"),
+    ("(nand T T)", "false"),
+    ("(nor F F)", "true"),
+    ("((if F & xor) T F)", "true"),
+    ("nand", "primitive operator: nand"),
+    // Named operators are bindings, so definitions can shadow them.
+    ("(def nand (lambda (a b) (& a b))) (nand T T)", "true"),
+    ("(let ((xor T)) xor)", "true"),
+    // `&` and `|` short-circuit from left to right.
+    ("(| T (undefined-thing))", "true"),
+    ("(& F (undefined-thing))", "false"),
+    ("(| F T &)", "true"),
+    ("(& (def x T) (def x F) (def x T)) x", "false"),
+    ("(| (def x F) (def x T) (def x F)) x", "true"),
+    (
+        "(& T (undefined-thing))",
+        "failed to evaluate: `undefined-thing` is not defined\n1 | (& T (undefined-thing))\n  |       ^^^^^^^^^^^^^^^",
+    ),
+    (
+        "(| F &)",
+        "failed to evaluate: expected bool, found `primitive operator: &`\n1 | (| F &)\n  |      ^",
+    ),
+    (
+        "(^ T F)",
+        "failed to evaluate: expected 1 argument(s), found 2\n1 | (^ T F)\n  | ^^^^^^^",
+    ),
+    (
+        "(^ &)",
+        "failed to evaluate: expected bool, found `primitive operator: &`\n1 | (^ &)\n  |    ^",
+    ),
+    // Branching.
+    ("(if (& T T) T (| F F))", "true"),
+    ("(if F (undefined-thing) F)", "false"),
+    ("((if T & |) T F)", "false"),
+    (
+        "(if | T F)",
+        "failed to evaluate: expected bool, found `primitive operator: |`\n1 | (if | T F)\n  |     ^",
+    ),
+    // Definitions and lambdas.
+    ("(def x (& T T F))", "false"),
+    ("(def x F) (^ x)", "true"),
+    ("(lambda (a b) (^ (& a b)))", "(lambda (a b) (^ (& a b)))"),
+    ("(def gate (lambda (a b) (^ (& a b)))) (gate T T)", "false"),
+    ("((lambda () T))", "true"),
+    (
+        "((lambda (a) a) T F)",
+        "failed to evaluate: expected 1 argument(s), found 2\n1 | ((lambda (a) a) T F)\n  | ^^^^^^^^^^^^^^^^^^^^",
+    ),
+    (
+        "(T F)",
+        "failed to evaluate: `true` is not callable\n1 | (T F)\n  |  ^",
+    ),
+    // Rest parameters, lists and partial application.
+    ("((lambda (a . rest) rest) T F T)", "(list false true)"),
+    ("((lambda xs (apply xor xs)) T T T)", "true"),
+    ("(apply & T (list T F))", "false"),
+    (
+        "(def and3 (lambda (a b c) (& a b c))) (partial and3 T)",
+        "(lambda (b c) (& a b c))",
+    ),
+    (
+        "(def and3 (lambda (a b c) (& a b c))) ((partial and3 T T) T)",
+        "true",
+    ),
+    (
+        "(def and3 (lambda (a b c) (& a b c))) (and3 T)",
+        "failed to evaluate: expected 3 argument(s), found 1\n1 | (def and3 (lambda (a b c) (& a b c))) (and3 T)\n  |                                       ^^^^^^^^",
+    ),
+    ("list", "builtin function: list"),
+    (
+        "(apply & T)",
+        "failed to evaluate: expected list, found `true`\n1 | (apply & T)\n  | ^^^^^^^^^^^",
+    ),
+    // Lists and destructuring.
+    ("(list T (list F T))", "(list true (list false true))"),
+    ("(first (list F T))", "false"),
+    ("(nth (list F T) 1)", "true"),
+    ("(length (list F T F))", "3"),
+    ("(let (((s c) (list T F))) c)", "false"),
+    ("((lambda ((a . b) c) (list b c)) (list T F) T)", "(list (list false) true)"),
+    (
+        "(nth (list T) 1)",
+        "failed to evaluate: index 1 is out of range for length 1\n1 | (nth (list T) 1)\n  | ^^^^^^^^^^^^^^^^",
+    ),
+    (
+        "((lambda ((s c)) s) (list T F T))",
+        "failed to evaluate: `(list true false true)` does not match the pattern `(s c)`\n1 | ((lambda ((s c)) s) (list T F T))\n  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^",
+    ),
+    // Lambdas capture the environment they are defined in.
+    ("(def mk (lambda (a) (lambda (b) (& a b)))) ((mk T) F)", "false"),
+    (
+        "(def f (lambda () a)) (def g (lambda (a) (f))) (g T)",
+        "failed to evaluate: `a` is not defined\n1 | (def f (lambda () a)) (def g (lambda (a) (f))) (g T)\n  |                   ^",
+    ),
+    // Multi-branch conditionals.
+    ("(cond (F F) ((& T T) T) (else F))", "true"),
+    ("(cond (F F) (else (^ F)))", "true"),
+    ("(cond (T T) ((undefined-thing) F))", "true"),
+    (
+        "(cond (F T) ((^ T) T))",
+        "failed to evaluate: no `cond` clause matched and there is no `else`\n1 | (cond (F T) ((^ T) T))\n  | ^^^^^^^^^^^^^^^^^^^^^^",
+    ),
+    // Local bindings.
+    ("(def a F) (let ((a T) (b a)) b)", "false"),
+    ("(let* ((a T) (b a)) b)", "true"),
+    (
+        "(let ((x T)) x) x",
+        "failed to evaluate: `x` is not defined\n1 | (let ((x T)) x) x\n  |                 ^",
+    ),
+    (
+        "(letrec ((f (lambda (a) (if a (g F) T))) (g (lambda (a) (f a)))) (f T))",
+        "true",
+    ),
+    // Bit vectors.
+    ("#b0101", "#b0101"),
+    ("(& #b1100 #b1010)", "#b1000"),
+    ("(^ #b1100)", "#b0011"),
+    ("(concat #b10 T #b0)", "#b1010"),
+    ("(slice #b110100 4 2)", "#b101"),
+    ("(bit #b0010 1)", "true"),
+    ("(shl #b0011 1)", "#b0110"),
+    ("(shr #b1100 3)", "#b0001"),
+    ("(and-reduce #b111)", "true"),
+    ("(or-reduce #b000)", "false"),
+    ("(xor-reduce #b0111)", "true"),
+    ("(width #b0111)", "4"),
+    (
+        "(| #b1100 #b101)",
+        "failed to evaluate: expected 4 bit(s), found 3\n1 | (| #b1100 #b101)\n  |           ^^^^^",
+    ),
+    (
+        "(bit #b0010 4)",
+        "failed to evaluate: index 4 is out of range for length 4\n1 | (bit #b0010 4)\n  | ^^^^^^^^^^^^^^",
+    ),
+    (
+        "(slice #b0010 1 2)",
+        "failed to evaluate: cannot slice from bit 1 down to bit 2\n1 | (slice #b0010 1 2)\n  | ^^^^^^^^^^^^^^^^^^",
+    ),
+    (
+        "(& #b01 T)",
+        "failed to evaluate: expected bits, found `true`\n1 | (& #b01 T)\n  |         ^",
+    ),
+    // Macros.
+    (
+        "(defmacro defgate (name params body) (def name (lambda params body))) (defgate nand2 (a b) (^ (& a b))) (nand2 T T)",
+        "false",
+    ),
+    ("(defmacro my-or (a b) (let ((t a)) (if t t b))) (let ((t T)) (my-or F t))", "true"),
+    (
+        "(def helper (lambda (a) (^ a))) (defmacro m (x) (helper x)) (let ((helper (lambda (a) a))) (m T))",
+        "false",
+    ),
+    ("(defmacro all (. xs) (& T xs)) (all T T F)", "false"),
+    ("(defmacro m (a) a) m", "(macro (a) a)"),
+    (
+        "(defmacro m (a) a) (m)",
+        "failed to evaluate: expected 1 argument(s), found 0\n1 | (defmacro m (a) a) (m)\n  |                    ^^^",
+    ),
+    (
+        "(defmacro m ((a b)) a) (m T)",
+        "failed to evaluate: `T` does not match the pattern `(a b)`\n1 | (defmacro m ((a b)) a) (m T)\n  |                           ^",
+    ),
+    // The prelude.
+    ("(mux4 T F F F T F)", "true"),
+    ("(decoder2 T F)", "(list false false true false)"),
+    ("(lt? #b011 #b100)", "true"),
+    ("(add #b0111 #b0011)", "(list #b1010 false)"),
+    ("(def mux2 &) (mux2 T F)", "false"),
+    // Truth tables.
+    (
+        "(truth-table (lambda (a b) (& a b)))",
+        "(list (list (list false false) false) (list (list false true) false) (list (list true false) false) (list (list true true) true))",
+    ),
+    ("(truth-table (lambda () T))", "(list (list (list) true))"),
+    (
+        "(truth-table &)",
+        "failed to evaluate: expected lambda, found `primitive operator: &`\n1 | (truth-table &)\n  | ^^^^^^^^^^^^^^^",
+    ),
+    // Equivalence.
+    (
+        "(equiv? (lambda (a b) (^ (& a b))) (lambda (a b) (| (^ a) (^ b))))",
+        "true",
+    ),
+    (
+        "(equiv? (lambda (a b) (| a b)) (lambda (a b) (xor a b)))",
+        "false",
+    ),
+    (
+        "(counterexample (lambda (a b) (| a b)) (lambda (a b) (xor a b)))",
+        "(list true true)",
+    ),
+    (
+        "(counterexample (lambda (a b) (^ (& a b))) (lambda (a b) (| (^ a) (^ b))))",
+        "false",
+    ),
+    (
+        "(equiv? (lambda (a) a) (lambda (a b) a))",
+        "failed to evaluate: cannot compare functions of 1 and 2 inputs\n1 | (equiv? (lambda (a) a) (lambda (a b) a))\n  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^",
+    ),
+    // Satisfiability.
+    ("(sat? (lambda (a b) (& a (^ b))))", "true"),
+    ("(solve (lambda (a b) (& a (^ b))))", "(list true false)"),
+    ("(solve (& a (^ a)))", "false"),
+    (
+        "(all-solutions (xor a b))",
+        "(list (list false true) (list true false))",
+    ),
+    ("(def f (lambda (a) (^ a))) (all-solutions f)", "(list (list false))"),
+    ("(apply solve (list (lambda (a) a)))", "(list true)"),
+    // Three pigeons in two holes, each in a hole and no two in the same one.
+    (
+        "(sat? (& (| p1a p1b) (| p2a p2b) (| p3a p3b) (nand p1a p2a) (nand p1a p3a) (nand p2a p3a) (nand p1b p2b) (nand p1b p3b) (nand p2b p3b)))",
+        "false",
+    ),
+    (
+        "(sat? (lambda (a) (list a)))",
+        "failed to evaluate: expected bool, found `(list false)`\n1 | (sat? (lambda (a) (list a)))\n  |       ^^^^^^^^^^^^^^^^^^^^^",
+    ),
+    // Modules, resolved from the package root where the tests run.
+    (
+        "(import \"tests/it/modules/adders.lip\") (adders/full-add T T T)",
+        "(list true true)",
+    ),
+    (
+        "(import \"tests/it/modules/adders.lip\" (half-add)) (half-add T F)",
+        "(list true false)",
+    ),
+    (
+        "(import \"tests/it/modules/adders.lip\" (both))",
+        "failed to evaluate: `tests/it/modules/adders.lip` does not define `both`\n1 | (import \"tests/it/modules/adders.lip\" (both))\n  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^",
+    ),
+    (
+        "(import \"tests/it/modules/pigeons.cnf\") (list (sat? pigeons) (pigeons T F F T T F))",
+        "(list false false)",
+    ),
+    (
+        "(import \"tests/it/modules/pigeons.cnf\") (solve (lambda (a b c d e) (pigeons a b c d e F)))",
+        "false",
+    ),
+    (
+        "(import adders)",
+        "failed to parse: malformed expression, expected `(import \"path\" [(names...)])`\n1 | (import adders)\n  |         ^^^^^^",
+    ),
+    // Recursion.
+    (
+        "(def loop (lambda (a n) (if n a (loop (^ a) T)))) (loop F F)",
+        "true",
+    ),
+    // Parse errors.
+    (
+        "(def cond T)",
+        "failed to parse: `cond` is a reserved word and cannot be used as a name\n1 | (def cond T)\n  |      ^^^^",
+    ),
+    (
+        "(& T",
+        "failed to parse: call is not closed with `)`\n1 | (& T\n  | ^",
+    ),
+    ("$", "failed to tokenize: invalid token `$`\n1 | $\n  | ^"),
+    (
+        "#b012",
+        "failed to tokenize: invalid token `#b012`\n1 | #b012\n  | ^^^^^",
+    ),
+];
+
+fn eval_cli(source: &str) -> String {
+    let mut input = Cursor::new(format!("{source}\n:exit").into_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output).unwrap();
+    let out = String::from_utf8(output.into_inner()).unwrap();
+    out.trim_start_matches("lip> ")
+        .trim_end_matches("lip> ")
+        .trim_end()
+        .to_string()
+}
+
+fn eval_wasm(source: &str) -> String {
+    match Repl::new().eval(source) {
+        Ok(value) => value,
+        Err(report) => report,
+    }
+}
+
+#[test]
+fn conformance_cli() {
+    for (source, expected) in CASES {
+        assert_eq!(*expected, eval_cli(source), "{source}");
+    }
+}
+
+#[test]
+fn conformance_wasm() {
+    for (source, expected) in CASES {
+        assert_eq!(*expected, eval_wasm(source), "{source}");
+    }
+}
@@ -1 +1,4 @@
+mod conformance;
+mod parser;
+mod printer;
 mod repl;
@@ -0,0 +1,160 @@
+use lip::{
+    bits::Bits,
+    parser::{parse, parse_program, parse_program_recovering},
+    span::Span,
+    tokenizer::{tokenize, Token, TokenKind},
+};
+use proptest::prelude::*;
+
+fn token_kind() -> impl Strategy<Value = TokenKind> {
+    prop_oneof![
+        4 => Just(TokenKind::Lparen),
+        4 => Just(TokenKind::Rparen),
+        1 => Just(TokenKind::Dot),
+        1 => Just(TokenKind::And),
+        1 => Just(TokenKind::Or),
+        1 => Just(TokenKind::Not),
+        1 => Just(TokenKind::True),
+        1 => Just(TokenKind::False),
+        1 => Just(TokenKind::If),
+        1 => Just(TokenKind::Def),
+        1 => Just(TokenKind::Defmacro),
+        1 => Just(TokenKind::Lambda),
+        1 => Just(TokenKind::Let),
+        1 => Just(TokenKind::LetStar),
+        1 => Just(TokenKind::Letrec),
+        1 => Just(TokenKind::Cond),
+        1 => Just(TokenKind::Else),
+        1 => Just(TokenKind::Import),
+        1 => "[a-c]\\.lip".prop_map(TokenKind::Str),
+        1 => "[01]{1,4}".prop_map(|digits| TokenKind::Bits(Bits::from_digits(&digits).unwrap())),
+        1 => (0..8usize).prop_map(TokenKind::Int),
+        1 => "[a-c]".prop_map(TokenKind::Ident),
+    ]
+}
+
+/// Source code of a well-formed expression.
+pub fn expr_source() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        Just("T".to_string()),
+        Just("F".to_string()),
+        Just("&".to_string()),
+        Just("|".to_string()),
+        Just("^".to_string()),
+        Just("xor".to_string()),
+        Just("implies".to_string()),
+        "#b[01]{1,4}",
+        "[0-7]",
+        "[a-c]",
+    ];
+    let pattern = "[a-c]".prop_recursive(2, 6, 3, |inner| {
+        (
+            prop::collection::vec(inner, 0..3),
+            prop::option::of("[a-c]"),
+        )
+            .prop_map(|(patterns, rest)| match rest {
+                Some(rest) => format!("({} . {rest})", patterns.join(" ")),
+                None => format!("({})", patterns.join(" ")),
+            })
+    });
+    leaf.prop_recursive(4, 32, 4, move |inner| {
+        prop_oneof![
+            (inner.clone(), prop::collection::vec(inner.clone(), 0..4))
+                .prop_map(|(operator, operands)| format!("({operator} {})", operands.join(" "))),
+            (inner.clone(), inner.clone(), inner.clone())
+                .prop_map(|(cond, then, other)| format!("(if {cond} {then} {other})")),
+            ("[a-c]", inner.clone()).prop_map(|(ident, expr)| format!("(def {ident} {expr})")),
+            ("[a-c]", pattern.clone(), inner.clone()).prop_map(|(ident, params, template)| {
+                format!("(defmacro {ident} {params} {template})")
+            }),
+            (
+                "[a-c]",
+                prop::option::of(prop::collection::vec("[a-c]", 0..3))
+            )
+                .prop_map(|(path, names)| match names {
+                    Some(names) => format!("(import \"{path}.lip\" ({}))", names.join(" ")),
+                    None => format!("(import \"{path}.lip\")"),
+                }),
+            (prop::collection::vec(pattern.clone(), 0..3), inner.clone())
+                .prop_map(|(args, body)| format!("(lambda ({}) {body})", args.join(" "))),
+            (prop::collection::vec("[a-c]", 0..3), "[a-c]", inner.clone()).prop_map(
+                |(args, rest, body)| format!("(lambda ({} . {rest}) {body})", args.join(" "))
+            ),
+            (
+                prop_oneof![Just("let"), Just("let*"), Just("letrec")],
+                prop::collection::vec((pattern.clone(), inner.clone()), 0..3),
+                inner.clone()
+            )
+                .prop_map(|(kind, bindings, body)| {
+                    let bindings: Vec<String> = bindings
+                        .into_iter()
+                        .map(|(ident, value)| format!("({ident} {value})"))
+                        .collect();
+                    format!("({kind} ({}) {body})", bindings.join(" "))
+                }),
+            (
+                prop::collection::vec((inner.clone(), inner.clone()), 0..3),
+                prop::option::of(inner)
+            )
+                .prop_map(|(clauses, other)| {
+                    let mut clauses: Vec<String> = clauses
+                        .into_iter()
+                        .map(|(test, expr)| format!("({test} {expr})"))
+                        .collect();
+                    clauses.extend(other.map(|other| format!("(else {other})")));
+                    format!("(cond {})", clauses.join(" "))
+                }),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn parse_never_panics(kinds in prop::collection::vec(token_kind(), 0..48)) {
+        let tokens: Vec<Token> = kinds
+            .into_iter()
+            .map(|kind| Token::new(kind, Span::default()))
+            .collect();
+        let _ = parse(&tokens);
+        let (_, errors) = parse_program_recovering(&tokens);
+        prop_assert_eq!(parse_program(&tokens).is_err(), !errors.is_empty());
+    }
+
+    #[test]
+    fn parse_truncated_expression_fails(source in expr_source()) {
+        let tokens = tokenize(&source).unwrap();
+        let expr = parse(&tokens).unwrap();
+        prop_assert_eq!((vec![expr], vec![]), parse_program_recovering(&tokens));
+        for len in 0..tokens.len() {
+            prop_assert!(parse(&tokens[..len]).is_err());
+        }
+    }
+}
+
+#[test]
+fn parse_truncated_special_forms_fail() {
+    for source in [
+        "(",
+        "(if T T T",
+        "(if T T)",
+        "(def x T",
+        "(def",
+        "(def)",
+        "(lambda (a) a",
+        "(lambda (a",
+        "(lambda",
+        "(lambda a",
+        "(lambda (a .) a)",
+        "(lambda (a . b",
+        "(defmacro m (a)",
+        "(defmacro m",
+        "(import",
+        "(import \"a.lip\" (b)",
+        "(import \"a.lip\" (b",
+        "()",
+        ")",
+    ] {
+        let tokens = tokenize(source).unwrap();
+        assert!(parse(&tokens).is_err(), "`{source}` should not parse");
+    }
+}
@@ -0,0 +1,24 @@
+use lip::{
+    parser::parse,
+    printer::{format, pretty},
+    tokenizer::tokenize,
+};
+use proptest::prelude::*;
+
+use crate::parser::expr_source;
+
+proptest! {
+    #[test]
+    fn pretty_output_parses_to_same_expr(source in expr_source(), width in 0usize..60) {
+        let expr = parse(&tokenize(&source).unwrap()).unwrap();
+        let printed = pretty(&expr, width);
+        prop_assert_eq!(&expr, &parse(&tokenize(&printed).unwrap()).unwrap());
+        prop_assert_eq!(expr.to_string(), pretty(&expr, usize::MAX));
+    }
+
+    #[test]
+    fn format_is_idempotent(source in expr_source(), width in 0usize..60) {
+        let formatted = format(&source, width).unwrap();
+        prop_assert_eq!(&formatted, &format(&formatted, width).unwrap());
+    }
+}
@@ -1,6 +1,9 @@
 use std::io::{self, Cursor};
 
-use lip::repl;
+use lip::{
+    prelude,
+    repl::{self, Repl},
+};
 
 fn get_outputs(output: Cursor<Vec<u8>>) -> Vec<String> {
     let out = String::from_utf8(output.into_inner()).unwrap();
@@ -20,6 +23,26 @@ fn repl_eval_primitive_succeed() -> io::Result<()> {
     Ok(())
 }
 
+#[test]
+fn repl_error_in_earlier_line_is_not_highlighted() -> io::Result<()> {
+    // The last line is long enough to have a line 1, column 25, where the error is in `f`.
+    let source = "(def f (lambda (a) (& a undefined-thing)))\n(f T)\n(& T (f T))\n\
+                  (let ((some-long-name T)) (f some-long-name))\n:exit";
+    let mut input = Cursor::new(source.as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(
+        vec![
+            "(lambda (a) (& a undefined-thing))",
+            "failed to evaluate: `undefined-thing` is not defined",
+            "failed to evaluate: `undefined-thing` is not defined",
+            "failed to evaluate: `undefined-thing` is not defined",
+        ],
+        get_outputs(output)
+    );
+    Ok(())
+}
+
 #[test]
 fn repl_env_command_succeed() -> io::Result<()> {
     let mut input = Cursor::new("(def x (& T T T))\n:env\n:exit".as_bytes());
@@ -31,3 +54,173 @@ fn repl_env_command_succeed() -> io::Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn repl_expand_command_succeed() -> io::Result<()> {
+    let source = "(defmacro my-or (a b) (let ((t a)) (if t t b)))
+:expand (my-or F t)
+:expand (my-or F)
+:exit";
+    let mut input = Cursor::new(source.as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(
+        vec![
+            "(macro (a b) (let ((t a)) (if t t b)))",
+            "(let ((t%1 F)) (if t%1 t%1 t))",
+            "failed to evaluate: expected 2 argument(s), found 1\n1 | (my-or F)\n  | ^^^^^^^^^",
+        ],
+        get_outputs(output)
+    );
+    let mut repl = Repl::new();
+    repl.eval("(defmacro twice (f a) (f (f a)))").unwrap();
+    assert_eq!(Ok("(^ (^ T))".to_string()), repl.expand("(twice ^ T)"));
+    Ok(())
+}
+
+#[test]
+fn repl_table_command_succeed() -> io::Result<()> {
+    let source = ":table (| a (& b c))
+:table --csv half-adder
+:table --markdown (lambda (x) (^ x))
+:table (& a 1)
+:exit";
+    let mut input = Cursor::new(source.as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(
+        vec![
+            "a b c | out\n------+----\nF F F | F\nF F T | F\nF T F | F\nF T T | T\nT F F | T\nT F T | T\nT T F | T\nT T T | T",
+            "a,b,out0,out1\nF,F,F,F\nF,T,T,F\nT,F,T,F\nT,T,F,T",
+            "| x | out |\n|---|-----|\n| F | T   |\n| T | F   |",
+            "failed to evaluate: expected bool, found `1`\n1 | (& a 1)\n  |      ^",
+        ],
+        get_outputs(output)
+    );
+    assert_eq!(
+        Ok("a,out\nF,T\nT,F\n".to_string()),
+        Repl::new().table("--csv (^ a)")
+    );
+    Ok(())
+}
+
+#[test]
+fn repl_equiv_command_succeed() -> io::Result<()> {
+    let source = "(def nand (lambda (a b) (^ (& a b))))
+:equiv nand (lambda (a b) (| (^ a) (^ b)))
+:equiv nand (lambda (a b) (nor a b))
+:equiv nand
+:exit";
+    let mut input = Cursor::new(source.as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(
+        vec![
+            "(lambda (a b) (^ (& a b)))",
+            "equivalent",
+            "not equivalent: with a = F, b = T the first gives `true` and the second `false`",
+            "failed to run command: expected `:equiv <f> <g>`\n1 | nand\n  | ^^^^",
+        ],
+        get_outputs(output)
+    );
+    assert_eq!(
+        Ok("equivalent".to_string()),
+        Repl::new().equiv("(lambda (a b) (mux2 a F b)) (lambda (a b) (& a b))")
+    );
+    Ok(())
+}
+
+#[test]
+fn repl_command_name_ends_at_whitespace() -> io::Result<()> {
+    let source = ":equivnand\n:cnf(| a b)\n:exit";
+    let mut input = Cursor::new(source.as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(
+        vec![
+            "failed to tokenize: invalid token `:equivnand`\n1 | :equivnand\n  | ^^^^^^^^^^",
+            "failed to tokenize: invalid token `:cnf`\n1 | :cnf(| a b)\n  | ^^^^",
+        ],
+        get_outputs(output)
+    );
+    Ok(())
+}
+
+#[test]
+fn repl_cnf_command_succeed() -> io::Result<()> {
+    let source = ":cnf (| a b)
+:cnf (lambda (x) (xor x T))
+:cnf (list a)
+:exit";
+    let mut input = Cursor::new(source.as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(
+        vec![
+            "c 1 a\nc 2 b\np cnf 3 4\n-3 -1 0\n-3 -2 0\n3 1 2 0\n-3 0",
+            "c 1 x\np cnf 1 1\n-1 0",
+            "failed to evaluate: expected bool, found `(list false)`\n1 | (list a)\n  | ^^^^^^^^",
+        ],
+        get_outputs(output)
+    );
+    assert_eq!(
+        Ok("p cnf 0 1\n0\n".to_string()),
+        Repl::new().cnf("(def f (lambda () F)) f")
+    );
+    assert_eq!(
+        Err(
+            "failed to run command: nothing to export, expected an expression to convert to CNF\n\
+             1 | ; comment\n  | ^"
+                .to_string()
+        ),
+        Repl::new().cnf("; comment")
+    );
+    Ok(())
+}
+
+#[test]
+fn repl_prelude_is_loaded_unless_left_out() -> io::Result<()> {
+    let mut input = Cursor::new("(mux2 T F T)\n(def mux2 &)\n:env".as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(
+        vec![
+            "true",
+            "primitive operator: &",
+            "Environment { data: {\"mux2\": Operator(And)} }"
+        ],
+        get_outputs(output)
+    );
+    let mut input = Cursor::new("(mux2 T F T)".as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run_in(&mut input, &mut output, prelude::environment(false))?;
+    assert_eq!(
+        vec!["failed to evaluate: `mux2` is not defined\n1 | (mux2 T F T)\n  |  ^^^^"],
+        get_outputs(output)
+    );
+    assert_eq!(Ok("true".to_string()), Repl::new().eval("(mux2 T F T)"));
+    assert!(Repl::without_prelude().eval("(mux2 T F T)").is_err());
+    Ok(())
+}
+
+#[test]
+fn repl_error_points_at_source() -> io::Result<()> {
+    let mut input = Cursor::new("(& T x)\n:exit".as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    let outputs = get_outputs(output);
+    assert_eq!(
+        vec!["failed to evaluate: `x` is not defined\n1 | (& T x)\n  |      ^"],
+        outputs
+    );
+    Ok(())
+}
+
+#[test]
+fn repl_eval_multiple_expressions_succeed() -> io::Result<()> {
+    let mut input = Cursor::new("(def x F) (^ x)\n\n(& x T)".as_bytes());
+    let mut output = Cursor::new(Vec::new());
+    repl::run(&mut input, &mut output)?;
+    assert_eq!(vec!["true", "false"], get_outputs(output));
+    Ok(())
+}